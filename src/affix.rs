@@ -0,0 +1,273 @@
+//! An allocator which surrounds every block with metadata.
+
+use std::cmp;
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr;
+
+use super::{Allocator, Error, Block, BlockOwner};
+
+/// This wraps an allocator, padding every block it hands out with a prefix
+/// of type `P` before it and a suffix of type `S` after it.
+/// The affixes are default-constructed on allocation, dropped on deallocation,
+/// and can be read back with `prefix` and `suffix`.
+///
+/// Use `()` for an affix you don't need; it takes up no space.
+pub struct Affix<A, P, S> {
+    alloc: A,
+    _marker: PhantomData<(P, S)>,
+}
+
+fn round_up(size: usize, align: usize) -> Option<usize> {
+    size.checked_add(align - 1).map(|size| size & !(align - 1))
+}
+
+impl<A: Allocator, P: Default, S: Default> Affix<A, P, S> {
+    /// Create a new `Affix` wrapping the given allocator.
+    pub fn new(alloc: A) -> Self {
+        Affix {
+            alloc: alloc,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Gets a reference to the prefix of a block.
+    ///
+    /// # Safety
+    /// The block must be a non-empty block allocated by this allocator,
+    /// and not yet deallocated.
+    pub unsafe fn prefix(&self, block: &Block) -> &P {
+        &*self.prefix_ptr(block)
+    }
+
+    /// Gets a reference to the suffix of a block.
+    ///
+    /// # Safety
+    /// The block must be a non-empty block allocated by this allocator,
+    /// and not yet deallocated.
+    pub unsafe fn suffix(&self, block: &Block) -> &S {
+        &*self.suffix_ptr(block)
+    }
+
+    /// Gets a mutable reference to the prefix of a block.
+    ///
+    /// # Safety
+    /// The same as for `prefix`.
+    pub unsafe fn prefix_mut(&self, block: &mut Block) -> &mut P {
+        &mut *self.prefix_ptr(block)
+    }
+
+    /// Gets a mutable reference to the suffix of a block.
+    ///
+    /// # Safety
+    /// The same as for `suffix`.
+    pub unsafe fn suffix_mut(&self, block: &mut Block) -> &mut S {
+        &mut *self.suffix_ptr(block)
+    }
+
+    // the offset of the user's block from the start of the underlying one.
+    fn prefix_offset(align: usize) -> usize {
+        // the prefix sits right before the user's block. Its size is a
+        // multiple of its alignment, so it stays aligned.
+        let align = cmp::max(align, mem::align_of::<P>());
+        (mem::size_of::<P>() + align - 1) & !(align - 1)
+    }
+
+    // the offset of the suffix from the start of the underlying block.
+    fn suffix_offset(size: usize, align: usize) -> Option<usize> {
+        // only the underlying block is sure to be aligned for the suffix,
+        // so round up from its start rather than from the user's block.
+        Self::prefix_offset(align)
+            .checked_add(size)
+            .and_then(|end| round_up(end, mem::align_of::<S>()))
+    }
+
+    fn outer_align(align: usize) -> usize {
+        cmp::max(align, cmp::max(mem::align_of::<P>(), mem::align_of::<S>()))
+    }
+
+    fn outer_size(size: usize, align: usize) -> Option<usize> {
+        Self::suffix_offset(size, align).and_then(|offset| offset.checked_add(mem::size_of::<S>()))
+    }
+
+    unsafe fn prefix_ptr(&self, block: &Block) -> *mut P {
+        block.ptr().offset(-(mem::size_of::<P>() as isize)) as *mut P
+    }
+
+    unsafe fn suffix_ptr(&self, block: &Block) -> *mut S {
+        // the suffix offset can't overflow, since it did not when allocating.
+        let offset = Self::suffix_offset(block.size(), block.align()).unwrap();
+        block.ptr().offset((offset - Self::prefix_offset(block.align())) as isize) as *mut S
+    }
+
+    // the underlying block for a user's block.
+    unsafe fn outer<'a>(&self, block: &Block) -> Block<'a> {
+        let offset = Self::prefix_offset(block.align());
+        Block::new(block.ptr().offset(-(offset as isize)),
+                   Self::outer_size(block.size(), block.align()).unwrap(),
+                   Self::outer_align(block.align()))
+    }
+
+    // the user's block for an underlying block.
+    unsafe fn inner<'a>(&self, outer: Block<'a>, size: usize, align: usize) -> Block<'a> {
+        let offset = Self::prefix_offset(align);
+        Block::new(outer.ptr().offset(offset as isize), size, align)
+    }
+}
+
+unsafe impl<A: Allocator, P: Default, S: Default> Allocator for Affix<A, P, S> {
+    unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        if size == 0 {
+            return Ok(Block::empty());
+        }
+
+        let outer_size = match Self::outer_size(size, align) {
+            Some(outer_size) => outer_size,
+            None => return Err(Error::OutOfMemory),
+        };
+
+        match self.alloc.allocate_raw(outer_size, Self::outer_align(align)) {
+            Ok(outer) => {
+                let block = self.inner(outer, size, align);
+                ptr::write(self.prefix_ptr(&block), P::default());
+                ptr::write(self.suffix_ptr(&block), S::default());
+                Ok(block)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    unsafe fn reallocate_raw<'a>(&'a self, block: Block<'a>, new_size: usize) -> Result<Block<'a>, (Error, Block<'a>)> {
+        if new_size == 0 {
+            self.deallocate_raw(block);
+            return Ok(Block::empty());
+        } else if block.is_empty() {
+            return Err((Error::UnsupportedAlignment, block));
+        }
+
+        let outer_size = match Self::outer_size(new_size, block.align()) {
+            Some(outer_size) => outer_size,
+            None => return Err((Error::OutOfMemory, block)),
+        };
+
+        // the prefix moves along with the block, but the suffix has to be
+        // carried over to its new position by hand.
+        let (size, align) = (block.size(), block.align());
+        let suffix = ptr::read(self.suffix_ptr(&block));
+        match self.alloc.reallocate_raw(self.outer(&block), outer_size) {
+            Ok(outer) => {
+                let new_block = self.inner(outer, new_size, align);
+                ptr::write(self.suffix_ptr(&new_block), suffix);
+                Ok(new_block)
+            }
+            Err((err, outer)) => {
+                // the suffix is still in place.
+                mem::forget(suffix);
+                Err((err, self.inner(outer, size, align)))
+            }
+        }
+    }
+
+    unsafe fn deallocate_raw(&self, block: Block) {
+        if block.is_empty() {
+            return;
+        }
+
+        ptr::drop_in_place(self.prefix_ptr(&block));
+        ptr::drop_in_place(self.suffix_ptr(&block));
+        self.alloc.deallocate_raw(self.outer(&block));
+    }
+}
+
+impl<A: BlockOwner, P: Default, S: Default> BlockOwner for Affix<A, P, S> {
+    fn owns_block(&self, block: &Block) -> bool {
+        if block.is_empty() {
+            return false;
+        }
+
+        match Self::outer_size(block.size(), block.align()) {
+            Some(_) => self.alloc.owns_block(&unsafe { self.outer(block) }),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+
+    use super::super::*;
+
+    const CANARY: u64 = 0xDEADBEEFCAFEBABE;
+
+    #[derive(Debug, PartialEq)]
+    struct Canary(u64);
+
+    impl Default for Canary {
+        fn default() -> Self {
+            Canary(CANARY)
+        }
+    }
+
+    #[test]
+    fn affixes() {
+        let alloc: Affix<_, u32, u16> = Affix::new(Scoped::new(256).unwrap());
+        unsafe {
+            let block = alloc.allocate_raw(3, 1).unwrap();
+            assert_eq!(*alloc.prefix(&block), 0);
+            assert_eq!(*alloc.suffix(&block), 0);
+
+            let block = alloc.allocate_raw(16, 16).unwrap();
+            assert_eq!(block.ptr() as usize % 16, 0);
+            assert!(alloc.owns_block(&block));
+        }
+
+        let val = alloc.allocate(7u64).unwrap();
+        assert_eq!(*val, 7);
+    }
+
+    #[test]
+    fn suffix_alignment() {
+        let alloc: Affix<_, u8, u64> = Affix::new(Scoped::new(1024).unwrap());
+        unsafe {
+            for size in 1..17 {
+                let block = alloc.allocate_raw(size, 1).unwrap();
+                assert_eq!(alloc.suffix(&block) as *const u64 as usize % mem::align_of::<u64>(), 0);
+                assert_eq!(*alloc.suffix(&block), 0);
+
+                let block = alloc.reallocate_raw(block, size + 3).ok().unwrap();
+                assert_eq!(alloc.suffix(&block) as *const u64 as usize % mem::align_of::<u64>(), 0);
+                alloc.deallocate_raw(block);
+            }
+        }
+    }
+
+    #[test]
+    fn canary() {
+        let alloc: Affix<_, (), Canary> = Affix::new(HEAP);
+        unsafe {
+            let block = alloc.allocate_raw(8, 8).unwrap();
+            assert_eq!(*alloc.suffix(&block), Canary(CANARY));
+
+            // write one byte past the end of the block.
+            for i in 0..9 {
+                *block.ptr().offset(i) = 0;
+            }
+            assert!(*alloc.suffix(&block) != Canary(CANARY));
+            alloc.deallocate_raw(block);
+        }
+    }
+
+    #[test]
+    fn realloc_keeps_affixes() {
+        let alloc: Affix<_, u64, Canary> = Affix::new(HEAP);
+        unsafe {
+            let mut block = alloc.allocate_raw(8, 8).unwrap();
+            *alloc.prefix_mut(&mut block) = 42;
+            let block = alloc.reallocate_raw(block, 1024).ok().unwrap();
+            assert_eq!(*alloc.prefix(&block), 42);
+            assert_eq!(*alloc.suffix(&block), Canary(CANARY));
+            alloc.deallocate_raw(block);
+        }
+    }
+}
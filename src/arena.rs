@@ -0,0 +1,219 @@
+//! A typed arena, handing out references to the values moved into it.
+
+use std::cell::RefCell;
+use std::cmp;
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr;
+use std::usize;
+
+use super::{Allocator, Block, Error, HeapAllocator, HEAP};
+
+// the size in bytes of the first chunk, unless a single value is larger.
+const FIRST_CHUNK_BYTES: usize = 4096;
+
+struct Chunk<T> {
+    ptr: *mut T,
+    len: usize,
+    cap: usize,
+}
+
+/// An arena of values of a single type.
+///
+/// Values are moved into chunks of memory from the backing allocator,
+/// and every reference handed out borrows the arena, so values can refer to each other.
+/// Each chunk is twice the size of the one before it. All values are dropped
+/// together when the arena is.
+pub struct TypedArena<'a, T, A: 'a + Allocator> {
+    alloc: &'a A,
+    chunks: RefCell<Vec<Chunk<T>>>,
+    _marker: PhantomData<T>,
+}
+
+impl<T> TypedArena<'static, T, HeapAllocator> {
+    /// Creates a new `TypedArena` backed by the heap.
+    pub fn new() -> Self {
+        TypedArena::new_from(HEAP)
+    }
+}
+
+impl<T> Default for TypedArena<'static, T, HeapAllocator> {
+    fn default() -> Self {
+        TypedArena::new()
+    }
+}
+
+impl<'a, T, A: 'a + Allocator> TypedArena<'a, T, A> {
+    /// Creates a new `TypedArena` backed by another allocator.
+    /// This does not allocate until the first value is.
+    pub fn new_from(alloc: &'a A) -> Self {
+        TypedArena {
+            alloc: alloc,
+            chunks: RefCell::new(Vec::new()),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Moves a value into the arena, returning a reference to it.
+    /// Gives back the value if a new chunk was needed and could not be allocated.
+    pub fn allocate(&self, val: T) -> Result<&mut T, (Error, T)> {
+        let mut chunks = self.chunks.borrow_mut();
+        let full = match chunks.last() {
+            Some(chunk) => chunk.len == chunk.cap,
+            None => true,
+        };
+
+        if full {
+            let chunk = match self.new_chunk(chunks.last()) {
+                Ok(chunk) => chunk,
+                Err(err) => return Err((err, val)),
+            };
+            chunks.push(chunk);
+        }
+
+        let chunk = chunks.last_mut().unwrap();
+        unsafe {
+            let ptr = chunk.ptr.offset(chunk.len as isize);
+            ptr::write(ptr, val);
+            chunk.len += 1;
+            Ok(&mut *ptr)
+        }
+    }
+
+    /// Moves a value into the arena, returning a reference to it.
+    ///
+    /// # Panics
+    /// Panics if a new chunk was needed and could not be allocated.
+    pub fn alloc(&self, val: T) -> &mut T {
+        match self.allocate(val) {
+            Ok(val) => val,
+            Err((err, _)) => panic!("TypedArena failed to allocate a chunk: {}", err),
+        }
+    }
+
+    /// The number of values in the arena.
+    pub fn len(&self) -> usize {
+        self.chunks.borrow().iter().map(|chunk| chunk.len).sum()
+    }
+
+    /// Whether the arena has no values.
+    pub fn is_empty(&self) -> bool {
+        self.chunks.borrow().iter().all(|chunk| chunk.len == 0)
+    }
+
+    fn new_chunk(&self, last: Option<&Chunk<T>>) -> Result<Chunk<T>, Error> {
+        let (size, align) = (mem::size_of::<T>(), mem::align_of::<T>());
+        if size == 0 {
+            // zero-sized values take up no memory, so one chunk holds all of them.
+            return Ok(Chunk {
+                ptr: align as *mut T,
+                len: 0,
+                cap: usize::MAX,
+            });
+        }
+
+        let cap = match last {
+            Some(chunk) => chunk.cap.saturating_mul(2),
+            None => cmp::max(FIRST_CHUNK_BYTES / size, 1),
+        };
+        let bytes = match cap.checked_mul(size) {
+            Some(bytes) => bytes,
+            None => return Err(Error::OutOfMemory),
+        };
+
+        match unsafe { self.alloc.allocate_raw(bytes, align) } {
+            Ok(block) => Ok(Chunk {
+                ptr: block.ptr() as *mut T,
+                len: 0,
+                cap: cap,
+            }),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+unsafe impl<'a, #[may_dangle] T, A: 'a + Allocator> Drop for TypedArena<'a, T, A> {
+    fn drop(&mut self) {
+        let (size, align) = (mem::size_of::<T>(), mem::align_of::<T>());
+        for chunk in self.chunks.get_mut().iter() {
+            unsafe {
+                for i in 0..chunk.len {
+                    ptr::drop_in_place(chunk.ptr.offset(i as isize));
+                }
+
+                if size != 0 {
+                    self.alloc.deallocate_raw(Block::new(chunk.ptr as *mut u8, chunk.cap * size, align));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::super::*;
+
+    #[test]
+    fn drops_everything() {
+        struct Bomb<'a>(&'a Cell<usize>);
+        impl<'a> Drop for Bomb<'a> {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let count = Cell::new(0);
+        {
+            let arena = TypedArena::new();
+            // enough to need a few chunks.
+            for _ in 0..10000 {
+                arena.alloc(Bomb(&count));
+            }
+            assert_eq!(arena.len(), 10000);
+            assert_eq!(count.get(), 0);
+        }
+        assert_eq!(count.get(), 10000);
+    }
+
+    #[test]
+    fn zero_sized() {
+        let arena = TypedArena::default();
+        assert!(arena.is_empty());
+        for _ in 0..10000 {
+            arena.alloc(());
+        }
+        assert_eq!(arena.len(), 10000);
+    }
+
+    #[test]
+    fn linked_list() {
+        struct Node<'a> {
+            val: u32,
+            next: Option<&'a Node<'a>>,
+        }
+
+        let arena = TypedArena::new();
+        let mut head: Option<&Node> = None;
+        for i in 0..1000 {
+            head = Some(arena.alloc(Node { val: i, next: head }));
+        }
+
+        let mut sum = 0;
+        let mut node = head;
+        while let Some(n) = node {
+            sum += n.val;
+            node = n.next;
+        }
+        assert_eq!(sum, 999 * 1000 / 2);
+    }
+
+    #[test]
+    fn bounded() {
+        let backing = Scoped::new(64).unwrap();
+        let arena = TypedArena::new_from(&backing);
+        // the first chunk is too large for the backing allocator.
+        assert_eq!(arena.allocate(0u64).err().unwrap(), (Error::OutOfMemory, 0));
+    }
+}
@@ -0,0 +1,212 @@
+//! A linear allocator which can be shared between threads.
+
+use std::cmp;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use super::{Allocator, Error, Block, BlockOwner, HeapAllocator, HEAP};
+
+/// A linear allocator like `Scoped`, but whose bump pointer is advanced atomically,
+/// so it can allocate from several threads at once without a lock.
+///
+/// Unlike `Scoped`, it can't be scoped. Deallocation only reclaims memory
+/// when the block freed is the most recent one.
+pub struct AtomicScoped<'parent, A: 'parent + Allocator> {
+    allocator: &'parent A,
+    current: AtomicPtr<u8>,
+    end: *mut u8,
+    start: *mut u8,
+}
+
+impl AtomicScoped<'static, HeapAllocator> {
+    /// Creates a new `AtomicScoped` backed by `size` bytes from the heap.
+    pub fn new(size: usize) -> Result<Self, Error> {
+        AtomicScoped::new_from(HEAP, size)
+    }
+}
+
+impl<'parent, A: Allocator> AtomicScoped<'parent, A> {
+    /// Creates a new `AtomicScoped` backed by `size` bytes from the allocator supplied.
+    pub fn new_from(alloc: &'parent A, size: usize) -> Result<Self, Error> {
+        match unsafe { alloc.allocate_raw(size, mem::align_of::<usize>()) } {
+            Ok(block) => Ok(AtomicScoped {
+                allocator: alloc,
+                current: AtomicPtr::new(block.ptr()),
+                end: unsafe { block.ptr().offset(block.size() as isize) },
+                start: block.ptr(),
+            }),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// The number of bytes allocated so far,
+    /// including any padding inserted for alignment.
+    pub fn bytes_used(&self) -> usize {
+        self.current.load(Ordering::Relaxed) as usize - self.start as usize
+    }
+
+    /// The number of bytes left for allocation.
+    pub fn bytes_remaining(&self) -> usize {
+        self.end as usize - self.current.load(Ordering::Relaxed) as usize
+    }
+
+    /// Makes the entire buffer of this allocator available again.
+    ///
+    /// See `Scoped::reset`.
+    pub fn reset(&mut self) {
+        *self.current.get_mut() = self.start;
+    }
+
+    // moves the current pointer from `old` to `new`, if no other thread got there first.
+    fn advance(&self, old: *mut u8, new: *mut u8) -> bool {
+        self.current.compare_exchange_weak(old, new, Ordering::AcqRel, Ordering::Relaxed).is_ok()
+    }
+}
+
+unsafe impl<'a, A: Allocator> Allocator for AtomicScoped<'a, A> {
+    unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        if size == 0 {
+            return Ok(Block::empty());
+        }
+
+        loop {
+            let current_ptr = self.current.load(Ordering::Relaxed);
+            let aligned_ptr = match super::align_forward(current_ptr, align) {
+                Some(ptr) => ptr,
+                None => return Err(Error::OutOfMemory),
+            };
+
+            match (aligned_ptr as usize).checked_add(size) {
+                Some(end) if end <= self.end as usize => {
+                    if self.advance(current_ptr, end as *mut u8) {
+                        return Ok(Block::new(aligned_ptr, size, align));
+                    }
+                }
+                _ => return Err(Error::OutOfMemory),
+            }
+        }
+    }
+
+    unsafe fn reallocate_raw<'b>(&'b self, block: Block<'b>, new_size: usize) -> Result<Block<'b>, (Error, Block<'b>)> {
+        if new_size == 0 {
+            self.deallocate_raw(block);
+            return Ok(Block::empty());
+        } else if block.is_empty() {
+            return Err((Error::UnsupportedAlignment, block));
+        }
+
+        match self.grow_in_place(block, new_size) {
+            Ok(block) => Ok(block),
+            Err((_, block)) => {
+                match self.allocate_raw(new_size, block.align()) {
+                    Ok(new_block) => {
+                        ptr::copy_nonoverlapping(block.ptr(), new_block.ptr(), cmp::min(block.size(), new_size));
+                        self.deallocate_raw(block);
+                        Ok(new_block)
+                    }
+                    Err(err) => Err((err, block)),
+                }
+            }
+        }
+    }
+
+    unsafe fn grow_in_place<'b>(&'b self, block: Block<'b>, new_size: usize) -> Result<Block<'b>, (Error, Block<'b>)> {
+        if block.is_empty() {
+            return Err((Error::CannotResizeInPlace, block));
+        }
+
+        // only the last block can be resized, and only if no other thread allocates meanwhile.
+        let block_end = block.ptr().offset(block.size() as isize);
+        let fits = (block.ptr() as usize).checked_add(new_size)
+                                         .map_or(false, |new_cur| new_cur <= self.end as usize);
+        if fits && self.current.compare_exchange(block_end,
+                                                 block.ptr().offset(new_size as isize),
+                                                 Ordering::AcqRel,
+                                                 Ordering::Relaxed)
+                               .is_ok() {
+            Ok(Block::new(block.ptr(), new_size, block.align()))
+        } else {
+            Err((Error::CannotResizeInPlace, block))
+        }
+    }
+
+    unsafe fn deallocate_raw(&self, block: Block) {
+        if block.is_empty() {
+            return;
+        }
+
+        // give the memory back only if this is still the last allocation.
+        let block_end = block.ptr().offset(block.size() as isize);
+        let _ = self.current.compare_exchange(block_end, block.ptr(), Ordering::AcqRel, Ordering::Relaxed);
+    }
+}
+
+impl<'a, A: Allocator> BlockOwner for AtomicScoped<'a, A> {
+    fn owns_block(&self, block: &Block) -> bool {
+        let ptr = block.ptr();
+
+        ptr >= self.start && (ptr as usize + block.size()) <= self.end as usize
+    }
+}
+
+impl<'a, A: Allocator> Drop for AtomicScoped<'a, A> {
+    fn drop(&mut self) {
+        let size = self.end as usize - self.start as usize;
+        if size > 0 {
+            unsafe {
+                self.allocator
+                    .deallocate_raw(Block::new(self.start, size, mem::align_of::<usize>()))
+            }
+        }
+    }
+}
+
+unsafe impl<'a, A: 'a + Allocator + Sync> Send for AtomicScoped<'a, A> {}
+unsafe impl<'a, A: 'a + Allocator + Sync> Sync for AtomicScoped<'a, A> {}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    #[test]
+    fn concurrent() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let alloc = Arc::new(AtomicScoped::new(8 * 1024).unwrap());
+        let threads: Vec<_> = (0..8).map(|_| {
+            let alloc = alloc.clone();
+            thread::spawn(move || {
+                (0..100).map(|_| unsafe { alloc.allocate_raw(8, 8).unwrap().ptr() as usize })
+                        .collect::<Vec<_>>()
+            })
+        }).collect();
+
+        let mut addrs: Vec<usize> = threads.into_iter().flat_map(|t| t.join().unwrap()).collect();
+        addrs.sort();
+        // no two blocks overlap.
+        for pair in addrs.windows(2) {
+            assert!(pair[1] - pair[0] >= 8);
+        }
+        assert_eq!(alloc.bytes_remaining(), 8 * 1024 - 8 * 800);
+    }
+
+    #[test]
+    fn last_block() {
+        let mut alloc = AtomicScoped::new(64).unwrap();
+        unsafe {
+            let first = alloc.allocate_raw(8, 8).unwrap();
+            let second = alloc.allocate_raw(8, 8).unwrap();
+            // only the last block is given back or grown.
+            alloc.deallocate_raw(first);
+            assert_eq!(alloc.bytes_used(), 16);
+            let second = alloc.reallocate_raw(second, 32).ok().unwrap();
+            assert_eq!(alloc.bytes_used(), 40);
+            alloc.deallocate_raw(second);
+            assert_eq!(alloc.bytes_used(), 8);
+        }
+        alloc.reset();
+        assert_eq!(alloc.bytes_used(), 0);
+    }
+}
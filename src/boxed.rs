@@ -1,23 +1,41 @@
 use std::any::Any;
 use std::borrow::{Borrow, BorrowMut};
+use std::cmp::{self, Ordering};
+use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::marker::{PhantomData, Unsize};
 use std::mem;
 use std::ops::{CoerceUnsized, Deref, DerefMut, InPlace, Placer};
 use std::ops::Place as StdPlace;
-use std::ptr::Unique;
+use std::ptr::{self, Unique};
+use std::slice;
 
-use super::{Allocator, Block};
+use super::{Allocator, Block, HeapAllocator};
 
 /// An item allocated by a custom allocator.
 pub struct AllocBox<'a, T: 'a + ?Sized, A: 'a + ?Sized + Allocator> {
     item: Unique<T>,
+    // the size and alignment the block was allocated with. These are kept
+    // through unsizing coercions, so a slice or trait object is freed with
+    // the layout of the value it was made from.
     size: usize,
     align: usize,
     allocator: &'a A,
 }
 
 impl<'a, T: ?Sized, A: ?Sized + Allocator> AllocBox<'a, T, A> {
+    /// Moves a value into memory from the allocator supplied,
+    /// giving the value back along with the error if the allocation fails.
+    /// This is the same as `allocate_in`.
+    pub fn try_allocate(alloc: &'a A, val: T) -> Result<Self, (super::Error, T)> where T: Sized {
+        allocate_in(alloc, val)
+    }
+
     /// Consumes this allocated value, yielding the value it manages.
+    ///
+    /// An `AllocBox` owns its value outright and only borrows its allocator,
+    /// so there is nothing shared to check first, unlike with `Rc::try_unwrap`:
+    /// this always succeeds, however many other values share the allocator.
     pub fn take(self) -> T where T: Sized {
         let val = unsafe { ::std::ptr::read(self.item.as_ptr()) };
         let block = Block::new(self.item.as_ptr() as *mut u8, self.size, self.align);
@@ -26,10 +44,129 @@ impl<'a, T: ?Sized, A: ?Sized + Allocator> AllocBox<'a, T, A> {
         val
     }
 
+    /// Consumes this allocated value, transforming it with the function supplied.
+    ///
+    /// The result is written into the same block if it fits, with no trip to the allocator.
+    /// Otherwise, the block is reallocated to make room for it.
+    ///
+    /// # Panics
+    /// Panics if the block has to be reallocated, and the allocator fails to.
+    pub fn map<U, F>(self, f: F) -> AllocBox<'a, U, A>
+        where T: Sized, F: FnOnce(T) -> U
+    {
+        let val = unsafe { ptr::read(self.item.as_ptr()) };
+        // the place frees the block if `f` panics.
+        let mut place: Place<'a, U, A> = Place {
+            allocator: self.allocator,
+            block: Block::new(self.item.as_ptr() as *mut u8, self.size, self.align),
+            _marker: PhantomData,
+        };
+        mem::forget(self);
+        let new_val = f(val);
+
+        let (size, align) = (mem::size_of::<U>(), mem::align_of::<U>());
+        if size > place.block.size() || align > place.block.align() {
+            let block = mem::replace(&mut place.block, Block::empty());
+            let result = unsafe {
+                if align <= block.align() && !block.is_empty() {
+                    place.allocator.reallocate_raw(block, size)
+                } else {
+                    // the block can't be reallocated to a larger alignment, so make a new one.
+                    match place.allocator.allocate_raw(size, align) {
+                        Ok(new_block) => {
+                            place.allocator.deallocate_raw(block);
+                            Ok(new_block)
+                        }
+                        Err(err) => Err((err, block)),
+                    }
+                }
+            };
+
+            match result {
+                Ok(new_block) => place.block = new_block,
+                Err((err, block)) => {
+                    place.block = block;
+                    panic!("Failed to map an AllocBox: {:?}", err);
+                }
+            }
+        }
+
+        unsafe {
+            ptr::write(place.pointer(), new_val);
+            place.finalize()
+        }
+    }
+
+    /// Converts this into a standard `Box`.
+    ///
+    /// Values allocated from the heap are handed over as they are, without copying.
+    /// Values from any other allocator are moved into a new `Box`, and their memory freed.
+    pub fn into_std_box(self) -> Box<T> where T: Sized {
+        IntoStdBox::into_std_box(self)
+    }
+
     /// Gets a handle to the block of memory this manages.
     pub unsafe fn as_block(&self) -> Block {
         Block::new(self.item.as_ptr() as *mut u8, self.size, self.align)
     }
+
+    /// Consumes this allocated value without dropping it or deallocating its memory,
+    /// yielding a reference which lives as long as the allocator borrow.
+    /// This is most useful with allocators which free their memory in bulk, like `Scoped`.
+    pub fn leak(self) -> &'a mut T {
+        let ptr = self.item.as_ptr();
+        mem::forget(self);
+        unsafe { &mut *ptr }
+    }
+
+    /// Consumes this allocated value without dropping it or deallocating its memory,
+    /// yielding a pointer to the value and the block of memory it lives in.
+    /// These can be turned back into an `AllocBox` with `from_raw`.
+    pub fn into_raw(self) -> (*mut T, Block<'a>) {
+        let ptr = self.item.as_ptr();
+        let block = Block::new(ptr as *mut u8, self.size, self.align);
+        mem::forget(self);
+        (ptr, block)
+    }
+
+    /// Reconstructs an `AllocBox` from the parts yielded by `into_raw`.
+    ///
+    /// # Safety
+    /// The pointer and block must have come from a call to `into_raw`
+    /// on a value allocated by `allocator`, and may only be used once.
+    pub unsafe fn from_raw(ptr: *mut T, block: Block<'a>, allocator: &'a A) -> Self {
+        AllocBox {
+            item: Unique::new(ptr),
+            size: block.size(),
+            align: block.align(),
+            allocator: allocator,
+        }
+    }
+}
+
+// picks the way to convert an `AllocBox` into a `Box` by allocator.
+trait IntoStdBox<T> {
+    fn into_std_box(self) -> Box<T>;
+}
+
+impl<'a, T, A: ?Sized + Allocator> IntoStdBox<T> for AllocBox<'a, T, A> {
+    default fn into_std_box(self) -> Box<T> {
+        Box::new(self.take())
+    }
+}
+
+impl<'a, T> IntoStdBox<T> for AllocBox<'a, T, HeapAllocator> {
+    fn into_std_box(self) -> Box<T> {
+        // a `Box` frees its memory with the layout of its value,
+        // so it can only take over a block allocated with exactly that.
+        if self.size == 0 || self.size != mem::size_of::<T>() || self.align != mem::align_of::<T>() {
+            return Box::new(self.take());
+        }
+
+        let ptr = self.item.as_ptr();
+        mem::forget(self);
+        unsafe { Box::from_raw(ptr) }
+    }
 }
 
 impl<'a, T: ?Sized, A: ?Sized + Allocator> Deref for AllocBox<'a, T, A> {
@@ -69,6 +206,19 @@ impl<'a, A: ?Sized + Allocator> AllocBox<'a, Any, A> {
     }
 }
 
+impl<'a, T: Clone, A: ?Sized + Allocator> Clone for AllocBox<'a, T, A> {
+    /// Clones the value into a new block from the same allocator.
+    ///
+    /// # Panics
+    /// Panics if the allocator can't serve the new block, much like `Box` aborts.
+    fn clone(&self) -> Self {
+        match allocate_in(self.allocator, (**self).clone()) {
+            Ok(allocated) => allocated,
+            Err((err, _)) => panic!("Failed to clone an AllocBox: {:?}", err),
+        }
+    }
+}
+
 impl<'a, T: ?Sized, A: ?Sized + Allocator> Borrow<T> for AllocBox<'a, T, A> {
     fn borrow(&self) -> &T {
         &**self
@@ -81,6 +231,147 @@ impl<'a, T: ?Sized, A: ?Sized + Allocator> BorrowMut<T> for AllocBox<'a, T, A> {
     }
 }
 
+impl<'a, T, A: ?Sized + Allocator> IntoIterator for AllocBox<'a, [T], A> {
+    type Item = T;
+    type IntoIter = SliceIntoIter<'a, T, A>;
+
+    /// Moves the elements out of the slice one by one.
+    /// The block is freed when the iterator is dropped, along with any elements left in it.
+    fn into_iter(self) -> SliceIntoIter<'a, T, A> {
+        let iter = SliceIntoIter {
+            ptr: self.item.as_ptr() as *mut T,
+            next: 0,
+            end: self.len(),
+            size: self.size,
+            align: self.align,
+            allocator: self.allocator,
+        };
+        mem::forget(self);
+        iter
+    }
+}
+
+impl<'a, 'b, T, A: ?Sized + Allocator> IntoIterator for &'b AllocBox<'a, [T], A> {
+    type Item = &'b T;
+    type IntoIter = slice::Iter<'b, T>;
+
+    fn into_iter(self) -> slice::Iter<'b, T> {
+        self.iter()
+    }
+}
+
+impl<'a, 'b, T, A: ?Sized + Allocator> IntoIterator for &'b mut AllocBox<'a, [T], A> {
+    type Item = &'b mut T;
+    type IntoIter = slice::IterMut<'b, T>;
+
+    fn into_iter(self) -> slice::IterMut<'b, T> {
+        self.iter_mut()
+    }
+}
+
+/// An iterator moving the elements out of an `AllocBox<[T]>`, created by `into_iter`.
+pub struct SliceIntoIter<'a, T, A: 'a + ?Sized + Allocator> {
+    ptr: *mut T,
+    // the elements in `next..end` haven't been moved out yet.
+    next: usize,
+    end: usize,
+    size: usize,
+    align: usize,
+    allocator: &'a A,
+}
+
+impl<'a, T, A: ?Sized + Allocator> Iterator for SliceIntoIter<'a, T, A> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.next == self.end {
+            return None;
+        }
+
+        let val = unsafe { ptr::read(self.ptr.offset(self.next as isize)) };
+        self.next += 1;
+        Some(val)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.end - self.next;
+        (len, Some(len))
+    }
+}
+
+impl<'a, T, A: ?Sized + Allocator> DoubleEndedIterator for SliceIntoIter<'a, T, A> {
+    fn next_back(&mut self) -> Option<T> {
+        if self.next == self.end {
+            return None;
+        }
+
+        self.end -= 1;
+        Some(unsafe { ptr::read(self.ptr.offset(self.end as isize)) })
+    }
+}
+
+impl<'a, T, A: ?Sized + Allocator> ExactSizeIterator for SliceIntoIter<'a, T, A> {}
+
+impl<'a, T, A: ?Sized + Allocator> Drop for SliceIntoIter<'a, T, A> {
+    fn drop(&mut self) {
+        unsafe {
+            let rest = slice::from_raw_parts_mut(self.ptr.offset(self.next as isize), self.end - self.next);
+            ptr::drop_in_place(rest);
+            self.allocator.deallocate_raw(Block::new(self.ptr as *mut u8, self.size, self.align));
+        }
+    }
+}
+
+impl<'a, T: ?Sized, A: ?Sized + Allocator> AsRef<T> for AllocBox<'a, T, A> {
+    fn as_ref(&self) -> &T {
+        &**self
+    }
+}
+
+impl<'a, T: ?Sized, A: ?Sized + Allocator> AsMut<T> for AllocBox<'a, T, A> {
+    fn as_mut(&mut self) -> &mut T {
+        &mut **self
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug, A: ?Sized + Allocator> fmt::Debug for AllocBox<'a, T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display, A: ?Sized + Allocator> fmt::Display for AllocBox<'a, T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<'a, 'b, T: ?Sized + PartialEq, A: ?Sized + Allocator, B: ?Sized + Allocator> PartialEq<AllocBox<'b, T, B>> for AllocBox<'a, T, A> {
+    fn eq(&self, other: &AllocBox<'b, T, B>) -> bool {
+        PartialEq::eq(&**self, &**other)
+    }
+}
+
+impl<'a, T: ?Sized + Eq, A: ?Sized + Allocator> Eq for AllocBox<'a, T, A> {}
+
+impl<'a, 'b, T: ?Sized + PartialOrd, A: ?Sized + Allocator, B: ?Sized + Allocator> PartialOrd<AllocBox<'b, T, B>> for AllocBox<'a, T, A> {
+    fn partial_cmp(&self, other: &AllocBox<'b, T, B>) -> Option<Ordering> {
+        PartialOrd::partial_cmp(&**self, &**other)
+    }
+}
+
+impl<'a, T: ?Sized + Ord, A: ?Sized + Allocator> Ord for AllocBox<'a, T, A> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        Ord::cmp(&**self, &**other)
+    }
+}
+
+impl<'a, T: ?Sized + Hash, A: ?Sized + Allocator> Hash for AllocBox<'a, T, A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
 impl<'a, T: ?Sized, A: ?Sized + Allocator> Drop for AllocBox<'a, T, A> {
     #[inline]
     fn drop(&mut self) {
@@ -95,9 +386,19 @@ impl<'a, T: ?Sized, A: ?Sized + Allocator> Drop for AllocBox<'a, T, A> {
 
 
 pub fn make_place<A: ?Sized + Allocator, T>(alloc: &A) -> Result<Place<T, A>, super::Error> {
-    let (size, align) = (mem::size_of::<T>(), mem::align_of::<T>());
+    make_place_aligned(alloc, mem::align_of::<T>())
+}
+
+pub fn make_place_aligned<A: ?Sized + Allocator, T>(alloc: &A, align: usize) -> Result<Place<T, A>, super::Error> {
+    if !align.is_power_of_two() {
+        return Err(super::Error::UnsupportedAlignment);
+    }
+
+    let (size, align) = (mem::size_of::<T>(), cmp::max(mem::align_of::<T>(), align));
     match unsafe { alloc.allocate_raw(size, align) } {
         Ok(block) => {
+            // an empty block doesn't keep its alignment, but a zero-sized value's address must.
+            let block = if block.is_empty() { Block::new(align as *mut u8, 0, align) } else { block };
             Ok(Place {
                 allocator: alloc,
                 block: block,
@@ -108,6 +409,132 @@ pub fn make_place<A: ?Sized + Allocator, T>(alloc: &A) -> Result<Place<T, A>, su
     }
 }
 
+/// Moves a value into memory from the allocator supplied,
+/// giving the value back if the allocation fails.
+///
+/// This is the same as `Allocator::allocate`, but also works for
+/// unsized allocators such as trait objects.
+///
+/// # Examples
+/// ```rust
+/// use allocators::{allocate_in, Scoped};
+///
+/// let alloc = Scoped::new(64).unwrap();
+/// let val = allocate_in(&alloc, [1u32, 2, 3]).ok().unwrap();
+/// assert_eq!(val[2], 3);
+///
+/// // the value comes back if there's no room for it.
+/// let (_, val) = allocate_in(&alloc, [0u8; 128]).err().unwrap();
+/// assert_eq!(val.len(), 128);
+/// ```
+pub fn allocate_in<'a, T, A: ?Sized + Allocator>(alloc: &'a A, val: T) -> Result<AllocBox<'a, T, A>, (super::Error, T)> {
+    match make_place(alloc) {
+        Ok(mut place) => unsafe {
+            ptr::write(place.pointer(), val);
+            Ok(place.finalize())
+        },
+        Err(e) => Err((e, val)),
+    }
+}
+
+pub fn allocate_slice<'a, A: ?Sized + Allocator, T: Clone>(alloc: &'a A, vals: &[T]) -> Result<AllocBox<'a, [T], A>, super::Error> {
+    collect_into(alloc, vals.iter().cloned())
+}
+
+pub fn allocate_str<'a, A: ?Sized + Allocator>(alloc: &'a A, s: &str) -> Result<AllocBox<'a, str, A>, super::Error> {
+    match allocate_slice(alloc, s.as_bytes()) {
+        Ok(bytes) => {
+            // the bytes came from a `str`, so they are valid UTF-8.
+            let allocated = AllocBox {
+                item: unsafe { Unique::new(bytes.item.as_ptr() as *mut str) },
+                size: bytes.size,
+                align: bytes.align,
+                allocator: bytes.allocator,
+            };
+            mem::forget(bytes);
+            Ok(allocated)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Collects the values of an iterator into a slice allocated from the allocator supplied.
+///
+/// The length of the iterator is known up front, so the slice is allocated exactly once.
+/// An iterator which yields fewer values than it reported gives a shorter slice,
+/// and any values past its reported length are left in it.
+///
+/// # Examples
+/// ```rust
+/// use allocators::{collect_into, Scoped};
+///
+/// let alloc = Scoped::new(64).unwrap();
+/// let squares = collect_into(&alloc, (1..5).map(|i| i * i)).unwrap();
+/// assert_eq!(&*squares, &[1, 4, 9, 16]);
+/// ```
+pub fn collect_into<'a, A, T, I>(alloc: &'a A, iter: I) -> Result<AllocBox<'a, [T], A>, super::Error>
+    where A: ?Sized + Allocator, I: IntoIterator<Item = T>, I::IntoIter: ExactSizeIterator
+{
+    let iter = iter.into_iter();
+    let len = iter.len();
+    let align = mem::align_of::<T>();
+    let size = match mem::size_of::<T>().checked_mul(len) {
+        Some(size) => size,
+        None => return Err(super::Error::OutOfMemory),
+    };
+
+    let block = match unsafe { alloc.allocate_raw(size, align) } {
+        Ok(block) => block,
+        Err(e) => return Err(e),
+    };
+
+    // an empty block has no meaningful address, so point at
+    // an aligned dangling one instead.
+    let ptr = if block.is_empty() {
+        align as *mut T
+    } else {
+        block.ptr() as *mut T
+    };
+
+    // if the iterator panics, drop the elements written so far and free the block.
+    struct Guard<'a, T: 'a, A: 'a + ?Sized + Allocator> {
+        allocator: &'a A,
+        ptr: *mut T,
+        size: usize,
+        len: usize,
+    }
+
+    impl<'a, T: 'a, A: 'a + ?Sized + Allocator> Drop for Guard<'a, T, A> {
+        fn drop(&mut self) {
+            unsafe {
+                ptr::drop_in_place(slice::from_raw_parts_mut(self.ptr, self.len));
+                self.allocator.deallocate_raw(Block::new(self.ptr as *mut u8, self.size, mem::align_of::<T>()));
+            }
+        }
+    }
+
+    let mut guard = Guard {
+        allocator: alloc,
+        ptr: ptr,
+        size: size,
+        len: 0,
+    };
+
+    for val in iter.take(len) {
+        unsafe { ptr::write(ptr.offset(guard.len as isize), val) };
+        guard.len += 1;
+    }
+    let len = guard.len;
+    mem::forget(guard);
+
+    Ok(AllocBox {
+        item: unsafe { Unique::new(slice::from_raw_parts_mut(ptr, len)) },
+        size: size,
+        align: align,
+        allocator: alloc,
+    })
+}
+
 /// A place for allocating into.
 /// This is only used for in-place allocation,
 /// e.g. `let val = in (alloc.make_place().unwrap()) { EXPR }`
@@ -124,11 +551,23 @@ impl<'a, T: 'a, A: 'a + ?Sized + Allocator> Placer<T> for Place<'a, T, A> {
     }
 }
 
+impl<'a, T: 'a, A: 'a + ?Sized + Allocator> Place<'a, T, A> {
+    // an empty block has no meaningful address, so zero-sized values
+    // get an aligned dangling one instead.
+    fn ptr(&self) -> *mut T {
+        if self.block.is_empty() {
+            cmp::max(mem::align_of::<T>(), self.block.align()) as *mut T
+        } else {
+            self.block.ptr() as *mut T
+        }
+    }
+}
+
 impl<'a, T: 'a, A: 'a + ?Sized + Allocator> InPlace<T> for Place<'a, T, A> {
     type Owner = AllocBox<'a, T, A>;
     unsafe fn finalize(self) -> Self::Owner {
         let allocated = AllocBox {
-            item: Unique::new(self.block.ptr() as *mut T),
+            item: Unique::new(self.ptr()),
             size: self.block.size(),
             align: self.block.align(),
             allocator: self.allocator,
@@ -141,7 +580,7 @@ impl<'a, T: 'a, A: 'a + ?Sized + Allocator> InPlace<T> for Place<'a, T, A> {
 
 impl<'a, T: 'a, A: 'a + ?Sized + Allocator> StdPlace<T> for Place<'a, T, A> {
     fn pointer(&mut self) -> *mut T {
-        self.block.ptr() as *mut T
+        self.ptr()
     }
 }
 
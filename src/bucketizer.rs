@@ -0,0 +1,217 @@
+//! A free list allocator with configurable size buckets.
+
+use std::cmp;
+use std::mem;
+use std::ptr;
+
+use super::{Allocator, Error, Block, BlockOwner, FreeList, HeapAllocator, HEAP};
+
+/// A `Bucketizer` keeps a `FreeList` for each of a list of bucket sizes chosen
+/// up front, and serves each request from the smallest bucket it fits in.
+/// Requests larger than the biggest bucket, or with greater alignment than a pointer,
+/// go straight to the backing allocator.
+///
+/// This is like a `SegregatedFreeList`, but the buckets can be fitted to the sizes
+/// a program actually allocates, rather than every power of two.
+///
+/// A bucket which has run out of blocks fails with `OutOfMemory` rather than
+/// turning to the backing allocator, so that deallocation can be routed purely by size.
+pub struct Bucketizer<'a, A: 'a + Allocator> {
+    alloc: &'a A,
+    sizes: Vec<usize>,
+    buckets: Vec<FreeList<'a, A>>,
+}
+
+impl Bucketizer<'static, HeapAllocator> {
+    /// Creates a new `Bucketizer` backed by the heap,
+    /// with `blocks_per_bucket` blocks in a bucket for each of `sizes`.
+    pub fn new(sizes: &[usize], blocks_per_bucket: usize) -> Result<Self, Error> {
+        Bucketizer::new_from(HEAP, sizes, blocks_per_bucket)
+    }
+}
+
+impl<'a, A: 'a + Allocator> Bucketizer<'a, A> {
+    /// Creates a new `Bucketizer` backed by another allocator,
+    /// with `blocks_per_bucket` blocks in a bucket for each of `sizes`.
+    ///
+    /// The sizes must be in strictly increasing order, and no smaller than a pointer.
+    pub fn new_from(alloc: &'a A, sizes: &[usize], blocks_per_bucket: usize) -> Result<Self, Error> {
+        if sizes.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(Error::AllocatorSpecific("Bucket sizes must be strictly increasing.".into()));
+        }
+
+        let mut buckets = Vec::with_capacity(sizes.len());
+        for &size in sizes {
+            match FreeList::new_from(alloc, size, blocks_per_bucket) {
+                Ok(list) => buckets.push(list),
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(Bucketizer {
+            alloc: alloc,
+            sizes: sizes.to_vec(),
+            buckets: buckets,
+        })
+    }
+
+    /// The bucket sizes, smallest first.
+    pub fn bucket_sizes(&self) -> &[usize] {
+        &self.sizes
+    }
+
+    // the index of the bucket a request is served from, or `None` if it
+    // goes to the backing allocator.
+    fn bucket_index(&self, size: usize, align: usize) -> Option<usize> {
+        if align > mem::align_of::<*mut u8>() {
+            return None;
+        }
+
+        self.sizes.iter().position(|&bucket| bucket >= size)
+    }
+
+    fn bucket_of(&self, size: usize, align: usize) -> Option<&FreeList<'a, A>> {
+        self.bucket_index(size, align).map(|i| &self.buckets[i])
+    }
+}
+
+unsafe impl<'a, A: 'a + Allocator> Allocator for Bucketizer<'a, A> {
+    unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        if size == 0 {
+            return Ok(Block::empty());
+        }
+
+        match self.bucket_of(size, align) {
+            Some(bucket) => bucket.allocate_raw(size, align),
+            None => self.alloc.allocate_raw(size, align),
+        }
+    }
+
+    unsafe fn reallocate_raw<'b>(&'b self, block: Block<'b>, new_size: usize) -> Result<Block<'b>, (Error, Block<'b>)> {
+        if new_size == 0 {
+            self.deallocate_raw(block);
+            return Ok(Block::empty());
+        } else if block.is_empty() {
+            return Err((Error::UnsupportedAlignment, block));
+        }
+
+        let old_bucket = self.bucket_index(block.size(), block.align());
+        let new_bucket = self.bucket_index(new_size, block.align());
+
+        match (old_bucket, new_bucket) {
+            // same bucket: the block is already big enough.
+            (Some(old), Some(new)) if old == new => {
+                return Ok(Block::new(block.ptr(), new_size, block.align()));
+            }
+            (None, None) => return self.alloc.reallocate_raw(block, new_size),
+            _ => {}
+        }
+
+        // moving between buckets, or between a bucket and the backing allocator.
+        match self.allocate_raw(new_size, block.align()) {
+            Ok(new_block) => {
+                ptr::copy_nonoverlapping(block.ptr(), new_block.ptr(), cmp::min(block.size(), new_size));
+                self.deallocate_raw(block);
+                Ok(new_block)
+            }
+            Err(err) => Err((err, block)),
+        }
+    }
+
+    unsafe fn deallocate_raw(&self, block: Block) {
+        if block.is_empty() {
+            return;
+        }
+
+        match self.bucket_of(block.size(), block.align()) {
+            Some(bucket) => bucket.deallocate_raw(block),
+            None => self.alloc.deallocate_raw(block),
+        }
+    }
+
+    fn usable_size(&self, size: usize, align: usize) -> usize {
+        match self.bucket_index(size, align) {
+            Some(index) => self.sizes[index],
+            None => self.alloc.usable_size(size, align),
+        }
+    }
+}
+
+impl<'a, A: 'a + BlockOwner> BlockOwner for Bucketizer<'a, A> {
+    fn owns_block(&self, block: &Block) -> bool {
+        match self.bucket_of(block.size(), block.align()) {
+            Some(bucket) => bucket.owns_block(block),
+            None => self.alloc.owns_block(block),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    #[test]
+    fn buckets() {
+        let parent = Proxy::new(HEAP, StatsLogger::new());
+        let alloc = Bucketizer::new_from(&parent, &[32, 128, 512, 2048], 1).unwrap();
+        // one block for each bucket.
+        assert_eq!(parent.logger().snapshot().allocations, 4);
+
+        let a = alloc.allocate([1u8; 20]).unwrap();
+        let b = alloc.allocate([2u8; 100]).unwrap();
+        let c = alloc.allocate([3u64; 64]).unwrap();
+        let d = alloc.allocate([4u8; 2048]).unwrap();
+        assert_eq!(parent.logger().snapshot().allocations, 4);
+
+        // every bucket is full now.
+        assert!(alloc.allocate(0u64).is_err());
+        assert!(alloc.allocate([0u8; 1000]).is_err());
+
+        // bigger than any bucket.
+        let e = alloc.allocate([5u8; 4096]).unwrap();
+        assert_eq!(parent.logger().snapshot().allocations, 5);
+
+        assert_eq!(a[19], 1);
+        assert_eq!(b[99], 2);
+        assert_eq!(c[63], 3);
+        assert_eq!(d[2047], 4);
+        assert_eq!(e[4095], 5);
+
+        drop(e);
+        assert_eq!(parent.logger().snapshot().deallocations, 1);
+        drop((a, b, c, d));
+        // the blocks went back to their buckets.
+        assert_eq!(parent.logger().snapshot().deallocations, 1);
+        assert!(alloc.allocate(0u64).is_ok());
+        assert!(alloc.allocate([0u8; 2000]).is_ok());
+    }
+
+    #[test]
+    fn usable_size() {
+        let alloc = Bucketizer::new(&[32, 128, 512, 2048], 1).unwrap();
+        assert_eq!(alloc.usable_size(1, 1), 32);
+        assert_eq!(alloc.usable_size(33, 8), 128);
+        assert_eq!(alloc.usable_size(2048, 8), 2048);
+        assert_eq!(alloc.usable_size(5000, 8), 5000);
+        assert!(Bucketizer::new(&[128, 32], 1).is_err());
+    }
+
+    #[test]
+    fn realloc_between_buckets() {
+        let alloc = Bucketizer::new(&[32, 128], 1).unwrap();
+        unsafe {
+            let block = alloc.allocate_raw(20, 8).unwrap();
+            *block.ptr() = 42;
+            let ptr = block.ptr();
+
+            let block = alloc.reallocate_raw(block, 32).ok().unwrap();
+            assert_eq!(block.ptr(), ptr);
+
+            let block = alloc.reallocate_raw(block, 100).ok().unwrap();
+            assert!(block.ptr() != ptr);
+            let block = alloc.reallocate_raw(block, 1000).ok().unwrap();
+            assert_eq!(*block.ptr(), 42);
+            alloc.deallocate_raw(block);
+        }
+    }
+}
@@ -0,0 +1,324 @@
+//! A buddy allocator.
+
+use std::cell::Cell;
+use std::cmp;
+use std::mem;
+use std::ptr;
+
+use super::{Allocator, Error, Block, BlockOwner, HeapAllocator, HEAP};
+
+// the header written into every free block.
+struct FreeNode {
+    next: *mut FreeNode,
+}
+
+// the size of the smallest blocks, at order 0.
+#[inline]
+fn min_size() -> usize {
+    mem::size_of::<FreeNode>()
+}
+
+// the largest alignment the buffer is allocated with.
+const MAX_BUFFER_ALIGN: usize = 4096;
+
+/// A `BuddyAllocator` manages a buffer whose size is a power of two.
+///
+/// The buffer is split into blocks whose sizes are powers of two as well, the order of a
+/// block being the number of times the smallest block size has to be doubled to reach it.
+/// Every request is rounded up to the next order. A larger free block is split in halves
+/// ("buddies") until one of the right order is made. When a block is freed and its buddy
+/// is free too, they are merged back into the larger block, so fragmentation stays bounded.
+///
+/// Blocks are aligned to their size, up to a page.
+pub struct BuddyAllocator<'a, A: 'a + Allocator> {
+    alloc: &'a A,
+    start: *mut u8,
+    size: usize,
+    // the free blocks of each order.
+    free_lists: Vec<Cell<*mut FreeNode>>,
+}
+
+impl BuddyAllocator<'static, HeapAllocator> {
+    /// Creates a new `BuddyAllocator` backed by `size` bytes from the heap.
+    /// `size` must be a power of two, and at least the size of a pointer.
+    pub fn new(size: usize) -> Result<Self, Error> {
+        BuddyAllocator::new_from(HEAP, size)
+    }
+}
+
+impl<'a, A: 'a + Allocator> BuddyAllocator<'a, A> {
+    /// Creates a new `BuddyAllocator` backed by `size` bytes from the allocator supplied.
+    /// `size` must be a power of two, and at least the size of a pointer.
+    pub fn new_from(alloc: &'a A, size: usize) -> Result<Self, Error> {
+        if !size.is_power_of_two() || size < min_size() {
+            return Err(Error::AllocatorSpecific("Buffer size must be a power of two, \
+                                                 and at least the size of a pointer."
+                                                    .into()));
+        }
+
+        let block = match unsafe { alloc.allocate_raw(size, cmp::min(size, MAX_BUFFER_ALIGN)) } {
+            Ok(block) => block,
+            Err(err) => return Err(err),
+        };
+
+        let orders = (size / min_size()).trailing_zeros() as usize + 1;
+        let allocator = BuddyAllocator {
+            alloc: alloc,
+            start: block.ptr(),
+            size: size,
+            free_lists: (0..orders).map(|_| Cell::new(ptr::null_mut())).collect(),
+        };
+
+        // the whole buffer starts out as a single free block.
+        unsafe { allocator.push(orders - 1, block.ptr()) };
+        Ok(allocator)
+    }
+
+    /// The order of the largest block, which spans the entire buffer.
+    pub fn max_order(&self) -> usize {
+        self.free_lists.len() - 1
+    }
+
+    /// The order of the block serving a request of the size and alignment given,
+    /// if any block is large enough.
+    pub fn order_of(&self, size: usize, align: usize) -> Option<usize> {
+        let size = cmp::max(cmp::max(size, align), min_size());
+        if size > self.size {
+            return None;
+        }
+
+        Some((size.next_power_of_two() / min_size()).trailing_zeros() as usize)
+    }
+
+    /// The number of free blocks of the given order.
+    pub fn free_blocks(&self, order: usize) -> usize {
+        let mut count = 0;
+        let mut node = self.free_lists[order].get();
+        while !node.is_null() {
+            count += 1;
+            node = unsafe { (*node).next };
+        }
+        count
+    }
+
+    fn block_size(order: usize) -> usize {
+        min_size() << order
+    }
+
+    unsafe fn push(&self, order: usize, ptr: *mut u8) {
+        let node = ptr as *mut FreeNode;
+        (*node).next = self.free_lists[order].get();
+        self.free_lists[order].set(node);
+    }
+
+    unsafe fn pop(&self, order: usize) -> Option<*mut u8> {
+        let node = self.free_lists[order].get();
+        if node.is_null() {
+            None
+        } else {
+            self.free_lists[order].set((*node).next);
+            Some(node as *mut u8)
+        }
+    }
+
+    // removes a block from a free list, returning whether it was there.
+    unsafe fn remove(&self, order: usize, ptr: *mut u8) -> bool {
+        let target = ptr as *mut FreeNode;
+        let mut node = self.free_lists[order].get();
+        let mut prev: *mut FreeNode = ptr::null_mut();
+        while !node.is_null() {
+            if node == target {
+                if prev.is_null() {
+                    self.free_lists[order].set((*node).next);
+                } else {
+                    (*prev).next = (*node).next;
+                }
+                return true;
+            }
+            prev = node;
+            node = (*node).next;
+        }
+        false
+    }
+
+    // the buddy of a block of the given order.
+    fn buddy_of(&self, ptr: *mut u8, order: usize) -> *mut u8 {
+        let offset = ptr as usize - self.start as usize;
+        (self.start as usize + (offset ^ Self::block_size(order))) as *mut u8
+    }
+
+    // frees a block, merging it with its buddy as long as that is free.
+    unsafe fn release(&self, ptr: *mut u8, order: usize) {
+        let mut ptr = ptr;
+        let mut order = order;
+        while order < self.max_order() {
+            let buddy = self.buddy_of(ptr, order);
+            if !self.remove(order, buddy) {
+                break;
+            }
+            ptr = cmp::min(ptr, buddy);
+            order += 1;
+        }
+        self.push(order, ptr);
+    }
+}
+
+unsafe impl<'a, A: 'a + Allocator> Allocator for BuddyAllocator<'a, A> {
+    unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        if size == 0 {
+            return Ok(Block::empty());
+        }
+
+        if align > cmp::min(self.size, MAX_BUFFER_ALIGN) {
+            return Err(Error::UnsupportedAlignment);
+        }
+
+        let order = match self.order_of(size, align) {
+            Some(order) => order,
+            None => return Err(Error::OutOfMemory),
+        };
+
+        // find the smallest free block large enough, and split it down to size.
+        for larger in order..self.free_lists.len() {
+            if let Some(ptr) = self.pop(larger) {
+                for split in (order..larger).rev() {
+                    self.push(split, ptr.offset(Self::block_size(split) as isize));
+                }
+                return Ok(Block::new(ptr, size, align));
+            }
+        }
+
+        Err(Error::OutOfMemory)
+    }
+
+    unsafe fn reallocate_raw<'b>(&'b self, block: Block<'b>, new_size: usize) -> Result<Block<'b>, (Error, Block<'b>)> {
+        if new_size == 0 {
+            self.deallocate_raw(block);
+            return Ok(Block::empty());
+        } else if block.is_empty() {
+            return Err((Error::UnsupportedAlignment, block));
+        }
+
+        let order = self.order_of(block.size(), block.align()).unwrap();
+        match self.order_of(new_size, block.align()) {
+            Some(new_order) if new_order <= order => {
+                // give back the upper halves no longer needed. Their buddies
+                // are still in use, so they can't be merged.
+                for split in new_order..order {
+                    self.push(split, block.ptr().offset(Self::block_size(split) as isize));
+                }
+                Ok(Block::new(block.ptr(), new_size, block.align()))
+            }
+            _ => {
+                match self.allocate_raw(new_size, block.align()) {
+                    Ok(new_block) => {
+                        ptr::copy_nonoverlapping(block.ptr(), new_block.ptr(), block.size());
+                        self.deallocate_raw(block);
+                        Ok(new_block)
+                    }
+                    Err(err) => Err((err, block)),
+                }
+            }
+        }
+    }
+
+    unsafe fn deallocate_raw(&self, block: Block) {
+        if block.is_empty() {
+            return;
+        }
+
+        if !self.owns_block(&block) {
+            debug_assert!(false, "Attempted to deallocate a block not owned by this BuddyAllocator.");
+            return;
+        }
+
+        let order = self.order_of(block.size(), block.align()).unwrap();
+        self.release(block.ptr(), order);
+    }
+
+    fn usable_size(&self, size: usize, align: usize) -> usize {
+        match self.order_of(size, align) {
+            Some(order) => Self::block_size(order),
+            None => size,
+        }
+    }
+}
+
+impl<'a, A: 'a + Allocator> BlockOwner for BuddyAllocator<'a, A> {
+    fn owns_block(&self, block: &Block) -> bool {
+        let ptr = block.ptr() as usize;
+        let start = self.start as usize;
+
+        ptr >= start && ptr + block.size() <= start + self.size
+    }
+}
+
+impl<'a, A: 'a + Allocator> Drop for BuddyAllocator<'a, A> {
+    fn drop(&mut self) {
+        unsafe {
+            self.alloc.deallocate_raw(Block::new(self.start, self.size, cmp::min(self.size, MAX_BUFFER_ALIGN)));
+        }
+    }
+}
+
+unsafe impl<'a, A: 'a + Allocator + Sync> Send for BuddyAllocator<'a, A> {}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    #[test]
+    fn split_merge() {
+        let alloc = BuddyAllocator::new(1024).unwrap();
+        let order = alloc.order_of(64, 8).unwrap();
+
+        let blocks: Vec<_> = (0..16).map(|_| unsafe { alloc.allocate_raw(64, 8).unwrap() }).collect();
+        assert!(unsafe { alloc.allocate_raw(64, 8) }.is_err());
+        assert_eq!(alloc.free_blocks(order), 0);
+
+        // every block is aligned to its size.
+        for block in &blocks {
+            assert_eq!(block.ptr() as usize % 64, 0);
+        }
+
+        for block in blocks {
+            unsafe { alloc.deallocate_raw(block) };
+        }
+
+        // all the buddies merged back into one block.
+        assert_eq!(alloc.free_blocks(alloc.max_order()), 1);
+        let block = unsafe { alloc.allocate_raw(1024, 8).unwrap() };
+        unsafe { alloc.deallocate_raw(block) };
+    }
+
+    #[test]
+    fn rounding() {
+        let alloc = BuddyAllocator::new(256).unwrap();
+        assert!(BuddyAllocator::new(100).is_err());
+        assert_eq!(alloc.order_of(256, 1), Some(alloc.max_order()));
+        assert_eq!(alloc.order_of(257, 1), None);
+
+        assert_eq!(alloc.usable_size(100, 1), 128);
+        let a = alloc.allocate([0u8; 100]).unwrap();
+        // 100 bytes take a 128 byte block, leaving the other half.
+        assert!(alloc.allocate([0u8; 200]).is_err());
+        let b = alloc.allocate([0u8; 128]).unwrap();
+        assert!(alloc.owns(&a) && alloc.owns(&b));
+    }
+
+    #[test]
+    fn realloc() {
+        let alloc = BuddyAllocator::new(256).unwrap();
+        unsafe {
+            let block = alloc.allocate_raw(200, 8).unwrap();
+            // shrinking gives back the upper halves.
+            let block = alloc.reallocate_raw(block, 16).ok().unwrap();
+            let other = alloc.allocate_raw(128, 8).unwrap();
+
+            let block = alloc.reallocate_raw(block, 64).ok().unwrap();
+            alloc.deallocate_raw(block);
+            alloc.deallocate_raw(other);
+            assert_eq!(alloc.free_blocks(alloc.max_order()), 1);
+        }
+    }
+}
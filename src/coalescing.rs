@@ -0,0 +1,346 @@
+//! A coalescing best-fit allocator.
+
+use std::cell::Cell;
+use std::cmp;
+use std::mem;
+use std::ptr;
+
+use super::{Allocator, Error, Block, BlockOwner, HeapAllocator, HEAP};
+
+// A header written into the start of every free span.
+// Free spans are kept in a doubly-linked list sorted by address.
+struct FreeSpan {
+    size: usize,
+    prev: *mut FreeSpan,
+    next: *mut FreeSpan,
+}
+
+// Every span starts and ends on a multiple of this from the start of the buffer,
+// so a free span always has room for its header.
+#[inline]
+fn unit() -> usize {
+    mem::size_of::<FreeSpan>().next_power_of_two()
+}
+
+#[inline]
+fn round_up(size: usize, align: usize) -> Option<usize> {
+    size.checked_add(align - 1).map(|size| size & !(align - 1))
+}
+
+/// A `CoalescingAllocator` manages a single contiguous buffer, serving requests of
+/// arbitrary size and alignment from it.
+///
+/// Each request is placed in the smallest free span that can hold it, and the rest of
+/// the span is split off. When a block is deallocated, it is merged with any free spans
+/// adjacent to it, so the buffer does not fragment permanently.
+/// Sizes are rounded up to a small granularity (a few words) for bookkeeping.
+pub struct CoalescingAllocator<'a, A: 'a + Allocator> {
+    alloc: &'a A,
+    start: *mut u8,
+    size: usize,
+    free_list: Cell<*mut FreeSpan>,
+}
+
+impl CoalescingAllocator<'static, HeapAllocator> {
+    /// Creates a new `CoalescingAllocator` backed by `size` bytes from the heap.
+    pub fn new(size: usize) -> Result<Self, Error> {
+        CoalescingAllocator::new_from(HEAP, size)
+    }
+}
+
+impl<'a, A: 'a + Allocator> CoalescingAllocator<'a, A> {
+    /// Creates a new `CoalescingAllocator` backed by `size` bytes from the allocator supplied.
+    /// The size is rounded down to the allocator's granularity.
+    pub fn new_from(alloc: &'a A, size: usize) -> Result<Self, Error> {
+        let size = size & !(unit() - 1);
+        let block = match unsafe { alloc.allocate_raw(size, unit()) } {
+            Ok(block) => block,
+            Err(err) => return Err(err),
+        };
+
+        let allocator = CoalescingAllocator {
+            alloc: alloc,
+            start: block.ptr(),
+            size: size,
+            free_list: Cell::new(ptr::null_mut()),
+        };
+
+        if size > 0 {
+            unsafe { allocator.insert_free(block.ptr(), size) };
+        }
+
+        Ok(allocator)
+    }
+
+    // Adds a span to the free list, merging it with its neighbors if they are free.
+    unsafe fn insert_free(&self, ptr: *mut u8, size: usize) {
+        let span = ptr as *mut FreeSpan;
+
+        // find the free spans on either side.
+        let mut prev: *mut FreeSpan = ptr::null_mut();
+        let mut next = self.free_list.get();
+        while !next.is_null() && next < span {
+            prev = next;
+            next = (*next).next;
+        }
+
+        ptr::write(span, FreeSpan {
+            size: size,
+            prev: prev,
+            next: next,
+        });
+
+        if prev.is_null() {
+            self.free_list.set(span);
+        } else {
+            (*prev).next = span;
+        }
+        if !next.is_null() {
+            (*next).prev = span;
+        }
+
+        // merge forwards, then backwards.
+        if !next.is_null() && ptr.offset(size as isize) == next as *mut u8 {
+            (*span).size += (*next).size;
+            self.unlink(next);
+        }
+        if !prev.is_null() && (prev as *mut u8).offset((*prev).size as isize) == ptr {
+            (*prev).size += (*span).size;
+            self.unlink(span);
+        }
+    }
+
+    // Removes a span from the free list.
+    unsafe fn unlink(&self, span: *mut FreeSpan) {
+        let (prev, next) = ((*span).prev, (*span).next);
+        if prev.is_null() {
+            self.free_list.set(next);
+        } else {
+            (*prev).next = next;
+        }
+        if !next.is_null() {
+            (*next).prev = prev;
+        }
+    }
+
+    // Carves `[ptr, ptr + size)` out of the free span supplied,
+    // putting whatever is left on either side back on the free list.
+    unsafe fn take(&self, span: *mut FreeSpan, ptr: *mut u8, size: usize) {
+        let span_start = span as *mut u8;
+        let span_size = (*span).size;
+        self.unlink(span);
+
+        let front = ptr as usize - span_start as usize;
+        if front > 0 {
+            self.insert_free(span_start, front);
+        }
+
+        let back = span_size - front - size;
+        if back > 0 {
+            self.insert_free(ptr.offset(size as isize), back);
+        }
+    }
+}
+
+unsafe impl<'a, A: 'a + Allocator> Allocator for CoalescingAllocator<'a, A> {
+    unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        if size == 0 {
+            return Ok(Block::empty());
+        }
+
+        let needed = match round_up(size, unit()) {
+            Some(needed) => needed,
+            None => return Err(Error::OutOfMemory),
+        };
+        let span_align = cmp::max(align, unit());
+
+        // find the span that fits with the least left over.
+        let mut best: Option<(*mut FreeSpan, *mut u8, usize)> = None;
+        let mut span = self.free_list.get();
+        while !span.is_null() {
+            if let Some(aligned) = super::align_forward(span as *mut u8, span_align) {
+                let front = aligned as usize - span as usize;
+                if front <= (*span).size && needed <= (*span).size - front {
+                    let left_over = (*span).size - front - needed;
+                    let better = match best {
+                        Some((_, _, best_left_over)) => left_over < best_left_over,
+                        None => true,
+                    };
+                    if better {
+                        best = Some((span, aligned, left_over));
+                    }
+                }
+            }
+            span = (*span).next;
+        }
+
+        match best {
+            Some((span, aligned, _)) => {
+                self.take(span, aligned, needed);
+                Ok(Block::new(aligned, size, align))
+            }
+            None => Err(Error::OutOfMemory),
+        }
+    }
+
+    unsafe fn reallocate_raw<'b>(&'b self, block: Block<'b>, new_size: usize) -> Result<Block<'b>, (Error, Block<'b>)> {
+        if new_size == 0 {
+            self.deallocate_raw(block);
+            return Ok(Block::empty());
+        } else if block.is_empty() {
+            return Err((Error::UnsupportedAlignment, block));
+        }
+
+        let old_needed = round_up(block.size(), unit()).unwrap();
+        let new_needed = match round_up(new_size, unit()) {
+            Some(needed) => needed,
+            None => return Err((Error::OutOfMemory, block)),
+        };
+
+        if new_needed <= old_needed {
+            // shrink in place, freeing the tail.
+            if new_needed < old_needed {
+                self.insert_free(block.ptr().offset(new_needed as isize), old_needed - new_needed);
+            }
+            return Ok(Block::new(block.ptr(), new_size, block.align()));
+        }
+
+        // grow in place if the span right after this block is free and big enough.
+        let end = block.ptr().offset(old_needed as isize);
+        let mut span = self.free_list.get();
+        while !span.is_null() && (span as *mut u8) < end {
+            span = (*span).next;
+        }
+        if span as *mut u8 == end && (*span).size >= new_needed - old_needed {
+            self.take(span, end, new_needed - old_needed);
+            return Ok(Block::new(block.ptr(), new_size, block.align()));
+        }
+
+        // otherwise move it elsewhere.
+        match self.allocate_raw(new_size, block.align()) {
+            Ok(new_block) => {
+                ptr::copy_nonoverlapping(block.ptr(), new_block.ptr(), block.size());
+                self.deallocate_raw(block);
+                Ok(new_block)
+            }
+            Err(err) => Err((err, block)),
+        }
+    }
+
+    unsafe fn deallocate_raw(&self, block: Block) {
+        if block.is_empty() {
+            return;
+        }
+
+        if !self.owns_block(&block) {
+            debug_assert!(false, "Attempted to deallocate a block not owned by this CoalescingAllocator.");
+            return;
+        }
+
+        self.insert_free(block.ptr(), round_up(block.size(), unit()).unwrap());
+    }
+}
+
+impl<'a, A: 'a + Allocator> BlockOwner for CoalescingAllocator<'a, A> {
+    fn owns_block(&self, block: &Block) -> bool {
+        let ptr = block.ptr() as usize;
+        let start = self.start as usize;
+
+        ptr >= start && ptr + block.size() <= start + self.size
+    }
+}
+
+impl<'a, A: 'a + Allocator> Drop for CoalescingAllocator<'a, A> {
+    fn drop(&mut self) {
+        if self.size > 0 {
+            unsafe { self.alloc.deallocate_raw(Block::new(self.start, self.size, unit())) };
+        }
+    }
+}
+
+unsafe impl<'a, A: 'a + Allocator + Sync> Send for CoalescingAllocator<'a, A> {}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    #[test]
+    fn coalescing() {
+        let alloc = CoalescingAllocator::new(768).unwrap();
+        unsafe {
+            let a = alloc.allocate_raw(256, 8).unwrap();
+            let b = alloc.allocate_raw(256, 8).unwrap();
+            let c = alloc.allocate_raw(256, 8).unwrap();
+            assert!(alloc.allocate_raw(1, 1).is_err());
+
+            alloc.deallocate_raw(a);
+            alloc.deallocate_raw(c);
+            // two free spans, but neither is big enough.
+            assert!(alloc.allocate_raw(512, 8).is_err());
+
+            alloc.deallocate_raw(b);
+            let all = alloc.allocate_raw(768, 8).unwrap();
+            alloc.deallocate_raw(all);
+        }
+    }
+
+    #[test]
+    fn best_fit() {
+        let alloc = CoalescingAllocator::new(1024).unwrap();
+        unsafe {
+            let a = alloc.allocate_raw(256, 8).unwrap();
+            let _b = alloc.allocate_raw(64, 8).unwrap();
+            let c = alloc.allocate_raw(128, 8).unwrap();
+            let _d = alloc.allocate_raw(64, 8).unwrap();
+            let (a_ptr, c_ptr) = (a.ptr(), c.ptr());
+            alloc.deallocate_raw(a);
+            alloc.deallocate_raw(c);
+
+            // the 128 byte hole is a better fit than the 256 byte one.
+            assert_eq!(alloc.allocate_raw(100, 8).unwrap().ptr(), c_ptr);
+            assert_eq!(alloc.allocate_raw(200, 8).unwrap().ptr(), a_ptr);
+        }
+    }
+
+    #[test]
+    fn alignment() {
+        let alloc = CoalescingAllocator::new(1024).unwrap();
+        unsafe {
+            let a = alloc.allocate_raw(1, 1).unwrap();
+            let b = alloc.allocate_raw(8, 128).unwrap();
+            assert_eq!(b.ptr() as usize % 128, 0);
+            alloc.deallocate_raw(a);
+            alloc.deallocate_raw(b);
+
+            // everything merged back together.
+            let all = alloc.allocate_raw(1024, 8).unwrap();
+            alloc.deallocate_raw(all);
+        }
+    }
+
+    #[test]
+    fn realloc() {
+        let alloc = CoalescingAllocator::new(1024).unwrap();
+        unsafe {
+            let a = alloc.allocate_raw(64, 8).unwrap();
+            *a.ptr() = 7;
+            let ptr = a.ptr();
+
+            // nothing after it, so it grows in place.
+            let a = alloc.reallocate_raw(a, 512).ok().unwrap();
+            assert_eq!(a.ptr(), ptr);
+
+            let a = alloc.reallocate_raw(a, 64).ok().unwrap();
+            let b = alloc.allocate_raw(64, 8).unwrap();
+            // now it has to move.
+            let a = alloc.reallocate_raw(a, 512).ok().unwrap();
+            assert!(a.ptr() != ptr);
+            assert_eq!(*a.ptr(), 7);
+
+            alloc.deallocate_raw(a);
+            alloc.deallocate_raw(b);
+            let all = alloc.allocate_raw(1024, 8).unwrap();
+            alloc.deallocate_raw(all);
+        }
+    }
+}
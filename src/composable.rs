@@ -1,9 +1,17 @@
 //! This module contains some composable building blocks to build allocator chains.
 
+use std::cell::{Cell, RefCell};
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::ptr;
+use std::sync::{Mutex, MutexGuard};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 use super::{Allocator, Error, Block, BlockOwner};
 
 /// This allocator always fails.
-/// It will panic if you try to deallocate with it.
+/// It will panic if you try to deallocate a non-empty block with it.
+#[derive(Debug, Default)]
 pub struct NullAllocator;
 
 unsafe impl Allocator for NullAllocator {
@@ -15,8 +23,11 @@ unsafe impl Allocator for NullAllocator {
         Err((Error::OutOfMemory, block))
     }
 
-    unsafe fn deallocate_raw(&self, _block: Block) {
-        panic!("Attempted to deallocate using null allocator.")
+    unsafe fn deallocate_raw(&self, block: Block) {
+        // empty blocks could have come from anywhere, and need no freeing.
+        if !block.is_empty() {
+            panic!("Attempted to deallocate using null allocator.")
+        }
     }
 }
 
@@ -29,9 +40,30 @@ impl BlockOwner for NullAllocator {
 /// This allocator has a main and a fallback allocator.
 /// It will always attempt to allocate first with the main allocator,
 /// and second with the fallback.
+/// A block from the main allocator which can't grow there is moved to the fallback.
+///
+/// Allocators which only check address ranges may both claim to own a block,
+/// e.g. when the fallback's memory comes out of the main allocator.
+/// The addresses of such blocks served by the fallback are kept in a set, so that
+/// they are given back to it. Each of them costs a lock and room in the set,
+/// which lives on the heap; blocks only one of the two allocators claims
+/// cost nothing more than asking both whether they own them.
 pub struct Fallback<M: BlockOwner, F: BlockOwner> {
     main: M,
     fallback: F,
+    // the live blocks served by the fallback allocator which the main allocator claims too.
+    fallback_blocks: Mutex<HashSet<usize>>,
+    main_hits: AtomicUsize,
+    fallback_hits: AtomicUsize,
+}
+
+/// Which allocator of a `Fallback` a block came from.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FallbackSource {
+    /// The main allocator.
+    Main,
+    /// The fallback allocator.
+    Fallback,
 }
 
 impl<M: BlockOwner, F: BlockOwner> Fallback<M, F> {
@@ -40,6 +72,57 @@ impl<M: BlockOwner, F: BlockOwner> Fallback<M, F> {
         Fallback {
             main: main,
             fallback: fallback,
+            fallback_blocks: Mutex::new(HashSet::new()),
+            main_hits: AtomicUsize::new(0),
+            fallback_hits: AtomicUsize::new(0),
+        }
+    }
+
+    /// Which of the two allocators owns the block, if either.
+    pub fn served_by(&self, block: &Block) -> Option<FallbackSource> {
+        match (self.main.owns_block(block), self.fallback.owns_block(block)) {
+            (true, true) => {
+                if self.fallback_blocks().contains(&(block.ptr() as usize)) {
+                    Some(FallbackSource::Fallback)
+                } else {
+                    Some(FallbackSource::Main)
+                }
+            }
+            (true, false) => Some(FallbackSource::Main),
+            (false, true) => Some(FallbackSource::Fallback),
+            (false, false) => None,
+        }
+    }
+
+    /// The number of allocations served by the main allocator.
+    pub fn main_hits(&self) -> usize {
+        self.main_hits.load(Ordering::Relaxed)
+    }
+
+    /// The number of allocations served by the fallback allocator.
+    pub fn fallback_hits(&self) -> usize {
+        self.fallback_hits.load(Ordering::Relaxed)
+    }
+
+    // the set is only ever changed outside of any code which could panic,
+    // so a poisoned lock still guards a consistent set.
+    fn fallback_blocks(&self) -> MutexGuard<HashSet<usize>> {
+        match self.fallback_blocks.lock() {
+            Ok(blocks) => blocks,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    // records a block served by the fallback allocator, if the main allocator would claim it.
+    fn track(&self, block: &Block) {
+        if !block.is_empty() && self.main.owns_block(block) {
+            self.fallback_blocks().insert(block.ptr() as usize);
+        }
+    }
+
+    fn untrack(&self, block: &Block) {
+        if self.main.owns_block(block) {
+            self.fallback_blocks().remove(&(block.ptr() as usize));
         }
     }
 }
@@ -47,33 +130,750 @@ impl<M: BlockOwner, F: BlockOwner> Fallback<M, F> {
 unsafe impl<M: BlockOwner, F: BlockOwner> Allocator for Fallback<M, F> {
     unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
         match self.main.allocate_raw(size, align) {
-            Ok(block) => Ok(block),
-            Err(_) => self.fallback.allocate_raw(size, align),
+            Ok(block) => {
+                self.main_hits.fetch_add(1, Ordering::Relaxed);
+                Ok(block)
+            }
+            Err(_) => {
+                let result = self.fallback.allocate_raw(size, align);
+                if let Ok(ref block) = result {
+                    self.fallback_hits.fetch_add(1, Ordering::Relaxed);
+                    self.track(block);
+                }
+                result
+            }
         }
     }
 
     unsafe fn reallocate_raw<'a>(&'a self, block: Block<'a>, new_size: usize) -> Result<Block<'a>, (Error, Block<'a>)> {
-        if self.main.owns_block(&block) {
-            self.main.reallocate_raw(block, new_size)
-        } else if self.fallback.owns_block(&block) {
-            self.fallback.reallocate_raw(block, new_size)
-        } else {
-            Err((Error::AllocatorSpecific("Neither fallback nor main owns this block.".into()), block))
+        match self.served_by(&block) {
+            Some(FallbackSource::Main) => {
+                let block = match self.main.reallocate_raw(block, new_size) {
+                    Ok(block) => return Ok(block),
+                    // only a block that outgrew the main allocator is moved.
+                    Err((err, block)) => {
+                        if new_size <= block.size() {
+                            return Err((err, block));
+                        }
+                        block
+                    }
+                };
+
+                match self.fallback.allocate_raw(new_size, block.align()) {
+                    Ok(new_block) => {
+                        self.fallback_hits.fetch_add(1, Ordering::Relaxed);
+                        self.track(&new_block);
+                        ptr::copy_nonoverlapping(block.ptr(), new_block.ptr(), block.size());
+                        self.main.deallocate_raw(block);
+                        Ok(new_block)
+                    }
+                    Err(err) => Err((err, block)),
+                }
+            }
+            Some(FallbackSource::Fallback) => {
+                let old_block = Block::new(block.ptr(), block.size(), block.align());
+                match self.fallback.reallocate_raw(block, new_size) {
+                    Ok(new_block) => {
+                        self.untrack(&old_block);
+                        self.track(&new_block);
+                        Ok(new_block)
+                    }
+                    Err(err) => Err(err),
+                }
+            }
+            None => Err((Error::NotOwned, block)),
         }
     }
 
     unsafe fn deallocate_raw(&self, block: Block) {
-        if self.main.owns_block(&block) {
-            self.main.deallocate_raw(block);
-        } else if self.fallback.owns_block(&block) {
-            self.fallback.deallocate_raw(block);
+        match self.served_by(&block) {
+            Some(FallbackSource::Main) => self.main.deallocate_raw(block),
+            Some(FallbackSource::Fallback) => {
+                self.untrack(&block);
+                self.fallback.deallocate_raw(block)
+            }
+            None => {}
         }
     }
 }
 
+impl<M: BlockOwner + Default, F: BlockOwner + Default> Default for Fallback<M, F> {
+    fn default() -> Self {
+        Fallback::new(M::default(), F::default())
+    }
+}
+
 impl<M: BlockOwner, F: BlockOwner> BlockOwner for Fallback<M, F> {
     fn owns_block(&self, block: &Block) -> bool {
-        self.main.owns_block(block) || self.fallback.owns_block(block)
+        self.served_by(block).is_some()
+    }
+}
+
+/// A chain of any number of allocators. Each allocation is attempted
+/// with every allocator in order, until one succeeds.
+/// Reallocations and deallocations go to the first allocator in the chain
+/// which owns the block.
+pub struct FallbackChain<'a> {
+    allocators: Vec<Box<BlockOwner + 'a>>,
+}
+
+impl<'a> FallbackChain<'a> {
+    /// Create a new, empty `FallbackChain`. It fails every allocation
+    /// until allocators are added to it.
+    pub fn new() -> Self {
+        FallbackChain { allocators: Vec::new() }
+    }
+
+    /// Adds an allocator to the end of the chain.
+    pub fn push<A: BlockOwner + 'a>(&mut self, alloc: A) {
+        self.allocators.push(Box::new(alloc));
+    }
+
+    /// Adds an allocator to the end of the chain, returning the chain.
+    pub fn with<A: BlockOwner + 'a>(mut self, alloc: A) -> Self {
+        self.push(alloc);
+        self
+    }
+
+    /// The number of allocators in the chain.
+    pub fn len(&self) -> usize {
+        self.allocators.len()
+    }
+
+    /// Whether the chain has no allocators, and so can't serve any request.
+    pub fn is_empty(&self) -> bool {
+        self.allocators.is_empty()
+    }
+
+    fn owner_of(&self, block: &Block) -> Option<&(BlockOwner + 'a)> {
+        self.allocators.iter().map(|alloc| &**alloc).find(|alloc| alloc.owns_block(block))
+    }
+}
+
+unsafe impl<'a> Allocator for FallbackChain<'a> {
+    unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        let mut last_err = Error::OutOfMemory;
+        for alloc in &self.allocators {
+            match alloc.allocate_raw(size, align) {
+                Ok(block) => return Ok(block),
+                Err(err) => last_err = err,
+            }
+        }
+
+        Err(last_err)
+    }
+
+    unsafe fn reallocate_raw<'b>(&'b self, block: Block<'b>, new_size: usize) -> Result<Block<'b>, (Error, Block<'b>)> {
+        match self.owner_of(&block) {
+            Some(alloc) => alloc.reallocate_raw(block, new_size),
+            None => Err((Error::NotOwned, block)),
+        }
+    }
+
+    unsafe fn deallocate_raw(&self, block: Block) {
+        if let Some(alloc) = self.owner_of(&block) {
+            alloc.deallocate_raw(block);
+        }
+    }
+}
+
+impl<'a> Default for FallbackChain<'a> {
+    fn default() -> Self {
+        FallbackChain::new()
+    }
+}
+
+impl<'a> BlockOwner for FallbackChain<'a> {
+    fn owns_block(&self, block: &Block) -> bool {
+        self.owner_of(block).is_some()
+    }
+}
+
+/// This allocator splits requests between two allocators by size.
+/// Requests of up to `threshold` bytes go to the small allocator,
+/// and larger ones go to the large allocator.
+pub struct Segregator<S: BlockOwner, L: BlockOwner> {
+    threshold: usize,
+    small: S,
+    large: L,
+}
+
+impl<S: BlockOwner, L: BlockOwner> Segregator<S, L> {
+    /// Create a new `Segregator`.
+    pub fn new(threshold: usize, small: S, large: L) -> Self {
+        Segregator {
+            threshold: threshold,
+            small: small,
+            large: large,
+        }
+    }
+
+    /// The largest size of request served by the small allocator.
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+}
+
+unsafe impl<S: BlockOwner, L: BlockOwner> Allocator for Segregator<S, L> {
+    unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        if size <= self.threshold {
+            self.small.allocate_raw(size, align)
+        } else {
+            self.large.allocate_raw(size, align)
+        }
+    }
+
+    unsafe fn reallocate_raw<'a>(&'a self, block: Block<'a>, new_size: usize) -> Result<Block<'a>, (Error, Block<'a>)> {
+        if new_size == 0 {
+            self.deallocate_raw(block);
+            return Ok(Block::empty());
+        } else if block.is_empty() {
+            return Err((Error::UnsupportedAlignment, block));
+        }
+
+        let in_small = self.small.owns_block(&block);
+        if !in_small && !self.large.owns_block(&block) {
+            return Err((Error::NotOwned, block));
+        }
+
+        // stay with the same allocator as long as the new size belongs there.
+        if in_small == (new_size <= self.threshold) {
+            return if in_small {
+                self.small.reallocate_raw(block, new_size)
+            } else {
+                self.large.reallocate_raw(block, new_size)
+            };
+        }
+
+        // otherwise, move the block across.
+        match self.allocate_raw(new_size, block.align()) {
+            Ok(new_block) => {
+                ptr::copy_nonoverlapping(block.ptr(),
+                                         new_block.ptr(),
+                                         cmp::min(block.size(), new_size));
+                self.deallocate_raw(block);
+                Ok(new_block)
+            }
+            Err(err) => Err((err, block)),
+        }
+    }
+
+    unsafe fn deallocate_raw(&self, block: Block) {
+        if self.small.owns_block(&block) {
+            self.small.deallocate_raw(block);
+        } else if self.large.owns_block(&block) {
+            self.large.deallocate_raw(block);
+        }
+    }
+}
+
+impl<S: BlockOwner, L: BlockOwner> BlockOwner for Segregator<S, L> {
+    fn owns_block(&self, block: &Block) -> bool {
+        self.small.owns_block(block) || self.large.owns_block(block)
+    }
+}
+
+/// This wraps an allocator, capping the total number of bytes
+/// it may have allocated at once.
+pub struct Quota<A: Allocator> {
+    inner: A,
+    limit: usize,
+    used: Cell<usize>,
+}
+
+impl<A: Allocator> Quota<A> {
+    /// Create a new `Quota` which allows up to `limit` bytes to be allocated from `inner`.
+    pub fn new(inner: A, limit: usize) -> Self {
+        Quota {
+            inner: inner,
+            limit: limit,
+            used: Cell::new(0),
+        }
+    }
+
+    /// The number of bytes currently allocated.
+    pub fn used(&self) -> usize {
+        self.used.get()
+    }
+
+    /// The maximum number of bytes which may be allocated at once.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// The number of bytes which may still be allocated.
+    pub fn remaining(&self) -> usize {
+        self.limit - self.used.get()
+    }
+
+    // whether a block may be resized to `new_size` without going over the limit.
+    fn fits(&self, block: &Block, new_size: usize) -> bool {
+        new_size <= block.size() || new_size - block.size() <= self.remaining()
+    }
+
+    // accounts for a resize which went through.
+    fn resized(&self, old_size: usize, new_block: &Block) {
+        self.used.set(self.used.get() - old_size + new_block.size());
+    }
+
+    // accounts for a new block, if the inner allocator handed one out.
+    fn charge<'a>(&self, result: Result<Block<'a>, Error>) -> Result<Block<'a>, Error> {
+        if let Ok(ref block) = result {
+            self.used.set(self.used.get() + block.size());
+        }
+        result
+    }
+}
+
+unsafe impl<A: Allocator> Allocator for Quota<A> {
+    unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        if size > self.remaining() {
+            return Err(Error::OutOfMemory);
+        }
+
+        self.charge(self.inner.allocate_raw(size, align))
+    }
+
+    unsafe fn allocate_zeroed_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        if size > self.remaining() {
+            return Err(Error::OutOfMemory);
+        }
+
+        self.charge(self.inner.allocate_zeroed_raw(size, align))
+    }
+
+    /// The excess counts against the limit as well. If it would go over,
+    /// the block is given back, and exactly `size` bytes are allocated instead.
+    unsafe fn allocate_excess_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        if size > self.remaining() {
+            return Err(Error::OutOfMemory);
+        }
+
+        match self.inner.allocate_excess_raw(size, align) {
+            Ok(block) => {
+                if block.size() > self.remaining() {
+                    self.inner.deallocate_raw(block);
+                    self.allocate_raw(size, align)
+                } else {
+                    self.charge(Ok(block))
+                }
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// A block may only grow by as many bytes as remain under the limit.
+    /// Larger requests fail without reaching the inner allocator.
+    unsafe fn reallocate_raw<'a>(&'a self, block: Block<'a>, new_size: usize) -> Result<Block<'a>, (Error, Block<'a>)> {
+        if !self.fits(&block, new_size) {
+            return Err((Error::OutOfMemory, block));
+        }
+
+        let old_size = block.size();
+        match self.inner.reallocate_raw(block, new_size) {
+            Ok(new_block) => {
+                self.resized(old_size, &new_block);
+                Ok(new_block)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    unsafe fn deallocate_raw(&self, block: Block) {
+        self.used.set(self.used.get() - block.size());
+        self.inner.deallocate_raw(block);
+    }
+
+    fn usable_size(&self, size: usize, align: usize) -> usize {
+        self.inner.usable_size(size, align)
+    }
+
+    unsafe fn grow_in_place<'a>(&'a self, block: Block<'a>, new_size: usize) -> Result<Block<'a>, (Error, Block<'a>)> {
+        if !self.fits(&block, new_size) {
+            return Err((Error::OutOfMemory, block));
+        }
+
+        let old_size = block.size();
+        match self.inner.grow_in_place(block, new_size) {
+            Ok(new_block) => {
+                self.resized(old_size, &new_block);
+                Ok(new_block)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    unsafe fn shrink_in_place<'a>(&'a self, block: Block<'a>, new_size: usize) -> Result<Block<'a>, (Error, Block<'a>)> {
+        let old_size = block.size();
+        match self.inner.shrink_in_place(block, new_size) {
+            Ok(new_block) => {
+                self.resized(old_size, &new_block);
+                Ok(new_block)
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl<A: BlockOwner> BlockOwner for Quota<A> {
+    fn owns_block(&self, block: &Block) -> bool {
+        self.inner.owns_block(block)
+    }
+}
+
+/// This wraps an allocator, zeroing the memory of every block it hands out.
+/// When a block grows, the new bytes are zeroed as well.
+pub struct Zeroing<A> {
+    inner: A,
+}
+
+impl<A: Allocator> Zeroing<A> {
+    /// Create a new `Zeroing` allocator.
+    pub fn new(inner: A) -> Self {
+        Zeroing { inner: inner }
+    }
+}
+
+unsafe impl<A: Allocator> Allocator for Zeroing<A> {
+    unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        match self.inner.allocate_raw(size, align) {
+            Ok(block) => {
+                if !block.is_empty() {
+                    ptr::write_bytes(block.ptr(), 0, block.size());
+                }
+                Ok(block)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    unsafe fn allocate_zeroed_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        self.inner.allocate_zeroed_raw(size, align)
+    }
+
+    unsafe fn reallocate_raw<'a>(&'a self, block: Block<'a>, new_size: usize) -> Result<Block<'a>, (Error, Block<'a>)> {
+        let old_size = block.size();
+        match self.inner.reallocate_raw(block, new_size) {
+            Ok(new_block) => {
+                if new_block.size() > old_size {
+                    ptr::write_bytes(new_block.ptr().offset(old_size as isize),
+                                     0,
+                                     new_block.size() - old_size);
+                }
+                Ok(new_block)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    unsafe fn deallocate_raw(&self, block: Block) {
+        self.inner.deallocate_raw(block);
+    }
+}
+
+impl<A: BlockOwner> BlockOwner for Zeroing<A> {
+    fn owns_block(&self, block: &Block) -> bool {
+        self.inner.owns_block(block)
+    }
+}
+
+/// The byte `Poison` fills deallocated memory with.
+pub const POISON_FREED: u8 = 0xDD;
+/// The byte `Poison` fills newly allocated memory with.
+pub const POISON_ALLOCATED: u8 = 0xCD;
+
+/// This wraps an allocator, filling every block with `POISON_FREED`
+/// when it is deallocated, so uses after free show up as garbage instead of
+/// stale data. It can also fill new blocks with `POISON_ALLOCATED`, to expose reads of
+/// uninitialized memory.
+///
+/// Growing a block always moves it, so that the old location gets poisoned.
+/// Shrinking poisons the freed tail if the inner allocator can shrink in place,
+/// and otherwise moves the block as well.
+pub struct Poison<A> {
+    inner: A,
+    fill_allocations: bool,
+}
+
+impl<A: Allocator> Poison<A> {
+    /// Create a new `Poison` allocator. If `fill_allocations` is true,
+    /// new blocks are filled with `POISON_ALLOCATED`.
+    pub fn new(inner: A, fill_allocations: bool) -> Self {
+        Poison {
+            inner: inner,
+            fill_allocations: fill_allocations,
+        }
+    }
+}
+
+unsafe impl<A: Allocator> Allocator for Poison<A> {
+    unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        match self.inner.allocate_raw(size, align) {
+            Ok(block) => {
+                if self.fill_allocations && !block.is_empty() {
+                    ptr::write_bytes(block.ptr(), POISON_ALLOCATED, block.size());
+                }
+                Ok(block)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    unsafe fn reallocate_raw<'a>(&'a self, block: Block<'a>, new_size: usize) -> Result<Block<'a>, (Error, Block<'a>)> {
+        if new_size == 0 {
+            self.deallocate_raw(block);
+            Ok(Block::empty())
+        } else if block.is_empty() {
+            Err((Error::UnsupportedAlignment, block))
+        } else {
+            let old_size = block.size();
+            let block = if new_size <= old_size {
+                // the tail is only poisoned once the inner allocator has let go of it.
+                match self.inner.shrink_in_place(block, new_size) {
+                    Ok(new_block) => {
+                        ptr::write_bytes(new_block.ptr().offset(new_size as isize),
+                                         POISON_FREED,
+                                         old_size - new_size);
+                        return Ok(new_block);
+                    }
+                    Err((_, block)) => block,
+                }
+            } else {
+                block
+            };
+
+            match self.allocate_raw(new_size, block.align()) {
+                Ok(new_block) => {
+                    ptr::copy_nonoverlapping(block.ptr(), new_block.ptr(), cmp::min(old_size, new_size));
+                    self.deallocate_raw(block);
+                    Ok(new_block)
+                }
+                Err(err) => Err((err, block)),
+            }
+        }
+    }
+
+    unsafe fn deallocate_raw(&self, block: Block) {
+        if !block.is_empty() {
+            ptr::write_bytes(block.ptr(), POISON_FREED, block.size());
+        }
+        self.inner.deallocate_raw(block);
+    }
+}
+
+impl<A: BlockOwner> BlockOwner for Poison<A> {
+    fn owns_block(&self, block: &Block) -> bool {
+        self.inner.owns_block(block)
+    }
+}
+
+/// How a `Quantizer` rounds request sizes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Quantum {
+    /// Round up to a multiple of the given power of two.
+    Multiple(usize),
+    /// Round up to the next power of two.
+    PowerOfTwo,
+}
+
+/// This wraps an allocator, rounding the size of every request up before
+/// passing it on. Blocks of similar sizes then look the same to the inner allocator,
+/// so it can reuse them more readily.
+///
+/// The blocks handed out keep the size requested; the rounded size is only
+/// seen by the inner allocator.
+pub struct Quantizer<A> {
+    inner: A,
+    quantum: Quantum,
+}
+
+impl<A: Allocator> Quantizer<A> {
+    /// Create a new `Quantizer`.
+    ///
+    /// # Panics
+    /// Panics if the quantum is a multiple of something other than a power of two.
+    pub fn new(inner: A, quantum: Quantum) -> Self {
+        if let Quantum::Multiple(granularity) = quantum {
+            assert!(granularity.is_power_of_two(), "Granularity must be a power of two.");
+        }
+
+        Quantizer {
+            inner: inner,
+            quantum: quantum,
+        }
+    }
+
+    /// The size a request of `size` bytes is rounded up to, if it doesn't overflow.
+    pub fn quantize(&self, size: usize) -> Option<usize> {
+        match self.quantum {
+            Quantum::Multiple(granularity) => {
+                size.checked_add(granularity - 1).map(|size| size & !(granularity - 1))
+            }
+            Quantum::PowerOfTwo => size.checked_next_power_of_two(),
+        }
+    }
+
+    // the block the inner allocator knows about.
+    fn quantized<'a>(&self, block: &Block) -> Block<'a> {
+        // the size was quantized once already, so this can't overflow.
+        Block::new(block.ptr(), self.quantize(block.size()).unwrap(), block.align())
+    }
+}
+
+unsafe impl<A: Allocator> Allocator for Quantizer<A> {
+    unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        if size == 0 {
+            return Ok(Block::empty());
+        }
+
+        let quantized = match self.quantize(size) {
+            Some(quantized) => quantized,
+            None => return Err(Error::OutOfMemory),
+        };
+
+        match self.inner.allocate_raw(quantized, align) {
+            Ok(block) => Ok(Block::new(block.ptr(), size, align)),
+            Err(err) => Err(err),
+        }
+    }
+
+    unsafe fn reallocate_raw<'a>(&'a self, block: Block<'a>, new_size: usize) -> Result<Block<'a>, (Error, Block<'a>)> {
+        if new_size == 0 {
+            self.deallocate_raw(block);
+            return Ok(Block::empty());
+        } else if block.is_empty() {
+            return Err((Error::UnsupportedAlignment, block));
+        }
+
+        let quantized = match self.quantize(new_size) {
+            Some(quantized) => quantized,
+            None => return Err((Error::OutOfMemory, block)),
+        };
+
+        // within the same quantum, there's nothing to do.
+        if Some(quantized) == self.quantize(block.size()) {
+            return Ok(Block::new(block.ptr(), new_size, block.align()));
+        }
+
+        let size = block.size();
+        match self.inner.reallocate_raw(self.quantized(&block), quantized) {
+            Ok(new_block) => Ok(Block::new(new_block.ptr(), new_size, new_block.align())),
+            Err((err, old)) => Err((err, Block::new(old.ptr(), size, old.align()))),
+        }
+    }
+
+    unsafe fn deallocate_raw(&self, block: Block) {
+        if !block.is_empty() {
+            self.inner.deallocate_raw(self.quantized(&block));
+        }
+    }
+
+    fn usable_size(&self, size: usize, align: usize) -> usize {
+        match self.quantize(size) {
+            Some(quantized) => self.inner.usable_size(quantized, align),
+            None => size,
+        }
+    }
+}
+
+impl<A: BlockOwner> BlockOwner for Quantizer<A> {
+    fn owns_block(&self, block: &Block) -> bool {
+        !block.is_empty() && self.inner.owns_block(&self.quantized(block))
+    }
+}
+
+/// This wraps two allocators, making every allocation, reallocation and
+/// deallocation from both, and panicking as soon as one of them succeeds
+/// where the other fails. The blocks of the first are handed out.
+///
+/// This is a testing aid, to check that a new allocator behaves like
+/// a known-good one given the same requests. Only the outcomes are compared:
+/// the contents of the second allocator's blocks are left alone.
+pub struct Mirror<A, B> {
+    primary: A,
+    shadow: B,
+    // the address of the shadow block for each live primary block.
+    shadows: RefCell<HashMap<usize, usize>>,
+}
+
+impl<A: Allocator, B: Allocator> Mirror<A, B> {
+    /// Creates a new `Mirror`, handing out blocks from `primary`, checked against `shadow`.
+    pub fn new(primary: A, shadow: B) -> Self {
+        Mirror {
+            primary: primary,
+            shadow: shadow,
+            shadows: RefCell::new(HashMap::new()),
+        }
+    }
+
+    // takes the shadow of a primary block out of the record.
+    fn shadow_of<'a>(&self, block: &Block<'a>) -> Block<'a> {
+        match self.shadows.borrow_mut().remove(&(block.ptr() as usize)) {
+            Some(addr) => Block::new(addr as *mut u8, block.size(), block.align()),
+            None => panic!("Block at {:p} was not allocated by this Mirror.", block.ptr()),
+        }
+    }
+}
+
+unsafe impl<A: Allocator, B: Allocator> Allocator for Mirror<A, B> {
+    unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        match (self.primary.allocate_raw(size, align), self.shadow.allocate_raw(size, align)) {
+            (Ok(block), Ok(shadow)) => {
+                if !block.is_empty() {
+                    self.shadows.borrow_mut().insert(block.ptr() as usize, shadow.ptr() as usize);
+                }
+                Ok(block)
+            }
+            (Err(err), Err(_)) => Err(err),
+            (Ok(_), Err(err)) => {
+                panic!("Mirror diverged: allocating {} bytes aligned to {} failed \
+                        only in the shadow allocator, with {:?}.", size, align, err)
+            }
+            (Err(err), Ok(_)) => {
+                panic!("Mirror diverged: allocating {} bytes aligned to {} failed \
+                        only in the primary allocator, with {:?}.", size, align, err)
+            }
+        }
+    }
+
+    unsafe fn reallocate_raw<'a>(&'a self, block: Block<'a>, new_size: usize) -> Result<Block<'a>, (Error, Block<'a>)> {
+        if block.is_empty() {
+            // an empty block has no alignment to allocate a new one with.
+            return if new_size == 0 { Ok(block) } else { Err((Error::UnsupportedAlignment, block)) };
+        }
+
+        let (size, align) = (block.size(), block.align());
+        let shadow = self.shadow_of(&block);
+        match (self.primary.reallocate_raw(block, new_size), self.shadow.reallocate_raw(shadow, new_size)) {
+            (Ok(block), Ok(shadow)) => {
+                if !block.is_empty() {
+                    self.shadows.borrow_mut().insert(block.ptr() as usize, shadow.ptr() as usize);
+                }
+                Ok(block)
+            }
+            (Err((err, block)), Err((_, shadow))) => {
+                self.shadows.borrow_mut().insert(block.ptr() as usize, shadow.ptr() as usize);
+                Err((err, block))
+            }
+            (Ok(_), Err((err, _))) => {
+                panic!("Mirror diverged: reallocating {} bytes aligned to {} to {} bytes failed \
+                        only in the shadow allocator, with {:?}.", size, align, new_size, err)
+            }
+            (Err((err, _)), Ok(_)) => {
+                panic!("Mirror diverged: reallocating {} bytes aligned to {} to {} bytes failed \
+                        only in the primary allocator, with {:?}.", size, align, new_size, err)
+            }
+        }
+    }
+
+    unsafe fn deallocate_raw(&self, block: Block) {
+        if block.is_empty() {
+            return;
+        }
+
+        let shadow = self.shadow_of(&block);
+        self.primary.deallocate_raw(block);
+        self.shadow.deallocate_raw(shadow);
     }
 }
 
@@ -93,10 +893,22 @@ pub trait ProxyLogger {
     fn reallocate_success(&self, old_block: &Block, new_block: &Block);
     /// Called after a failed reallocation.
     fn reallocate_fail(&self, err: &Error, block: &Block, req_size: usize);
+
+    /// Called after a block was successfully grown or shrunk in place.
+    /// By default, this is logged like any other reallocation.
+    fn resize_in_place_success(&self, old_block: &Block, new_block: &Block) {
+        self.reallocate_success(old_block, new_block);
+    }
+    /// Called after a block couldn't be grown or shrunk in place.
+    /// The block is left as it was, so by default, this is ignored.
+    fn resize_in_place_fail(&self, _err: &Error, _block: &Block, _req_size: usize) {}
 }
 
 /// This wraps an allocator and a logger, logging all allocations
 /// and deallocations.
+///
+/// The allocator can be a trait object, like a `Box<Allocator>` or an `&Allocator`,
+/// to log the activity of an allocator chosen at runtime.
 pub struct Proxy<A, L> {
     alloc: A,
     logger: L,
@@ -110,11 +922,15 @@ impl<A: Allocator, L: ProxyLogger> Proxy<A, L> {
             logger: logger,
         }
     }
-}
 
-unsafe impl<A: Allocator, L: ProxyLogger> Allocator for Proxy<A, L> {
-    unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
-        match self.alloc.allocate_raw(size, align) {
+    /// Gets a reference to the logger.
+    pub fn logger(&self) -> &L {
+        &self.logger
+    }
+
+    // informs the logger of the outcome of an allocation.
+    fn log_allocate<'a>(&self, result: Result<Block<'a>, Error>, size: usize, align: usize) -> Result<Block<'a>, Error> {
+        match result {
             Ok(block) => {
                 self.logger.allocate_success(&block);
                 Ok(block)
@@ -126,6 +942,44 @@ unsafe impl<A: Allocator, L: ProxyLogger> Allocator for Proxy<A, L> {
         }
     }
 
+    // informs the logger of the outcome of an in-place resize.
+    fn log_resize<'a>(&self,
+                      old_block: Block,
+                      result: Result<Block<'a>, (Error, Block<'a>)>,
+                      new_size: usize)
+                      -> Result<Block<'a>, (Error, Block<'a>)> {
+        match result {
+            Ok(new_block) => {
+                self.logger.resize_in_place_success(&old_block, &new_block);
+                Ok(new_block)
+            }
+            Err((err, old)) => {
+                self.logger.resize_in_place_fail(&err, &old, new_size);
+                Err((err, old))
+            }
+        }
+    }
+}
+
+impl<A: Allocator + Default, L: ProxyLogger + Default> Default for Proxy<A, L> {
+    fn default() -> Self {
+        Proxy::new(A::default(), L::default())
+    }
+}
+
+unsafe impl<A: Allocator, L: ProxyLogger> Allocator for Proxy<A, L> {
+    unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        self.log_allocate(self.alloc.allocate_raw(size, align), size, align)
+    }
+
+    unsafe fn allocate_zeroed_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        self.log_allocate(self.alloc.allocate_zeroed_raw(size, align), size, align)
+    }
+
+    unsafe fn allocate_excess_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        self.log_allocate(self.alloc.allocate_excess_raw(size, align), size, align)
+    }
+
     unsafe fn reallocate_raw<'a>(&'a self, block: Block<'a>, new_size: usize) -> Result<Block<'a>, (Error, Block<'a>)> {
         let old_copy = Block::new(block.ptr(), block.size(), block.align());
 
@@ -145,10 +999,32 @@ unsafe impl<A: Allocator, L: ProxyLogger> Allocator for Proxy<A, L> {
         self.logger.deallocate(&block);
         self.alloc.deallocate_raw(block);
     }
+
+    fn usable_size(&self, size: usize, align: usize) -> usize {
+        self.alloc.usable_size(size, align)
+    }
+
+    unsafe fn grow_in_place<'a>(&'a self, block: Block<'a>, new_size: usize) -> Result<Block<'a>, (Error, Block<'a>)> {
+        let old_copy = Block::new(block.ptr(), block.size(), block.align());
+        self.log_resize(old_copy, self.alloc.grow_in_place(block, new_size), new_size)
+    }
+
+    unsafe fn shrink_in_place<'a>(&'a self, block: Block<'a>, new_size: usize) -> Result<Block<'a>, (Error, Block<'a>)> {
+        let old_copy = Block::new(block.ptr(), block.size(), block.align());
+        self.log_resize(old_copy, self.alloc.shrink_in_place(block, new_size), new_size)
+    }
+}
+
+impl<A: BlockOwner, L: ProxyLogger> BlockOwner for Proxy<A, L> {
+    fn owns_block(&self, block: &Block) -> bool {
+        self.alloc.owns_block(block)
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::ptr;
+
     use super::super::*;
 
     #[test]
@@ -157,4 +1033,494 @@ mod tests {
         let alloc = NullAllocator;
         alloc.allocate(1i32).unwrap();
     }
+
+    #[test]
+    fn null_deallocate_empty() {
+        let alloc = NullAllocator;
+        unsafe { alloc.deallocate_raw(Block::empty()) };
+    }
+
+    #[test]
+    #[should_panic]
+    fn null_deallocate() {
+        let alloc = NullAllocator;
+        let mut val = 0u64;
+        unsafe { alloc.deallocate_raw(Block::new(&mut val as *mut u64 as *mut u8, 8, 8)) };
+    }
+
+    #[test]
+    fn zeroed_passes_through() {
+        use std::cell::Cell;
+
+        // counts the zeroed allocations which reach it.
+        struct CountZeroed(Cell<usize>);
+
+        unsafe impl Allocator for CountZeroed {
+            unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+                HEAP.allocate_raw(size, align)
+            }
+
+            unsafe fn allocate_zeroed_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+                self.0.set(self.0.get() + 1);
+                HEAP.allocate_zeroed_raw(size, align)
+            }
+
+            unsafe fn reallocate_raw<'a>(&'a self, block: Block<'a>, new_size: usize) -> Result<Block<'a>, (Error, Block<'a>)> {
+                HEAP.reallocate_raw(block, new_size)
+            }
+
+            unsafe fn deallocate_raw(&self, block: Block) {
+                HEAP.deallocate_raw(block)
+            }
+        }
+
+        let counter = CountZeroed(Cell::new(0));
+        let alloc = TrackingAllocator::new(Quota::new(Proxy::new(&counter, StatsLogger::new()), 64));
+        unsafe {
+            let block = alloc.allocate_zeroed_raw(16, 8).unwrap();
+            assert_eq!(counter.0.get(), 1);
+            assert_eq!(alloc.live_blocks(), 1);
+            alloc.deallocate_raw(block);
+        }
+        alloc.assert_no_leaks();
+
+        let zeroing = Zeroing::new(&counter);
+        unsafe {
+            let block = zeroing.allocate_zeroed_raw(16, 8).unwrap();
+            assert_eq!(counter.0.get(), 2);
+            zeroing.deallocate_raw(block);
+        }
+    }
+
+    #[test]
+    fn proxy_owning() {
+        let main = Proxy::new(Scoped::new(64).unwrap(), StatsLogger::new());
+        let fallback = Scoped::new(256).unwrap();
+        let alloc = Fallback::new(&main, &fallback);
+
+        let small = alloc.allocate(0u64).unwrap();
+        let big = alloc.allocate([0u8; 128]).unwrap();
+        assert!(main.owns(&small));
+        assert!(!main.owns(&big));
+
+        // only the main allocator's activity was logged.
+        assert_eq!(main.logger().snapshot().allocations, 1);
+        assert_eq!(main.logger().snapshot().failures, 1);
+    }
+
+    #[test]
+    fn proxy_forwards_hooks() {
+        let list = FreeList::new(64, 2).unwrap();
+        let alloc = Proxy::new(&list, StatsLogger::new());
+        assert_eq!(alloc.usable_size(10, 8), 64);
+
+        unsafe {
+            let block = alloc.allocate_raw(10, 8).unwrap();
+            let block = alloc.grow_in_place(block, 64).ok().unwrap();
+            assert_eq!(alloc.logger().snapshot().live_bytes, 64);
+            let block = alloc.shrink_in_place(block, 32).ok().unwrap();
+            let (err, block) = alloc.grow_in_place(block, 128).err().unwrap();
+            assert_eq!(err, Error::CannotResizeInPlace);
+            assert_eq!(alloc.logger().snapshot().live_bytes, 32);
+            assert_eq!(alloc.logger().snapshot().failures, 0);
+            alloc.deallocate_raw(block);
+        }
+    }
+
+    #[test]
+    fn proxy_trait_object() {
+        let boxed: Box<Allocator> = Box::new(HEAP);
+        let alloc = Proxy::new(boxed, StatsLogger::new());
+        {
+            let mut val = alloc.allocate([0u8; 32]).unwrap();
+            val[31] = 1;
+        }
+        let stats = alloc.logger().snapshot();
+        assert_eq!(stats.allocations, 1);
+        assert_eq!(stats.deallocations, 1);
+        assert_eq!(stats.live_bytes, 0);
+    }
+
+    #[test]
+    fn fallback_spill() {
+        let main = Scoped::new(16).unwrap();
+        let fallback = Scoped::new(256).unwrap();
+        let alloc = Fallback::new(&main, &fallback);
+
+        unsafe {
+            let block = alloc.allocate_raw(8, 8).unwrap();
+            ptr::write(block.ptr() as *mut u64, 0xdeadbeef);
+            let block = alloc.reallocate_raw(block, 16).ok().unwrap();
+            assert_eq!(alloc.served_by(&block), Some(FallbackSource::Main));
+
+            // too large for the main allocator, so it moves to the fallback.
+            let block = alloc.reallocate_raw(block, 64).ok().unwrap();
+            assert_eq!(alloc.served_by(&block), Some(FallbackSource::Fallback));
+            assert_eq!(*(block.ptr() as *const u64), 0xdeadbeef);
+            assert_eq!(main.bytes_used(), 0);
+            assert_eq!(alloc.fallback_hits(), 1);
+            alloc.deallocate_raw(block);
+        }
+    }
+
+    #[test]
+    fn fallback_overlap() {
+        let main = Scoped::new(64).unwrap().with_free_spans();
+        // the fallback's buffer comes out of the main allocator, so both claim its blocks.
+        let fallback = Scoped::new_from(&main, 32).unwrap();
+
+        let alloc = Fallback::new(&main, &fallback);
+        let a = alloc.allocate([1u8; 32]).unwrap();
+        let b = alloc.allocate([2u8; 16]).unwrap();
+        assert!(main.owns(&b) && fallback.owns(&b));
+        assert_eq!(alloc.served_by(&unsafe { b.as_block() }), Some(FallbackSource::Fallback));
+        assert_eq!(alloc.fallback_blocks().len(), 1);
+
+        drop(b);
+        // it went back to the fallback, rather than becoming a hole in the main allocator.
+        assert_eq!(fallback.bytes_used(), 0);
+        assert_eq!(main.free_span_bytes(), 0);
+        assert!(alloc.fallback_blocks().is_empty());
+        drop(a);
+    }
+
+    #[test]
+    fn fallback_untracked() {
+        let mut main_buf = [0u8; 16];
+        let mut fallback_buf = [0u8; 64];
+        let main = Scoped::from_buffer(&mut main_buf);
+        let fallback = Scoped::from_buffer(&mut fallback_buf);
+        let alloc = Fallback::new(&main, &fallback);
+
+        let a = alloc.allocate([0u8; 16]).unwrap();
+        let b = alloc.allocate([0u8; 32]).unwrap();
+        assert_eq!(alloc.served_by(&unsafe { b.as_block() }), Some(FallbackSource::Fallback));
+        // the two arenas never claim the same block, so none is kept track of.
+        assert!(alloc.fallback_blocks().is_empty());
+        drop((a, b));
+    }
+
+    #[test]
+    fn fallback_hits() {
+        let main = Scoped::new(16).unwrap();
+        let fallback = Scoped::new(256).unwrap();
+        let alloc = Fallback::new(&main, &fallback);
+
+        let vals: Vec<_> = (0..6u64).map(|i| alloc.allocate(i).unwrap()).collect();
+        assert_eq!(alloc.main_hits(), 2);
+        assert_eq!(alloc.fallback_hits(), 4);
+
+        let sources: Vec<_> = vals.iter().map(|val| alloc.served_by(&unsafe { val.as_block() })).collect();
+        assert_eq!(sources[1], Some(FallbackSource::Main));
+        assert_eq!(sources[2], Some(FallbackSource::Fallback));
+
+        let other = HEAP.allocate(0u64).unwrap();
+        assert_eq!(alloc.served_by(&unsafe { other.as_block() }), None);
+        unsafe {
+            let block = HEAP.allocate_raw(8, 8).unwrap();
+            let (err, block) = alloc.reallocate_raw(block, 16).err().unwrap();
+            assert_eq!(err, Error::NotOwned);
+            HEAP.deallocate_raw(block);
+        }
+    }
+
+    #[test]
+    fn fallback_chain() {
+        let (a, b, c) = (Scoped::new(8).unwrap(), Scoped::new(16).unwrap(), Scoped::new(32).unwrap());
+        assert!(FallbackChain::new().is_empty());
+        let alloc = FallbackChain::new().with(&a).with(&b).with(&c);
+        assert_eq!(alloc.len(), 3);
+
+        let first = alloc.allocate([0u8; 8]).unwrap();
+        let second = alloc.allocate([0u8; 16]).unwrap();
+        let third = alloc.allocate([0u8; 32]).unwrap();
+        assert!(a.owns(&first));
+        assert!(b.owns(&second));
+        assert!(c.owns(&third));
+        assert!(alloc.allocate(0u8).is_err());
+
+        // each block goes back to the allocator it came from.
+        drop(second);
+        assert_eq!(b.bytes_used(), 0);
+        drop(third);
+        assert_eq!(c.bytes_used(), 0);
+        drop(first);
+        assert_eq!(a.bytes_used(), 0);
+    }
+
+    #[test]
+    fn segregator() {
+        let small = Scoped::new(256).unwrap();
+        let large = Scoped::new(8192).unwrap();
+        let alloc = Segregator::new(64, &small, &large);
+
+        let a = alloc.allocate([0u8; 32]).unwrap();
+        let b = alloc.allocate([0u8; 4096]).unwrap();
+        assert!(small.owns(&a));
+        assert!(large.owns(&b));
+        assert!(alloc.owns(&a) && alloc.owns(&b));
+
+        unsafe {
+            // growing past the threshold moves the block to the large allocator.
+            let block = alloc.allocate_raw(16, 8).unwrap();
+            *block.ptr() = 42;
+            let block = alloc.reallocate_raw(block, 128).ok().unwrap();
+            assert!(large.owns_block(&block));
+            assert_eq!(*block.ptr(), 42);
+            alloc.deallocate_raw(block);
+        }
+    }
+
+    #[test]
+    fn quota() {
+        let alloc = Quota::new(HEAP, 64);
+        let first = alloc.allocate([0u8; 32]).unwrap();
+        let second = alloc.allocate([0u8; 32]).unwrap();
+        assert_eq!(alloc.used(), 64);
+        assert_eq!(alloc.allocate(0u8).err().unwrap().0, Error::OutOfMemory);
+
+        drop(first);
+        assert_eq!(alloc.remaining(), 32);
+        let third = alloc.allocate([0u8; 16]).unwrap();
+
+        unsafe {
+            let block = alloc.allocate_raw(8, 1).unwrap();
+            let (err, block) = alloc.reallocate_raw(block, 32).err().unwrap();
+            assert_eq!(err, Error::OutOfMemory);
+            let block = alloc.reallocate_raw(block, 16).ok().unwrap();
+            assert_eq!(alloc.used(), 64);
+            alloc.deallocate_raw(block);
+        }
+
+        drop((second, third));
+        assert_eq!(alloc.used(), 0);
+    }
+
+    #[test]
+    fn quota_realloc() {
+        let alloc = Quota::new(Proxy::new(HEAP, StatsLogger::new()), 64);
+        unsafe {
+            let other = alloc.allocate_raw(16, 8).unwrap();
+            let block = alloc.allocate_raw(8, 8).unwrap();
+            let block = alloc.reallocate_raw(block, 48).ok().unwrap();
+            assert_eq!(alloc.used(), 64);
+
+            // the failed grow never reached the inner allocator.
+            let (err, block) = alloc.reallocate_raw(block, 49).err().unwrap();
+            assert_eq!(err, Error::OutOfMemory);
+            assert_eq!(alloc.inner.logger().snapshot().reallocations, 1);
+            assert_eq!(alloc.used(), 64);
+
+            let block = alloc.reallocate_raw(block, 24).ok().unwrap();
+            assert_eq!(alloc.used(), 40);
+            let block = alloc.reallocate_raw(block, 48).ok().unwrap();
+            assert_eq!(alloc.used(), 64);
+
+            alloc.deallocate_raw(block);
+            alloc.deallocate_raw(other);
+        }
+        assert_eq!(alloc.used(), 0);
+    }
+
+    #[test]
+    fn quota_excess() {
+        let list = FreeList::new(64, 2).unwrap();
+        let alloc = TrackingAllocator::new(Quota::new(Proxy::new(&list, StatsLogger::new()), 100));
+        unsafe {
+            // the whole block makes it through, and counts against the quota.
+            let block = alloc.allocate_excess_raw(10, 8).unwrap();
+            assert_eq!(block.size(), 64);
+
+            // the slack of a second block would go over.
+            let small = alloc.allocate_excess_raw(10, 8).unwrap();
+            assert_eq!(small.size(), 10);
+            alloc.deallocate_raw(small);
+            alloc.deallocate_raw(block);
+        }
+        alloc.assert_no_leaks();
+        assert_eq!(list.available(), 2);
+    }
+
+    #[test]
+    fn quota_in_place() {
+        let alloc = Quota::new(Scoped::new(128).unwrap(), 64);
+        unsafe {
+            let block = alloc.allocate_raw(16, 8).unwrap();
+            let block = alloc.grow_in_place(block, 64).ok().unwrap();
+            assert_eq!(alloc.used(), 64);
+            let (err, block) = alloc.grow_in_place(block, 65).err().unwrap();
+            assert_eq!(err, Error::OutOfMemory);
+            let block = alloc.shrink_in_place(block, 8).ok().unwrap();
+            assert_eq!(alloc.used(), 8);
+            alloc.deallocate_raw(block);
+        }
+        assert_eq!(alloc.used(), 0);
+    }
+
+    #[test]
+    fn mirror() {
+        let alloc = Mirror::new(FreeList::new(64, 4).unwrap(), FreeList::new(64, 4).unwrap());
+        let vals: Vec<_> = (0..4).map(|i| alloc.allocate([i as u8; 32]).unwrap()).collect();
+        assert!(alloc.allocate(0u8).is_err());
+        drop(vals);
+
+        unsafe {
+            let block = alloc.allocate_raw(16, 8).unwrap();
+            let block = alloc.reallocate_raw(block, 64).ok().unwrap();
+            let (_, block) = alloc.reallocate_raw(block, 128).err().unwrap();
+            alloc.deallocate_raw(block);
+
+            let (err, _) = alloc.reallocate_raw(Block::empty(), 8).err().unwrap();
+            assert_eq!(err, Error::UnsupportedAlignment);
+        }
+        assert!(alloc.shadows.borrow().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Mirror diverged")]
+    fn mirror_divergence() {
+        let alloc = Mirror::new(FreeList::new(64, 2).unwrap(), HEAP);
+        let _a = alloc.allocate(1u64).unwrap();
+        let _b = alloc.allocate(2u64).unwrap();
+        // the free list is full, but the heap isn't.
+        let _c = alloc.allocate(3u64);
+    }
+
+    #[test]
+    fn zeroing() {
+        let scoped = Scoped::new(64).unwrap();
+        scoped.scope(|inner| unsafe {
+            let block = inner.allocate_raw(64, 1).unwrap();
+            ptr::write_bytes(block.ptr(), 0xAB, 64);
+        }).unwrap();
+
+        // the scope handed the garbage back to `scoped`.
+        let alloc = Zeroing::new(&scoped);
+        unsafe {
+            let block = alloc.allocate_raw(32, 1).unwrap();
+            for i in 0..32 {
+                assert_eq!(*block.ptr().offset(i), 0);
+            }
+
+            // growing in place zeroes the new bytes too.
+            let block = alloc.reallocate_raw(block, 64).ok().unwrap();
+            for i in 0..64 {
+                assert_eq!(*block.ptr().offset(i), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn poison() {
+        let scoped = Scoped::new(64).unwrap();
+        let alloc = Poison::new(&scoped, true);
+        unsafe {
+            let block = alloc.allocate_raw(16, 1).unwrap();
+            let ptr = block.ptr();
+            assert_eq!(*ptr, POISON_ALLOCATED);
+
+            ptr::write_bytes(ptr, 1, 16);
+            alloc.deallocate_raw(block);
+            // the arena doesn't care, but the old contents are gone.
+            for i in 0..16 {
+                assert_eq!(*ptr.offset(i), POISON_FREED);
+            }
+
+            // growing moves the block, poisoning the old location.
+            let block = alloc.allocate_raw(8, 1).unwrap();
+            *block.ptr() = 42;
+            let old = block.ptr();
+            let block = alloc.reallocate_raw(block, 32).ok().unwrap();
+            assert!(block.ptr() != old);
+            assert_eq!(*block.ptr(), 42);
+            assert_eq!(*block.ptr().offset(8), POISON_ALLOCATED);
+            assert_eq!(*old, POISON_FREED);
+
+            let (err, _) = alloc.reallocate_raw(Block::empty(), 8).err().unwrap();
+            assert_eq!(err, Error::UnsupportedAlignment);
+        }
+    }
+
+    #[test]
+    fn poison_shrink() {
+        // a free list shrinks in place, so only the tail is poisoned.
+        let list = FreeList::new(32, 2).unwrap();
+        let alloc = Poison::new(&list, false);
+        unsafe {
+            let block = alloc.allocate_raw(32, 1).unwrap();
+            let ptr = block.ptr();
+            ptr::write_bytes(ptr, 1, 32);
+            let block = alloc.reallocate_raw(block, 8).ok().unwrap();
+            assert_eq!(block.ptr(), ptr);
+            assert_eq!(*ptr.offset(7), 1);
+            assert_eq!(*ptr.offset(8), POISON_FREED);
+            alloc.deallocate_raw(block);
+        }
+
+        // an arena which can't shrink in place, so the block has to move.
+        struct NoShrink<'a>(&'a Scoped<'static, HeapAllocator>);
+
+        unsafe impl<'a> Allocator for NoShrink<'a> {
+            unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+                self.0.allocate_raw(size, align)
+            }
+
+            unsafe fn reallocate_raw<'b>(&'b self, block: Block<'b>, new_size: usize) -> Result<Block<'b>, (Error, Block<'b>)> {
+                self.0.reallocate_raw(block, new_size)
+            }
+
+            unsafe fn deallocate_raw(&self, block: Block) {
+                self.0.deallocate_raw(block)
+            }
+        }
+
+        // the whole old block is poisoned when it moves.
+        let scoped = Scoped::new(64).unwrap();
+        let alloc = Poison::new(NoShrink(&scoped), false);
+        unsafe {
+            let block = alloc.allocate_raw(16, 1).unwrap();
+            let old = block.ptr();
+            ptr::write_bytes(old, 1, 16);
+            let block = alloc.reallocate_raw(block, 8).ok().unwrap();
+            assert!(block.ptr() != old);
+            assert_eq!(*block.ptr().offset(7), 1);
+            assert_eq!(*old, POISON_FREED);
+
+            // with nowhere to move to, the block comes back untouched.
+            let rest = alloc.allocate_raw(scoped.bytes_remaining(), 1).unwrap();
+            let (_, block) = alloc.reallocate_raw(block, 4).err().unwrap();
+            for i in 0..8 {
+                assert_eq!(*block.ptr().offset(i), 1);
+            }
+            alloc.deallocate_raw(rest);
+            alloc.deallocate_raw(block);
+        }
+    }
+
+    #[test]
+    fn quantizer() {
+        let alloc = Quantizer::new(Proxy::new(HEAP, StatsLogger::new()), Quantum::Multiple(16));
+        assert_eq!(alloc.quantize(33), Some(48));
+        assert_eq!(alloc.usable_size(40, 8), 48);
+
+        let a = alloc.allocate([0u8; 33]).unwrap();
+        let b = alloc.allocate([0u8; 40]).unwrap();
+        assert_eq!(unsafe { a.as_block() }.size(), 33);
+        assert_eq!(alloc.inner.logger().snapshot().total_bytes, 96);
+        drop((a, b));
+        assert_eq!(alloc.inner.logger().snapshot().live_bytes, 0);
+
+        let pow = Quantizer::new(HEAP, Quantum::PowerOfTwo);
+        assert_eq!(pow.quantize(33), Some(64));
+        unsafe {
+            let block = pow.allocate_raw(33, 1).unwrap();
+            let ptr = block.ptr();
+            // still within the same quantum.
+            let block = pow.reallocate_raw(block, 64).ok().unwrap();
+            assert_eq!(block.ptr(), ptr);
+            let block = pow.reallocate_raw(block, 100).ok().unwrap();
+            assert_eq!(block.size(), 100);
+            pow.deallocate_raw(block);
+        }
+    }
 }
@@ -1,17 +1,26 @@
 //! A Free List allocator.
 
 use std::cell::Cell;
+use std::cmp;
+use std::marker::PhantomData;
 use std::mem;
 use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
 
-use super::{Allocator, Error, Block, HeapAllocator, HEAP};
+use super::{Allocator, Error, Block, BlockOwner, HeapAllocator, HEAP};
 
 /// A `FreeList` allocator manages a list of free memory blocks of uniform size.
 /// Whenever a block is requested, it returns the first free block.
 pub struct FreeList<'a, A: 'a + Allocator> {
     alloc: &'a A,
     block_size: usize,
+    align: usize,
     free_list: Cell<*mut u8>,
+    free_count: Cell<usize>,
+    capacity: usize,
+    // the address of every block allocated for this list, in ascending order.
+    blocks: Vec<*mut u8>,
 }
 
 impl FreeList<'static, HeapAllocator> {
@@ -20,7 +29,31 @@ impl FreeList<'static, HeapAllocator> {
     pub fn new(block_size: usize, num_blocks: usize) -> Result<Self, Error> {
         FreeList::new_from(HEAP, block_size, num_blocks)
     }
+
+    /// Creates a new `FreeList` backed by the heap, whose blocks
+    /// are aligned to `align`. See `new_from_aligned`.
+    pub fn new_aligned(block_size: usize, num_blocks: usize, align: usize) -> Result<Self, Error> {
+        FreeList::new_from_aligned(HEAP, block_size, num_blocks, align)
+    }
 }
+
+/// The size of the blocks of a `FreeList` made by `default`: 1 KiB.
+pub const DEFAULT_BLOCK_SIZE: usize = 1024;
+/// The number of blocks of a `FreeList` made by `default`.
+pub const DEFAULT_NUM_BLOCKS: usize = 64;
+
+impl Default for FreeList<'static, HeapAllocator> {
+    /// Creates a new `FreeList` of `DEFAULT_NUM_BLOCKS` blocks of
+    /// `DEFAULT_BLOCK_SIZE` bytes, backed by the heap.
+    ///
+    /// # Panics
+    /// Panics if the blocks can't be allocated.
+    fn default() -> Self {
+        FreeList::new(DEFAULT_BLOCK_SIZE, DEFAULT_NUM_BLOCKS)
+            .expect("Failed to allocate the blocks of a default FreeList.")
+    }
+}
+
 impl<'a, A: 'a + Allocator> FreeList<'a, A> {
     /// Creates a new `FreeList` backed by another allocator. `block_size` must be greater
     /// than or equal to the size of a pointer.
@@ -28,40 +61,153 @@ impl<'a, A: 'a + Allocator> FreeList<'a, A> {
                     block_size: usize,
                     num_blocks: usize)
                     -> Result<Self, Error> {
+        FreeList::new_from_aligned(alloc, block_size, num_blocks, mem::align_of::<*mut u8>())
+    }
+
+    /// Creates a new `FreeList` backed by another allocator, whose blocks are
+    /// aligned to `align`. Requests with alignment up to `align` can then be served.
+    /// `align` must be a power of two, and is raised to the alignment of a pointer if
+    /// it is smaller. `block_size` must be greater than or equal to the size of a pointer.
+    pub fn new_from_aligned(alloc: &'a A,
+                            block_size: usize,
+                            num_blocks: usize,
+                            align: usize)
+                            -> Result<Self, Error> {
         if block_size < mem::size_of::<*mut u8>() {
             return Err(Error::AllocatorSpecific("Block size too small.".into()));
         }
 
-        let mut free_list = ptr::null_mut();
+        if !align.is_power_of_two() {
+            return Err(Error::UnsupportedAlignment);
+        }
+        let align = cmp::max(align, mem::align_of::<*mut u8>());
 
-        // allocate each block with maximal alignment.
-        for _ in 0..num_blocks {
+        let mut free_list = ptr::null_mut();
+        let mut blocks = Vec::with_capacity(num_blocks);
 
-            match unsafe { alloc.allocate_raw(block_size, mem::align_of::<*mut u8>()) } {
+        for allocated in 0..num_blocks {
+            match unsafe { alloc.allocate_raw(block_size, align) } {
                 Ok(block) => {
                     let ptr: *mut *mut u8 = block.ptr() as *mut *mut u8;
                     unsafe { *ptr = free_list }
                     free_list = block.ptr();
+                    blocks.push(block.ptr());
                 }
                 Err(err) => {
-                    // destructor cleans up after us.
-                    drop(FreeList {
-                        alloc: alloc,
-                        block_size: block_size,
-                        free_list: Cell::new(free_list),
-                    });
+                    // give back exactly the blocks allocated so far.
+                    while !free_list.is_null() {
+                        unsafe {
+                            let next = *(free_list as *mut *mut u8);
+                            alloc.deallocate_raw(Block::new(free_list, block_size, align));
+                            free_list = next;
+                        }
+                    }
 
-                    return Err(err);
+                    // with nothing allocated, the parent's error is passed on as it is.
+                    return Err(if allocated == 0 {
+                        err
+                    } else {
+                        Error::Incomplete {
+                            allocated: allocated,
+                            cause: Box::new(err),
+                        }
+                    });
                 }
             }
         }
 
+        blocks.sort();
         Ok(FreeList {
             alloc: alloc,
             block_size: block_size,
+            align: align,
             free_list: Cell::new(free_list),
+            free_count: Cell::new(num_blocks),
+            capacity: num_blocks,
+            blocks: blocks,
         })
     }
+
+    /// The number of blocks currently available for allocation.
+    pub fn available(&self) -> usize {
+        self.free_count.get()
+    }
+
+    /// The total number of blocks managed by this allocator.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Allocates `additional` more blocks from the backing allocator,
+    /// and adds them to the free list.
+    ///
+    /// If the backing allocator fails partway, the blocks it did hand out are kept,
+    /// and their number is reported in an `Error::Incomplete`. If it fails on the
+    /// first block, its error is returned unchanged.
+    pub fn grow(&mut self, additional: usize) -> Result<(), Error> {
+        for added in 0..additional {
+            match unsafe { self.alloc.allocate_raw(self.block_size, self.align) } {
+                Ok(block) => {
+                    let ptr = block.ptr();
+                    unsafe { *(ptr as *mut *mut u8) = self.free_list.get() };
+                    self.free_list.set(ptr);
+                    self.free_count.set(self.free_count.get() + 1);
+                    self.capacity += 1;
+
+                    let index = self.blocks.binary_search(&ptr).unwrap_or_else(|index| index);
+                    self.blocks.insert(index, ptr);
+                }
+                Err(err) => {
+                    return Err(if added == 0 {
+                        err
+                    } else {
+                        Error::Incomplete {
+                            allocated: added,
+                            cause: Box::new(err),
+                        }
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Iterates over the addresses of the blocks currently on the free list,
+    /// in the order they will be handed out.
+    ///
+    /// The iteration stops after `capacity` blocks, so a list corrupted into
+    /// a cycle (by a block freed twice, say) yields more blocks than `available`
+    /// reports rather than looping forever.
+    pub fn iter_free(&self) -> FreeBlockIter {
+        FreeBlockIter {
+            next: self.free_list.get(),
+            remaining: self.capacity,
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// An iterator over the free blocks of a `FreeList`, created by `iter_free`.
+pub struct FreeBlockIter<'a> {
+    next: *mut u8,
+    remaining: usize,
+    _marker: PhantomData<&'a ()>,
+}
+
+impl<'a> Iterator for FreeBlockIter<'a> {
+    type Item = *mut u8;
+
+    fn next(&mut self) -> Option<*mut u8> {
+        if self.next.is_null() || self.remaining == 0 {
+            return None;
+        }
+
+        let block = self.next;
+        self.next = unsafe { *(block as *mut *mut u8) };
+        self.remaining -= 1;
+        Some(block)
+    }
 }
 
 unsafe impl<'a, A: 'a + Allocator> Allocator for FreeList<'a, A> {
@@ -72,7 +218,7 @@ unsafe impl<'a, A: 'a + Allocator> Allocator for FreeList<'a, A> {
             return Err(Error::OutOfMemory);
         }
 
-        if align > mem::align_of::<*mut u8>() {
+        if align > self.align {
             return Err(Error::UnsupportedAlignment);
         }
 
@@ -80,6 +226,7 @@ unsafe impl<'a, A: 'a + Allocator> Allocator for FreeList<'a, A> {
         if !free_list.is_null() {
             let next_block = *(free_list as *mut *mut u8);
             self.free_list.set(next_block);
+            self.free_count.set(self.free_count.get() - 1);
 
             Ok(Block::new(free_list, size, align))
         } else {
@@ -87,12 +234,28 @@ unsafe impl<'a, A: 'a + Allocator> Allocator for FreeList<'a, A> {
         }
     }
 
+    /// Every block is `block_size` bytes, whatever the size requested.
+    unsafe fn allocate_excess_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        match self.allocate_raw(size, align) {
+            Ok(block) => {
+                if block.is_empty() {
+                    Ok(block)
+                } else {
+                    Ok(Block::new(block.ptr(), self.block_size, block.align()))
+                }
+            }
+            Err(err) => Err(err),
+        }
+    }
+
     unsafe fn reallocate_raw<'b>(&'b self, block: Block<'b>, new_size: usize) -> Result<Block<'b>, (Error, Block<'b>)> {
         if new_size == 0 {
+            self.deallocate_raw(block);
             Ok(Block::empty())
         } else if block.is_empty() {
             Err((Error::UnsupportedAlignment, block))
         } else if new_size <= self.block_size {
+            // every block is `block_size` bytes, so this always fits in place.
             Ok(Block::new(block.ptr(), new_size, block.align()))
         } else {
             Err((Error::OutOfMemory, block))
@@ -100,25 +263,76 @@ unsafe impl<'a, A: 'a + Allocator> Allocator for FreeList<'a, A> {
     }
 
     unsafe fn deallocate_raw(&self, block: Block) {
-        if !block.is_empty() {
-            let first = self.free_list.get();
-            let ptr = block.ptr();
-            *(ptr as *mut *mut u8) = first;
-            self.free_list.set(ptr);
+        if block.is_empty() {
+            return;
+        }
+
+        // pushing a foreign block would corrupt it and later hand it out,
+        // so refuse it outright.
+        if !self.owns_block(&block) {
+            debug_assert!(false, "Attempted to deallocate a block not owned by this FreeList.");
+            return;
+        }
+
+        let first = self.free_list.get();
+        let ptr = block.ptr();
+        *(ptr as *mut *mut u8) = first;
+        self.free_list.set(ptr);
+        self.free_count.set(self.free_count.get() + 1);
+    }
+
+    fn usable_size(&self, size: usize, align: usize) -> usize {
+        if size <= self.block_size && align <= self.align {
+            self.block_size
+        } else {
+            size
+        }
+    }
+
+    unsafe fn grow_in_place<'b>(&'b self, block: Block<'b>, new_size: usize) -> Result<Block<'b>, (Error, Block<'b>)> {
+        // every block is `block_size` bytes, whatever its size says.
+        if !block.is_empty() && new_size <= self.block_size {
+            Ok(Block::new(block.ptr(), new_size, block.align()))
+        } else {
+            Err((Error::CannotResizeInPlace, block))
+        }
+    }
+
+    unsafe fn shrink_in_place<'b>(&'b self, block: Block<'b>, new_size: usize) -> Result<Block<'b>, (Error, Block<'b>)> {
+        if block.is_empty() {
+            Err((Error::CannotResizeInPlace, block))
+        } else {
+            Ok(Block::new(block.ptr(), new_size, block.align()))
         }
     }
 }
 
+impl<'a, A: 'a + Allocator> BlockOwner for FreeList<'a, A> {
+    /// The blocks are allocated separately from the backing allocator,
+    /// so this looks the block up among them, rather than checking an address range
+    /// which could take in memory the backing allocator handed out to someone else.
+    fn owns_block(&self, block: &Block) -> bool {
+        block.size() <= self.block_size && self.blocks.binary_search(&block.ptr()).is_ok()
+    }
+}
+
 impl<'a, A: 'a + Allocator> Drop for FreeList<'a, A> {
     fn drop(&mut self) {
+        // blocks which were never returned can't be freed from here.
+        // don't pile a second panic onto an unwind, though.
+        if cfg!(debug_assertions) && !thread::panicking() {
+            let outstanding = self.capacity - self.free_count.get();
+            assert!(outstanding == 0,
+                    "FreeList dropped with {} blocks still allocated.",
+                    outstanding);
+        }
+
         let mut free_list = self.free_list.get();
         //free all the blocks in the list.
         while !free_list.is_null() {
             unsafe {
                 let next = *(free_list as *mut *mut u8);
-                self.alloc.deallocate_raw(Block::new(free_list,
-                                                     self.block_size,
-                                                     mem::align_of::<*mut u8>()));
+                self.alloc.deallocate_raw(Block::new(free_list, self.block_size, self.align));
                 free_list = next;
             }
         }
@@ -127,8 +341,206 @@ impl<'a, A: 'a + Allocator> Drop for FreeList<'a, A> {
 
 unsafe impl<'a, A: 'a + Allocator + Sync> Send for FreeList<'a, A> {}
 
+// The head of a `ConcurrentFreeList` packs the index of the first free block
+// into the low half of a word, and a counter bumped on every update into the high half.
+// The counter keeps a stale compare-and-swap from succeeding when the same block
+// has been popped and pushed back in the meantime (the ABA problem).
+const TAG_SHIFT: usize = mem::size_of::<usize>() * 4;
+const INDEX_MASK: usize = (1 << TAG_SHIFT) - 1;
+// marks the end of the free list.
+const NIL: usize = INDEX_MASK;
+
+/// A thread-safe variant of `FreeList`.
+///
+/// The free blocks form a lock-free stack, so this can be shared between threads
+/// without any external locking. Unlike `FreeList`, the blocks are carved out of a
+/// single buffer from the backing allocator.
+pub struct ConcurrentFreeList<'a, A: 'a + Allocator> {
+    alloc: &'a A,
+    block_size: usize,
+    num_blocks: usize,
+    buffer: *mut u8,
+    head: AtomicUsize,
+}
+
+impl ConcurrentFreeList<'static, HeapAllocator> {
+    /// Creates a new `ConcurrentFreeList` backed by the heap. `block_size` must be greater
+    /// than or equal to the size of a pointer.
+    pub fn new(block_size: usize, num_blocks: usize) -> Result<Self, Error> {
+        ConcurrentFreeList::new_from(HEAP, block_size, num_blocks)
+    }
+}
+
+impl<'a, A: 'a + Allocator> ConcurrentFreeList<'a, A> {
+    /// Creates a new `ConcurrentFreeList` backed by another allocator. `block_size` must be greater
+    /// than or equal to the size of a pointer.
+    pub fn new_from(alloc: &'a A,
+                    block_size: usize,
+                    num_blocks: usize)
+                    -> Result<Self, Error> {
+        if block_size < mem::size_of::<*mut u8>() {
+            return Err(Error::AllocatorSpecific("Block size too small.".into()));
+        }
+
+        if num_blocks >= NIL {
+            return Err(Error::AllocatorSpecific("Too many blocks.".into()));
+        }
+
+        // keep every block aligned by rounding the size up to the alignment.
+        let align = mem::align_of::<*mut u8>();
+        let block_size = (block_size + align - 1) & !(align - 1);
+        let size = match block_size.checked_mul(num_blocks) {
+            Some(size) => size,
+            None => return Err(Error::OutOfMemory),
+        };
+
+        let buffer = match unsafe { alloc.allocate_raw(size, align) } {
+            Ok(block) => block.ptr(),
+            Err(err) => return Err(err),
+        };
+
+        // link every block to the one after it.
+        for i in 0..num_blocks {
+            let next = if i + 1 == num_blocks { NIL } else { i + 1 };
+            unsafe { *(buffer.offset((i * block_size) as isize) as *mut usize) = next };
+        }
+
+        Ok(ConcurrentFreeList {
+            alloc: alloc,
+            block_size: block_size,
+            num_blocks: num_blocks,
+            buffer: buffer,
+            head: AtomicUsize::new(if num_blocks == 0 { NIL } else { 0 }),
+        })
+    }
+
+    // the next-index stored in a free block. This is read atomically since
+    // another thread may have popped the block in the meantime.
+    unsafe fn next_of(&self, index: usize) -> &AtomicUsize {
+        &*(self.buffer.offset((index * self.block_size) as isize) as *const AtomicUsize)
+    }
+}
+
+unsafe impl<'a, A: 'a + Allocator> Allocator for ConcurrentFreeList<'a, A> {
+    unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        if size == 0 {
+            return Ok(Block::empty());
+        } else if size > self.block_size {
+            return Err(Error::OutOfMemory);
+        }
+
+        if align > mem::align_of::<*mut u8>() {
+            return Err(Error::UnsupportedAlignment);
+        }
+
+        let mut head = self.head.load(Ordering::Acquire);
+        loop {
+            let index = head & INDEX_MASK;
+            if index == NIL {
+                return Err(Error::OutOfMemory);
+            }
+
+            let next = self.next_of(index).load(Ordering::Relaxed);
+            let new_head = ((head >> TAG_SHIFT).wrapping_add(1) << TAG_SHIFT) | next;
+            match self.head.compare_exchange_weak(head, new_head, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => {
+                    let ptr = self.buffer.offset((index * self.block_size) as isize);
+                    return Ok(Block::new(ptr, size, align));
+                }
+                Err(actual) => head = actual,
+            }
+        }
+    }
+
+    unsafe fn reallocate_raw<'b>(&'b self, block: Block<'b>, new_size: usize) -> Result<Block<'b>, (Error, Block<'b>)> {
+        if new_size == 0 {
+            self.deallocate_raw(block);
+            Ok(Block::empty())
+        } else if block.is_empty() {
+            Err((Error::UnsupportedAlignment, block))
+        } else if new_size <= self.block_size {
+            Ok(Block::new(block.ptr(), new_size, block.align()))
+        } else {
+            Err((Error::OutOfMemory, block))
+        }
+    }
+
+    unsafe fn deallocate_raw(&self, block: Block) {
+        if block.is_empty() {
+            return;
+        }
+
+        if !self.owns_block(&block) {
+            debug_assert!(false, "Attempted to deallocate a block not owned by this ConcurrentFreeList.");
+            return;
+        }
+
+        let index = (block.ptr() as usize - self.buffer as usize) / self.block_size;
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            self.next_of(index).store(head & INDEX_MASK, Ordering::Relaxed);
+            let new_head = ((head >> TAG_SHIFT).wrapping_add(1) << TAG_SHIFT) | index;
+            match self.head.compare_exchange_weak(head, new_head, Ordering::AcqRel, Ordering::Relaxed) {
+                Ok(_) => return,
+                Err(actual) => head = actual,
+            }
+        }
+    }
+
+    fn usable_size(&self, size: usize, align: usize) -> usize {
+        if size <= self.block_size && align <= mem::align_of::<*mut u8>() {
+            self.block_size
+        } else {
+            size
+        }
+    }
+
+    unsafe fn grow_in_place<'b>(&'b self, block: Block<'b>, new_size: usize) -> Result<Block<'b>, (Error, Block<'b>)> {
+        // every block is `block_size` bytes, whatever its size says.
+        if !block.is_empty() && new_size <= self.block_size {
+            Ok(Block::new(block.ptr(), new_size, block.align()))
+        } else {
+            Err((Error::CannotResizeInPlace, block))
+        }
+    }
+
+    unsafe fn shrink_in_place<'b>(&'b self, block: Block<'b>, new_size: usize) -> Result<Block<'b>, (Error, Block<'b>)> {
+        if block.is_empty() {
+            Err((Error::CannotResizeInPlace, block))
+        } else {
+            Ok(Block::new(block.ptr(), new_size, block.align()))
+        }
+    }
+}
+
+impl<'a, A: 'a + Allocator> BlockOwner for ConcurrentFreeList<'a, A> {
+    fn owns_block(&self, block: &Block) -> bool {
+        let ptr = block.ptr() as usize;
+        let start = self.buffer as usize;
+        let end = start + self.block_size * self.num_blocks;
+
+        ptr >= start && ptr + block.size() <= end && (ptr - start) % self.block_size == 0
+    }
+}
+
+impl<'a, A: 'a + Allocator> Drop for ConcurrentFreeList<'a, A> {
+    fn drop(&mut self) {
+        let size = self.block_size * self.num_blocks;
+        if size > 0 {
+            unsafe {
+                self.alloc.deallocate_raw(Block::new(self.buffer, size, mem::align_of::<*mut u8>()));
+            }
+        }
+    }
+}
+
+unsafe impl<'a, A: 'a + Allocator + Sync> Send for ConcurrentFreeList<'a, A> {}
+unsafe impl<'a, A: 'a + Allocator + Sync> Sync for ConcurrentFreeList<'a, A> {}
+
 #[cfg(test)]
 mod tests {
+    use std::ptr;
+
     use super::super::*;
 
     #[test]
@@ -142,4 +554,295 @@ mod tests {
         drop(blocks);
         assert!(alloc.allocate([0u8; 1024]).is_ok());
     }
+
+    #[test]
+    fn over_aligned() {
+        #[repr(align(32))]
+        struct Simd([u8; 32]);
+
+        let alloc = FreeList::new(32, 4).unwrap();
+        assert_eq!(alloc.allocate(Simd([0; 32])).err().unwrap().0, Error::UnsupportedAlignment);
+
+        let alloc = FreeList::new_aligned(32, 4, 32).unwrap();
+        let vals: Vec<_> = (0..4).map(|i| alloc.allocate(Simd([i; 32])).ok().unwrap()).collect();
+        for val in &vals {
+            assert_eq!(&**val as *const Simd as usize % 32, 0);
+        }
+    }
+
+    #[test]
+    fn zero_sized() {
+        let alloc = FreeList::new(64, 1).unwrap();
+        let units: Vec<_> = (0..4).map(|_| alloc.allocate(()).unwrap()).collect();
+        // no blocks were used up.
+        assert_eq!(alloc.available(), 1);
+        let _val = alloc.allocate(0u64).unwrap();
+        drop(units);
+        assert_eq!(alloc.available(), 0);
+    }
+
+    #[test]
+    fn counting() {
+        let alloc = FreeList::new(64, 8).unwrap();
+        assert_eq!(alloc.capacity(), 8);
+        assert_eq!(alloc.available(), 8);
+        {
+            let mut blocks = Vec::new();
+            for i in 0..4 {
+                blocks.push(alloc.allocate(i).unwrap());
+            }
+            assert_eq!(alloc.available(), 4);
+        }
+        assert_eq!(alloc.available(), alloc.capacity());
+    }
+
+    #[test]
+    fn partial_construction() {
+        let parent = TrackingAllocator::new(Quota::new(HEAP, 100));
+        match FreeList::new_from(&parent, 32, 4) {
+            Err(Error::Incomplete { allocated, cause }) => {
+                assert_eq!(allocated, 3);
+                assert_eq!(*cause, Error::OutOfMemory);
+            }
+            _ => panic!("the quota should only fit three blocks"),
+        }
+        // the three blocks were given back.
+        parent.assert_no_leaks();
+
+        // so a smaller list can be made instead.
+        let alloc = FreeList::new_from(&parent, 32, 3).unwrap();
+        assert_eq!(alloc.capacity(), 3);
+
+        // with no blocks at all, the parent's error comes through as it is.
+        assert_eq!(FreeList::new_from(&parent, 32, 1).err(), Some(Error::OutOfMemory));
+    }
+
+    #[test]
+    fn excess() {
+        let alloc = FreeList::new(64, 2).unwrap();
+        unsafe {
+            let block = alloc.allocate_excess_raw(10, 8).unwrap();
+            assert_eq!(block.size(), 64);
+            // the whole block can be written to, and grown into in place.
+            ::std::ptr::write_bytes(block.ptr(), 1, 64);
+            let block = alloc.grow_in_place(block, 64).ok().unwrap();
+            alloc.deallocate_raw(block);
+
+            assert!(alloc.allocate_excess_raw(0, 8).unwrap().is_empty());
+            assert_eq!(alloc.allocate_excess_raw(65, 8).err(), Some(Error::OutOfMemory));
+            // other allocators give exactly what was asked for.
+            let block = HEAP.allocate_excess_raw(10, 8).unwrap();
+            assert_eq!(block.size(), 10);
+            HEAP.deallocate_raw(block);
+        }
+        assert_eq!(alloc.available(), 2);
+    }
+
+    #[test]
+    fn grow() {
+        let mut alloc = FreeList::new(16, 4).unwrap();
+        {
+            let _full: Vec<_> = (0..4).map(|i| alloc.allocate(i as u64).unwrap()).collect();
+            assert!(alloc.allocate(0u64).is_err());
+        }
+
+        alloc.grow(4).unwrap();
+        assert_eq!(alloc.capacity(), 8);
+        assert_eq!(alloc.available(), 8);
+        let vals: Vec<_> = (0..8).map(|i| alloc.allocate(i as u64).unwrap()).collect();
+        assert!(vals.iter().all(|val| alloc.owns(val)));
+        assert!(alloc.allocate(0u64).is_err());
+    }
+
+    #[test]
+    fn grow_around_foreign() {
+        let parent = Scoped::new(64).unwrap();
+        let mut alloc = FreeList::new_from(&parent, 16, 1).unwrap();
+        let other = parent.allocate([0u8; 16]).unwrap();
+        alloc.grow(1).unwrap();
+
+        // the parent's block lies between the list's two, but isn't one of them.
+        assert!(!alloc.owns(&other));
+        let vals: Vec<_> = (0..2).map(|i| alloc.allocate(i as u64).unwrap()).collect();
+        assert!(vals.iter().all(|val| alloc.owns(val)));
+    }
+
+    #[test]
+    fn partial_grow() {
+        let parent = Quota::new(HEAP, 100);
+        let mut alloc = FreeList::new_from(&parent, 32, 1).unwrap();
+        match alloc.grow(4) {
+            Err(Error::Incomplete { allocated, cause }) => {
+                assert_eq!(allocated, 2);
+                assert_eq!(*cause, Error::OutOfMemory);
+            }
+            _ => panic!("the quota should only fit three blocks"),
+        }
+        // the blocks that were added are kept.
+        assert_eq!(alloc.capacity(), 3);
+        assert_eq!(alloc.available(), 3);
+
+        assert_eq!(alloc.grow(1).err(), Some(Error::OutOfMemory));
+    }
+
+    #[test]
+    fn iter_free() {
+        let alloc = FreeList::new(16, 4).unwrap();
+        assert_eq!(alloc.iter_free().count(), 4);
+
+        let blocks: Vec<_> = (0..4).map(|_| unsafe { alloc.allocate_raw(16, 8).unwrap() }).collect();
+        assert_eq!(alloc.iter_free().next(), None);
+
+        let addrs: Vec<_> = blocks.iter().map(|block| block.ptr()).collect();
+        for block in blocks {
+            unsafe { alloc.deallocate_raw(block) };
+        }
+
+        // the last block freed is the first handed out.
+        let free: Vec<_> = alloc.iter_free().collect();
+        let expected: Vec<_> = addrs.into_iter().rev().collect();
+        assert_eq!(free, expected);
+    }
+
+    #[test]
+    #[should_panic]
+    #[cfg(debug_assertions)]
+    fn leak_detection() {
+        let alloc = FreeList::new(64, 4).unwrap();
+        unsafe { alloc.allocate_raw(8, 8).unwrap() };
+    }
+
+    #[test]
+    fn owning() {
+        let alloc = FreeList::new(64, 4).unwrap();
+
+        let val = alloc.allocate(1i32).unwrap();
+        assert!(alloc.owns(&val));
+
+        let heap_val = HEAP.allocate([0u8; 4096]).unwrap();
+        assert!(!alloc.owns(&heap_val));
+    }
+
+    #[test]
+    fn owning_whole_blocks() {
+        // the blocks sit right next to each other in the arena.
+        let arena = Scoped::new(64).unwrap();
+        let alloc = FreeList::new_from(&arena, 16, 4).unwrap();
+        unsafe {
+            let block = alloc.allocate_raw(16, 8).unwrap();
+            assert!(alloc.owns_block(&block));
+            // memory inside a block, or spanning two of them, isn't a block of the list.
+            assert!(!alloc.owns_block(&Block::new(block.ptr().offset(8), 8, 8)));
+            assert!(!alloc.owns_block(&Block::new(block.ptr(), 32, 8)));
+            alloc.deallocate_raw(block);
+        }
+    }
+
+    #[test]
+    fn fallback() {
+        let main = FreeList::new(64, 4).unwrap();
+        let fallback = Scoped::new(4096).unwrap();
+        let alloc = Fallback::new(&main, &fallback);
+
+        let small = alloc.allocate([0u8; 32]).unwrap();
+        assert!(main.owns(&small));
+
+        let big = alloc.allocate([0u8; 1024]).unwrap();
+        assert!(!main.owns(&big));
+        assert!(fallback.owns(&big));
+    }
+
+    #[test]
+    #[should_panic]
+    #[cfg(debug_assertions)]
+    fn foreign_dealloc() {
+        let alloc = FreeList::new(64, 4).unwrap();
+        let mut buf = [0usize; 8];
+        unsafe {
+            alloc.deallocate_raw(Block::new(buf.as_mut_ptr() as *mut u8, 64, 8));
+        }
+    }
+
+    #[test]
+    fn concurrent_stress() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let alloc = Arc::new(ConcurrentFreeList::new(64, 16).unwrap());
+        let handles: Vec<_> = (0..8u8).map(|id| {
+            let alloc = alloc.clone();
+            thread::spawn(move || {
+                for _ in 0..10_000 {
+                    unsafe {
+                        let block = alloc.allocate_raw(64, 8).unwrap();
+                        ptr::write_bytes(block.ptr(), id, 64);
+                        thread::yield_now();
+                        // a block handed out twice would have been overwritten.
+                        for i in 0..64 {
+                            assert_eq!(*block.ptr().offset(i), id);
+                        }
+                        alloc.deallocate_raw(block);
+                    }
+                }
+            })
+        }).collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // every block made it back.
+        let blocks: Vec<_> = (0..16).map(|_| alloc.allocate(0u64).unwrap()).collect();
+        assert!(alloc.allocate(0u64).is_err());
+        drop(blocks);
+    }
+
+    #[test]
+    fn usable_size() {
+        let alloc = FreeList::new(1024, 1).unwrap();
+        assert_eq!(alloc.usable_size(100, 8), 1024);
+        assert_eq!(alloc.usable_size(2048, 8), 2048);
+
+        let concurrent = ConcurrentFreeList::new(60, 1).unwrap();
+        // block sizes are rounded up to keep the blocks aligned.
+        assert_eq!(concurrent.usable_size(1, 1), 64);
+    }
+
+    #[test]
+    fn resize_in_place() {
+        let alloc = FreeList::new(1024, 1).unwrap();
+        unsafe {
+            let block = alloc.allocate_raw(100, 8).unwrap();
+            let ptr = block.ptr();
+            let block = alloc.grow_in_place(block, 1024).ok().unwrap();
+            assert_eq!((block.ptr(), block.size()), (ptr, 1024));
+
+            let (err, block) = alloc.grow_in_place(block, 1025).err().unwrap();
+            assert_eq!(err, Error::CannotResizeInPlace);
+
+            let block = alloc.shrink_in_place(block, 8).ok().unwrap();
+            assert_eq!((block.ptr(), block.size()), (ptr, 8));
+            alloc.deallocate_raw(block);
+        }
+    }
+
+    #[test]
+    fn realloc_in_place() {
+        let alloc = FreeList::new(1024, 1).unwrap();
+        unsafe {
+            let block = alloc.allocate_raw(8, 8).unwrap();
+            let ptr = block.ptr();
+            let block = alloc.reallocate_raw(block, 64).ok().unwrap();
+            assert_eq!(block.ptr(), ptr);
+            assert_eq!(block.size(), 64);
+
+            let (err, block) = alloc.reallocate_raw(block, 2048).err().unwrap();
+            assert_eq!(err, Error::OutOfMemory);
+
+            // shrinking to zero hands the block back to the list.
+            let block = alloc.reallocate_raw(block, 0).ok().unwrap();
+            assert!(block.is_empty());
+            assert!(alloc.allocate([0u8; 1024]).is_ok());
+        }
+    }
 }
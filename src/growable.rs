@@ -0,0 +1,299 @@
+//! A scoped linear allocator which grows by chaining chunks of memory.
+
+use std::cell::Cell;
+use std::cmp;
+use std::mem;
+use std::ptr;
+
+use super::{Allocator, Error, Block, BlockOwner, HeapAllocator, ScopeError, HEAP};
+
+// A header written at the start of every chunk.
+struct Chunk {
+    prev: *mut Chunk,
+    size: usize,
+}
+
+/// A scoped linear allocator which, instead of running out of memory,
+/// allocates another chunk from its parent when the current one is full.
+///
+/// Allocations never move: a request that doesn't fit in the current chunk
+/// is served from a new one, and the space left in the old chunk goes unused.
+/// Each new chunk is as large as the initial one, or large enough for the
+/// request, whichever is bigger. Chunks allocated within a scope are returned
+/// to the parent when that scope exits, and all of them when this is dropped.
+pub struct GrowableScoped<'parent, A: 'parent + Allocator> {
+    allocator: &'parent A,
+    chunk_size: usize,
+    chunk: Cell<*mut Chunk>,
+    current: Cell<*mut u8>,
+    end: Cell<*mut u8>,
+    root: bool,
+    // the chunk this scope started in, and where in it.
+    base_chunk: *mut Chunk,
+    start: *mut u8,
+}
+
+impl GrowableScoped<'static, HeapAllocator> {
+    /// Creates a new `GrowableScoped` backed by chunks of at least `size` bytes from the heap.
+    pub fn new(size: usize) -> Result<Self, Error> {
+        GrowableScoped::new_from(HEAP, size)
+    }
+}
+
+impl<'parent, A: Allocator> GrowableScoped<'parent, A> {
+    /// Creates a new `GrowableScoped` backed by chunks of at least `size` bytes
+    /// from the allocator supplied. The first chunk is allocated up front.
+    pub fn new_from(alloc: &'parent A, size: usize) -> Result<Self, Error> {
+        let chunk = match unsafe { new_chunk(alloc, ptr::null_mut(), size) } {
+            Ok(chunk) => chunk,
+            Err(err) => return Err(err),
+        };
+
+        let (start, end) = unsafe { chunk_bounds(chunk) };
+        Ok(GrowableScoped {
+            allocator: alloc,
+            chunk_size: size,
+            chunk: Cell::new(chunk),
+            current: Cell::new(start),
+            end: Cell::new(end),
+            root: true,
+            base_chunk: chunk,
+            start: start,
+        })
+    }
+
+    /// Calls the supplied function with a new scope of the allocator.
+    ///
+    /// Returns the result of the closure or an error if this allocator
+    /// has already been scoped.
+    pub fn scope<F, U>(&self, f: F) -> Result<U, ScopeError>
+        where F: FnMut(&Self) -> U
+    {
+        if self.is_scoped() {
+            return Err(ScopeError::AlreadyScoped);
+        }
+
+        let mut f = f;
+        let old = self.current.get();
+        let alloc = GrowableScoped {
+            allocator: self.allocator,
+            chunk_size: self.chunk_size,
+            chunk: self.chunk.clone(),
+            current: self.current.clone(),
+            end: self.end.clone(),
+            root: false,
+            base_chunk: self.chunk.get(),
+            start: old,
+        };
+
+        // set the current pointer to null as a flag to indicate
+        // that this allocator is being scoped.
+        self.current.set(ptr::null_mut());
+        let u = f(&alloc);
+        self.current.set(old);
+
+        // free every chunk the inner scope added.
+        unsafe { alloc.free_chunks_until(self.chunk.get()) };
+        mem::forget(alloc);
+        Ok(u)
+    }
+
+    // Whether this allocator is currently scoped.
+    pub fn is_scoped(&self) -> bool {
+        self.current.get().is_null()
+    }
+
+    // Returns every chunk newer than `last` to the parent.
+    unsafe fn free_chunks_until(&self, last: *mut Chunk) {
+        let mut chunk = self.chunk.get();
+        while chunk != last {
+            let prev = (*chunk).prev;
+            self.allocator.deallocate_raw(Block::new(chunk as *mut u8, (*chunk).size, mem::align_of::<Chunk>()));
+            chunk = prev;
+        }
+    }
+}
+
+// Allocates a chunk with room for at least `size` bytes after the header.
+unsafe fn new_chunk<A: Allocator>(alloc: &A, prev: *mut Chunk, size: usize) -> Result<*mut Chunk, Error> {
+    let size = match size.checked_add(mem::size_of::<Chunk>()) {
+        Some(size) => size,
+        None => return Err(Error::OutOfMemory),
+    };
+
+    match alloc.allocate_raw(size, mem::align_of::<Chunk>()) {
+        Ok(block) => {
+            let chunk = block.ptr() as *mut Chunk;
+            ptr::write(chunk, Chunk {
+                prev: prev,
+                size: size,
+            });
+            Ok(chunk)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+// The usable region of a chunk.
+unsafe fn chunk_bounds(chunk: *mut Chunk) -> (*mut u8, *mut u8) {
+    let start = (chunk as *mut u8).offset(mem::size_of::<Chunk>() as isize);
+    let end = (chunk as *mut u8).offset((*chunk).size as isize);
+    (start, end)
+}
+
+unsafe impl<'a, A: Allocator> Allocator for GrowableScoped<'a, A> {
+    unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        if self.is_scoped() {
+            return Err(Error::AlreadyScoped);
+        }
+
+        if size == 0 {
+            return Ok(Block::empty());
+        }
+
+        if let Some(aligned_ptr) = super::align_forward(self.current.get(), align) {
+            if (aligned_ptr as usize).checked_add(size).map_or(false, |end| end <= self.end.get() as usize) {
+                self.current.set(aligned_ptr.offset(size as isize));
+                return Ok(Block::new(aligned_ptr, size, align));
+            }
+        }
+
+        // make room for the request plus any padding needed to align it.
+        let needed = match size.checked_add(align - 1) {
+            Some(needed) => cmp::max(needed, self.chunk_size),
+            None => return Err(Error::OutOfMemory),
+        };
+        let chunk = match new_chunk(self.allocator, self.chunk.get(), needed) {
+            Ok(chunk) => chunk,
+            Err(err) => return Err(err),
+        };
+
+        let (start, end) = chunk_bounds(chunk);
+        // the chunk was sized to fit the request after alignment.
+        let aligned_ptr = super::align_forward(start, align).unwrap();
+        self.chunk.set(chunk);
+        self.current.set(aligned_ptr.offset(size as isize));
+        self.end.set(end);
+        Ok(Block::new(aligned_ptr, size, align))
+    }
+
+    /// Reallocating a block that is not the most recent, or one that doesn't
+    /// fit in the current chunk, will copy it into fresh space and waste the old.
+    unsafe fn reallocate_raw<'b>(&'b self, block: Block<'b>, new_size: usize) -> Result<Block<'b>, (Error, Block<'b>)> {
+        if new_size == 0 {
+            self.deallocate_raw(block);
+            return Ok(Block::empty());
+        } else if block.is_empty() {
+            return Err((Error::UnsupportedAlignment, block));
+        }
+
+        let current_ptr = self.current.get();
+        if !self.is_scoped() && block.ptr().offset(block.size() as isize) == current_ptr {
+            // this block is the last allocated, so resize it if it fits.
+            let fits = (block.ptr() as usize).checked_add(new_size)
+                                             .map_or(false, |end| end <= self.end.get() as usize);
+            if fits {
+                self.current.set(block.ptr().offset(new_size as isize));
+                return Ok(Block::new(block.ptr(), new_size, block.align()));
+            }
+        }
+
+        match self.allocate_raw(new_size, block.align()) {
+            Ok(new_block) => {
+                ptr::copy_nonoverlapping(block.ptr(), new_block.ptr(), cmp::min(block.size(), new_size));
+                Ok(new_block)
+            }
+            Err(err) => Err((err, block)),
+        }
+    }
+
+    unsafe fn deallocate_raw(&self, block: Block) {
+        if block.is_empty() || block.ptr().is_null() {
+            return;
+        }
+        // no op for this unless this is the last allocation.
+        // The memory gets reused when the scope is cleared.
+        let current_ptr = self.current.get();
+        if !self.is_scoped() && block.ptr().offset(block.size() as isize) == current_ptr {
+            self.current.set(block.ptr());
+        }
+    }
+}
+
+impl<'a, A: Allocator> BlockOwner for GrowableScoped<'a, A> {
+    fn owns_block(&self, block: &Block) -> bool {
+        let (ptr, size) = (block.ptr() as usize, block.size());
+        let within = |start: *mut u8, end: *mut u8| {
+            ptr >= start as usize && ptr + size <= end as usize
+        };
+
+        unsafe {
+            // every chunk added in this scope, and the part of the
+            // chunk it started in that it has allocated from.
+            let mut chunk = self.chunk.get();
+            while chunk != self.base_chunk {
+                let (start, end) = chunk_bounds(chunk);
+                if within(start, end) {
+                    return true;
+                }
+                chunk = (*chunk).prev;
+            }
+
+            within(self.start, chunk_bounds(self.base_chunk).1)
+        }
+    }
+}
+
+impl<'a, A: Allocator> Drop for GrowableScoped<'a, A> {
+    /// Drops the `GrowableScoped`, freeing every chunk.
+    fn drop(&mut self) {
+        if self.root {
+            unsafe { self.free_chunks_until(ptr::null_mut()) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+
+    use super::Chunk;
+    use super::super::*;
+
+    #[test]
+    fn grows() {
+        let alloc = GrowableScoped::new(64).unwrap();
+        let vals: Vec<_> = (0..100u64).map(|i| alloc.allocate(i).unwrap()).collect();
+        for (i, val) in vals.iter().enumerate() {
+            assert_eq!(**val, i as u64);
+        }
+
+        // bigger than a chunk.
+        let big = alloc.allocate([1u8; 1000]).unwrap();
+        assert_eq!(big[999], 1);
+        assert!(alloc.owns(&big));
+        assert!(alloc.owns(&vals[0]));
+    }
+
+    #[test]
+    fn scope_frees_chunks() {
+        let parent = FreeList::new(64 + mem::size_of::<Chunk>(), 4).unwrap();
+        let alloc = GrowableScoped::new_from(&parent, 64).unwrap();
+        assert_eq!(parent.available(), 3);
+
+        let outer = alloc.allocate(0u64).unwrap();
+        alloc.scope(|inner| {
+                 let vals: Vec<_> = (0..16u64).map(|i| inner.allocate(i).unwrap()).collect();
+                 // two more chunks were needed.
+                 assert_eq!(parent.available(), 1);
+                 assert!(!inner.owns(&outer));
+                 assert!(inner.owns(&vals[15]));
+             })
+             .unwrap();
+
+        assert_eq!(parent.available(), 3);
+        drop(outer);
+        drop(alloc);
+        assert_eq!(parent.available(), 4);
+    }
+}
@@ -40,32 +40,81 @@
 #![feature(
     alloc,
     coerce_unsized,
+    dropck_eyepatch,
+    generic_param_attrs,
     heap_api,
     placement_new_protocol,
     placement_in_syntax,
     raw,
+    specialization,
     unique,
     unsize,
 )]
+#![cfg_attr(test, feature(repr_align))]
 
 use std::error::Error as StdError;
 use std::fmt;
 use std::marker::PhantomData;
-use std::ptr::Unique;
+use std::mem;
+use std::ptr::{self, Unique};
+use std::slice;
 
 use alloc::heap;
 
 extern crate alloc;
+#[cfg(unix)]
+extern crate libc;
 
+pub mod affix;
+pub mod arena;
+pub mod atomic;
 mod boxed;
+pub mod bucketizer;
+pub mod buddy;
+pub mod coalescing;
 pub mod composable;
 pub mod freelist;
+pub mod growable;
+#[macro_use]
+pub mod local;
+pub mod loggers;
+#[cfg(unix)]
+pub mod mmap;
+pub mod oneshot;
+pub mod pool;
+pub mod recycler;
+pub mod ring;
 pub mod scoped;
+pub mod segregated;
+pub mod slab;
+pub mod stack;
+pub mod tracking;
+pub mod vec;
 
-pub use boxed::{AllocBox, Place};
+pub use affix::Affix;
+pub use arena::TypedArena;
+pub use atomic::AtomicScoped;
+pub use boxed::{allocate_in, collect_into, AllocBox, Place, SliceIntoIter};
+pub use bucketizer::Bucketizer;
+pub use buddy::BuddyAllocator;
+pub use coalescing::CoalescingAllocator;
 pub use composable::*;
-pub use freelist::FreeList;
-pub use scoped::Scoped;
+pub use freelist::{ConcurrentFreeList, FreeList};
+pub use growable::GrowableScoped;
+pub use local::ThreadScoped;
+pub use loggers::{AllocEvent, ChannelLogger, DebugLogger, HistogramLogger, Stats, StatsLogger, WriteLogger};
+#[cfg(unix)]
+pub use mmap::{GuardPageAllocator, MmapAllocator, GUARD_PAGES};
+pub use oneshot::OneShot;
+pub use pool::Pool;
+pub use recycler::Recycler;
+pub use ring::RingAllocator;
+pub use scoped::{DropScoped, ScopeError, Scoped};
+pub use segregated::SegregatedFreeList;
+pub use slab::SlabAllocator;
+pub use stack::StackAllocator;
+pub use tracking::TrackingAllocator;
+pub use vec::AllocVec;
 
 /// A custom memory allocator.
 pub unsafe trait Allocator {
@@ -92,6 +141,38 @@ pub unsafe trait Allocator {
         }
     }
 
+    /// Attempts to allocate a slice, cloning the values supplied into it.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use allocators::{Allocator, Scoped};
+    ///
+    /// let alloc = Scoped::new(64).unwrap();
+    /// let nums = alloc.allocate_slice(&[1, 2, 3]).unwrap();
+    /// assert_eq!(&*nums, &[1, 2, 3]);
+    /// ```
+    fn allocate_slice<T: Clone>(&self, vals: &[T]) -> Result<AllocBox<[T], Self>, Error>
+    where Self: Sized
+    {
+        boxed::allocate_slice(self, vals)
+    }
+
+    /// Attempts to allocate a copy of the string supplied.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use allocators::{Allocator, Scoped};
+    ///
+    /// let alloc = Scoped::new(64).unwrap();
+    /// let name = alloc.allocate_str("allocators").unwrap();
+    /// assert_eq!(&*name, "allocators");
+    /// ```
+    fn allocate_str(&self, s: &str) -> Result<AllocBox<str, Self>, Error>
+    where Self: Sized
+    {
+        boxed::allocate_str(self, s)
+    }
+
     /// Attempts to create a place to allocate into.
     /// For the general purpose, calling `allocate` on the allocator is enough.
     /// However, when you know the value you are allocating is too large
@@ -111,7 +192,72 @@ pub unsafe trait Allocator {
     {
         boxed::make_place(self)
     }
-    
+
+    /// Attempts to create a place to allocate into, aligned to at least `align`,
+    /// like placing a value on a cache line of its own.
+    /// The alignment of `T` is used if it is greater.
+    /// Fails with `UnsupportedAlignment` if `align` is not a power of two.
+    ///
+    /// # Examples
+    /// ```rust
+    /// #![feature(placement_in_syntax)]
+    /// use allocators::{Allocator, Scoped};
+    ///
+    /// let alloc = Scoped::new(128).unwrap();
+    /// let val = in alloc.make_place_aligned(64).unwrap() { 5i32 };
+    /// assert_eq!(&*val as *const i32 as usize % 64, 0);
+    /// ```
+    fn make_place_aligned<T>(&self, align: usize) -> Result<Place<T, Self>, Error>
+    where Self: Sized
+    {
+        boxed::make_place_aligned(self, align)
+    }
+
+    /// Attempts to allocate a block of memory for `len` contiguous values of type `T`,
+    /// aligned for `T`. The values are left uninitialized.
+    ///
+    /// This is `allocate_raw` with the size and alignment worked out, so
+    /// the block is deallocated with `deallocate_raw` like any other.
+    /// Allocators with fixed block sizes, like `FreeList`, only serve arrays
+    /// which fit in one of their blocks.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use allocators::{Allocator, Scoped};
+    ///
+    /// let alloc = Scoped::new(64).unwrap();
+    /// unsafe {
+    ///     let block = alloc.allocate_array::<u32>(10).unwrap();
+    ///     assert_eq!(block.size(), 40);
+    ///     alloc.deallocate_raw(block);
+    /// }
+    /// ```
+    ///
+    /// # Safety
+    /// The same as for `allocate_raw`.
+    unsafe fn allocate_array<T>(&self, len: usize) -> Result<Block, Error>
+    where Self: Sized
+    {
+        match mem::size_of::<T>().checked_mul(len) {
+            Some(size) => self.allocate_raw(size, mem::align_of::<T>()),
+            None => Err(Error::OutOfMemory),
+        }
+    }
+
+    /// Reallocates a block allocated with `allocate_array` to hold `new_len` values of type `T`.
+    ///
+    /// # Safety
+    /// The same as for `reallocate_raw`. The block must have been allocated for values of type `T`.
+    unsafe fn reallocate_array<'a, T>(&'a self, block: Block<'a>, new_len: usize) -> Result<Block<'a>, (Error, Block<'a>)>
+    where Self: Sized
+    {
+        debug_assert!(block.is_empty() || block.align() == mem::align_of::<T>());
+        match mem::size_of::<T>().checked_mul(new_len) {
+            Some(new_size) => self.reallocate_raw(block, new_size),
+            None => Err((Error::OutOfMemory, block)),
+        }
+    }
+
     /// Attempt to allocate a block of memory.
     ///
     /// Returns either a block of memory allocated
@@ -124,6 +270,40 @@ pub unsafe trait Allocator {
     /// It is undefined behavior to provide a non power-of-two align.
     unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error>;
 
+    /// Attempt to allocate a block of memory filled with zeroes.
+    ///
+    /// By default, this allocates a block with `allocate_raw` and clears it.
+    /// Allocators which know some of their memory to be zeroed already
+    /// can skip that.
+    ///
+    /// # Safety
+    /// The same as for `allocate_raw`.
+    unsafe fn allocate_zeroed_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        match self.allocate_raw(size, align) {
+            Ok(block) => {
+                if !block.is_empty() {
+                    ptr::write_bytes(block.ptr(), 0, block.size());
+                }
+                Ok(block)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Attempt to allocate a block of memory at least `size` bytes large,
+    /// which covers all of the memory set aside for the request.
+    ///
+    /// Allocators which round requests up, like `FreeList`, return a block of the
+    /// size they rounded to, so the slack can be used, say to grow a buffer into
+    /// without reallocating. The block is deallocated or reallocated as returned.
+    /// By default, this is the same as `allocate_raw`.
+    ///
+    /// # Safety
+    /// The same as for `allocate_raw`.
+    unsafe fn allocate_excess_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        self.allocate_raw(size, align)
+    }
+
     /// Reallocate a block of memory.
     ///
     /// This either returns a new, possibly moved block with the requested size,
@@ -142,12 +322,51 @@ pub unsafe trait Allocator {
     /// # Safety
     /// This block must have been allocated by this allocator.
     unsafe fn deallocate_raw(&self, block: Block);
+
+    /// The number of bytes actually available in a block allocated with the size and alignment
+    /// given. Allocators which round requests up can report it here, so that callers
+    /// can make use of the extra room, growing the block in place with `reallocate_raw`.
+    ///
+    /// Defaults to the size requested.
+    fn usable_size(&self, size: usize, _align: usize) -> usize {
+        size
+    }
+
+    /// Attempts to grow a block to `new_size` bytes without moving it.
+    /// Returns the block back if that's not possible.
+    ///
+    /// By default, this always fails.
+    ///
+    /// # Safety
+    /// `new_size` must not be smaller than the block's size, and the block
+    /// must have been allocated by this allocator.
+    unsafe fn grow_in_place<'a>(&'a self, block: Block<'a>, new_size: usize) -> Result<Block<'a>, (Error, Block<'a>)> {
+        debug_assert!(new_size >= block.size());
+        let _ = new_size;
+        Err((Error::CannotResizeInPlace, block))
+    }
+
+    /// Attempts to shrink a block to `new_size` bytes without moving it.
+    /// Returns the block back if that's not possible.
+    ///
+    /// By default, this always fails.
+    ///
+    /// # Safety
+    /// `new_size` must not be larger than the block's size, nor 0, and the block
+    /// must have been allocated by this allocator.
+    unsafe fn shrink_in_place<'a>(&'a self, block: Block<'a>, new_size: usize) -> Result<Block<'a>, (Error, Block<'a>)> {
+        debug_assert!(new_size <= block.size());
+        let _ = new_size;
+        Err((Error::CannotResizeInPlace, block))
+    }
 }
 
 /// An allocator that knows which blocks have been issued by it.
 pub trait BlockOwner: Allocator {
     /// Whether this allocator owns this allocated value. 
-    fn owns<'a, T, A: Allocator>(&self, val: &AllocBox<'a, T, A>) -> bool {
+    fn owns<'a, T, A: Allocator>(&self, val: &AllocBox<'a, T, A>) -> bool
+        where Self: Sized
+    {
         self.owns_block(& unsafe { val.as_block() })
     }
 
@@ -179,8 +398,13 @@ impl<'a> Block<'a> {
     ///
     /// # Panics
     /// Panics if the pointer passed is null.
+    /// With debug assertions enabled, also panics if it is not aligned to `align`.
     pub fn new(ptr: *mut u8, size: usize, align: usize) -> Self {
         assert!(!ptr.is_null());
+        debug_assert!(align == 0 || ptr as usize % align == 0,
+                      "Block pointer {:p} is not aligned to {}.",
+                      ptr,
+                      align);
         Block {
             ptr: unsafe { Unique::new(ptr) },
             size: size,
@@ -215,23 +439,91 @@ impl<'a> Block<'a> {
     pub fn is_empty(&self) -> bool {
         self.size == 0
     }
+
+    /// Views the memory of this block as a byte slice.
+    ///
+    /// # Safety
+    /// The memory may not be initialized yet, and the block must not have been deallocated.
+    pub unsafe fn as_slice(&self) -> &[u8] {
+        slice::from_raw_parts(self.ptr(), self.size)
+    }
+
+    /// Views the memory of this block as a mutable byte slice.
+    ///
+    /// # Safety
+    /// The memory may not be initialized yet, and the block must not have been deallocated.
+    pub unsafe fn as_mut_slice(&mut self) -> &mut [u8] {
+        slice::from_raw_parts_mut(self.ptr(), self.size)
+    }
+
+    /// Splits this block in two at `offset`. The first block covers the bytes
+    /// before the offset, and the second the bytes from it onwards. Both keep this block's alignment.
+    ///
+    /// Returns `None` if either block would be empty, or the second would not be aligned.
+    pub fn split(self, offset: usize) -> Option<(Block<'a>, Block<'a>)> {
+        if offset == 0 || offset >= self.size {
+            return None;
+        }
+
+        let second = (self.ptr() as usize).wrapping_add(offset);
+        if second % self.align != 0 {
+            return None;
+        }
+
+        Some((Block::new(self.ptr(), offset, self.align),
+              Block::new(second as *mut u8, self.size - offset, self.align)))
+    }
 }
 
 /// Errors that can occur while creating an allocator
 /// or allocating from it.
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Error {
     /// The allocator failed to allocate the amount of memory requested of it.
     OutOfMemory,
     /// The allocator does not support the requested alignment.
     UnsupportedAlignment,
+    /// The allocator is currently scoped, and can't be used until the scope ends.
+    AlreadyScoped,
+    /// The block was not allocated by the allocator, or any of those it delegates to.
+    NotOwned,
+    /// The block could not be resized without moving it.
+    CannotResizeInPlace,
+    /// An allocator needing several blocks from its parent only got some of them
+    /// before the parent failed with `cause`. The blocks it did get were freed again,
+    /// unless the operation says otherwise, like `FreeList::grow`.
+    /// If the parent fails on the first block, its own error is returned instead.
+    Incomplete {
+        /// The number of blocks allocated before the failure.
+        allocated: usize,
+        /// The error the parent failed with.
+        cause: Box<Error>,
+    },
+    /// An allocator couldn't get its buffer of `size` bytes, aligned to `align`,
+    /// because its parent failed with `cause`.
+    NoBuffer {
+        /// The size of the buffer requested.
+        size: usize,
+        /// The alignment of the buffer requested.
+        align: usize,
+        /// The error the parent failed with.
+        cause: Box<Error>,
+    },
     /// An allocator-specific error message.
     AllocatorSpecific(String),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter.write_str(self.description())
+        match *self {
+            Error::Incomplete { allocated, ref cause } => {
+                write!(formatter, "Only {} blocks could be allocated: {}", allocated, cause)
+            }
+            Error::NoBuffer { size, align, ref cause } => {
+                write!(formatter, "Could not allocate a buffer of {} bytes aligned to {}: {}", size, align, cause)
+            }
+            _ => formatter.write_str(self.description()),
+        }
     }
 }
 
@@ -246,6 +538,21 @@ impl StdError for Error {
             UnsupportedAlignment => {
                 "Attempted to allocate with unsupported alignment."
             }
+            AlreadyScoped => {
+                "Called allocate on already scoped allocator."
+            }
+            NotOwned => {
+                "The block is not owned by this allocator."
+            }
+            CannotResizeInPlace => {
+                "The block could not be resized in place."
+            }
+            Incomplete { .. } => {
+                "Only some of the blocks needed could be allocated."
+            }
+            NoBuffer { .. } => {
+                "The buffer of the allocator could not be allocated."
+            }
             AllocatorSpecific(ref reason) => {
                 reason
             }
@@ -257,7 +564,7 @@ impl StdError for Error {
 /// It is recommended to use the `HEAP` constant instead
 /// of creating a new instance of this, to benefit from
 /// the static lifetime that it provides.
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct HeapAllocator;
 
 // A constant for allocators to use the heap as a root.
@@ -279,6 +586,20 @@ unsafe impl Allocator for HeapAllocator {
         }
     }
 
+    #[inline]
+    unsafe fn allocate_zeroed_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        if size != 0 {
+            let ptr = heap::allocate_zeroed(size, align);
+            if !ptr.is_null() {
+                Ok(Block::new(ptr, size, align))
+            } else {
+                Err(Error::OutOfMemory)
+            }
+        } else {
+            Ok(Block::empty())
+        }
+    }
+
     #[inline]
     unsafe fn reallocate_raw<'a>(&'a self, block: Block<'a>, new_size: usize) -> Result<Block<'a>, (Error, Block<'a>)> {
         if new_size == 0 {
@@ -306,9 +627,10 @@ unsafe impl Allocator for HeapAllocator {
 }
 
 // aligns a pointer forward to the next value aligned with `align`.
+// returns `None` if that would overflow the address space.
 #[inline]
-fn align_forward(ptr: *mut u8, align: usize) -> *mut u8 {
-    ((ptr as usize + align - 1) & !(align - 1)) as *mut u8
+fn align_forward(ptr: *mut u8, align: usize) -> Option<*mut u8> {
+    (ptr as usize).checked_add(align - 1).map(|addr| (addr & !(align - 1)) as *mut u8)
 }
 
 // implementations for trait object types.
@@ -318,6 +640,14 @@ unsafe impl<'a, A: ?Sized + Allocator + 'a> Allocator for Box<A> {
         (**self).allocate_raw(size, align)
     }
 
+    unsafe fn allocate_zeroed_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        (**self).allocate_zeroed_raw(size, align)
+    }
+
+    unsafe fn allocate_excess_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        (**self).allocate_excess_raw(size, align)
+    }
+
     unsafe fn reallocate_raw<'b>(&'b self, block: Block<'b>, new_size: usize) -> Result<Block<'b>, (Error, Block<'b>)> {
         (**self).reallocate_raw(block, new_size)
     }
@@ -325,6 +655,18 @@ unsafe impl<'a, A: ?Sized + Allocator + 'a> Allocator for Box<A> {
     unsafe fn deallocate_raw(&self, block: Block) {
         (**self).deallocate_raw(block)
     }
+
+    fn usable_size(&self, size: usize, align: usize) -> usize {
+        (**self).usable_size(size, align)
+    }
+
+    unsafe fn grow_in_place<'c>(&'c self, block: Block<'c>, new_size: usize) -> Result<Block<'c>, (Error, Block<'c>)> {
+        (**self).grow_in_place(block, new_size)
+    }
+
+    unsafe fn shrink_in_place<'c>(&'c self, block: Block<'c>, new_size: usize) -> Result<Block<'c>, (Error, Block<'c>)> {
+        (**self).shrink_in_place(block, new_size)
+    }
 }
 
 unsafe impl<'a, 'b: 'a, A: ?Sized + Allocator + 'b> Allocator for &'a A {
@@ -332,6 +674,14 @@ unsafe impl<'a, 'b: 'a, A: ?Sized + Allocator + 'b> Allocator for &'a A {
         (**self).allocate_raw(size, align)
     }
 
+    unsafe fn allocate_zeroed_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        (**self).allocate_zeroed_raw(size, align)
+    }
+
+    unsafe fn allocate_excess_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        (**self).allocate_excess_raw(size, align)
+    }
+
     unsafe fn reallocate_raw<'c>(&'c self, block: Block<'c>, new_size: usize) -> Result<Block<'c>, (Error, Block<'c>)> {
         (**self).reallocate_raw(block, new_size)
     }
@@ -339,6 +689,18 @@ unsafe impl<'a, 'b: 'a, A: ?Sized + Allocator + 'b> Allocator for &'a A {
     unsafe fn deallocate_raw(&self, block: Block) {
         (**self).deallocate_raw(block)
     }
+
+    fn usable_size(&self, size: usize, align: usize) -> usize {
+        (**self).usable_size(size, align)
+    }
+
+    unsafe fn grow_in_place<'c>(&'c self, block: Block<'c>, new_size: usize) -> Result<Block<'c>, (Error, Block<'c>)> {
+        (**self).grow_in_place(block, new_size)
+    }
+
+    unsafe fn shrink_in_place<'c>(&'c self, block: Block<'c>, new_size: usize) -> Result<Block<'c>, (Error, Block<'c>)> {
+        (**self).shrink_in_place(block, new_size)
+    }
 }
 
 unsafe impl<'a, 'b: 'a, A: ?Sized + Allocator + 'b> Allocator for &'a mut A {
@@ -346,6 +708,14 @@ unsafe impl<'a, 'b: 'a, A: ?Sized + Allocator + 'b> Allocator for &'a mut A {
         (**self).allocate_raw(size, align)
     }
 
+    unsafe fn allocate_zeroed_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        (**self).allocate_zeroed_raw(size, align)
+    }
+
+    unsafe fn allocate_excess_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        (**self).allocate_excess_raw(size, align)
+    }
+
     unsafe fn reallocate_raw<'c>(&'c self, block: Block<'c>, new_size: usize) -> Result<Block<'c>, (Error, Block<'c>)> {
         (**self).reallocate_raw(block, new_size)
     }
@@ -353,6 +723,24 @@ unsafe impl<'a, 'b: 'a, A: ?Sized + Allocator + 'b> Allocator for &'a mut A {
     unsafe fn deallocate_raw(&self, block: Block) {
         (**self).deallocate_raw(block)
     }
+
+    fn usable_size(&self, size: usize, align: usize) -> usize {
+        (**self).usable_size(size, align)
+    }
+
+    unsafe fn grow_in_place<'c>(&'c self, block: Block<'c>, new_size: usize) -> Result<Block<'c>, (Error, Block<'c>)> {
+        (**self).grow_in_place(block, new_size)
+    }
+
+    unsafe fn shrink_in_place<'c>(&'c self, block: Block<'c>, new_size: usize) -> Result<Block<'c>, (Error, Block<'c>)> {
+        (**self).shrink_in_place(block, new_size)
+    }
+}
+
+impl<'a, 'b: 'a, A: ?Sized + BlockOwner + 'b> BlockOwner for &'a A {
+    fn owns_block(&self, block: &Block) -> bool {
+        (**self).owns_block(block)
+    }
 }
 
 #[cfg(test)]
@@ -371,6 +759,218 @@ mod tests {
 
         assert_eq!(*my_int, 0);
     }
+
+    #[test]
+    fn block_slices() {
+        unsafe {
+            let mut block = HEAP.allocate_raw(16, 1).unwrap();
+            for (i, byte) in block.as_mut_slice().iter_mut().enumerate() {
+                *byte = i as u8;
+            }
+            assert_eq!(block.as_slice().len(), 16);
+            assert_eq!(block.as_slice()[15], 15);
+            assert_eq!(*block.ptr().offset(3), 3);
+            HEAP.deallocate_raw(block);
+
+            assert!(Block::empty().as_slice().is_empty());
+        }
+    }
+
+    #[test]
+    fn block_split() {
+        let mut buf = [0u64; 4];
+        let ptr = buf.as_mut_ptr() as *mut u8;
+
+        let (first, second) = Block::new(ptr, 32, 8).split(8).unwrap();
+        assert_eq!((first.ptr(), first.size(), first.align()), (ptr, 8, 8));
+        assert_eq!((second.ptr() as usize - ptr as usize, second.size(), second.align()), (8, 24, 8));
+
+        assert!(Block::new(ptr, 32, 8).split(0).is_none());
+        assert!(Block::new(ptr, 32, 8).split(32).is_none());
+        assert!(Block::new(ptr, 32, 8).split(4).is_none());
+        assert!(Block::empty().split(0).is_none());
+        assert!(Block::new(ptr, 32, 1).split(3).is_some());
+    }
+
+    #[test]
+    #[should_panic]
+    #[cfg(debug_assertions)]
+    fn misaligned_block() {
+        let mut buf = [0u64; 2];
+        let ptr = buf.as_mut_ptr() as *mut u8;
+        Block::new(unsafe { ptr.offset(1) }, 8, 8);
+    }
+
+    #[test]
+    fn allocate_in_unsized() {
+        let alloc: Box<Allocator> = Box::new(Scoped::new(8).unwrap());
+        let val = allocate_in(&*alloc, 5u64).ok().unwrap();
+        assert_eq!(*val, 5);
+        assert_eq!(allocate_in(&*alloc, 7u64).err().unwrap(), (Error::OutOfMemory, 7));
+    }
+
+    #[test]
+    fn try_allocate_retry() {
+        let small = Scoped::new(16).unwrap();
+        let large = Scoped::new(1024).unwrap();
+        let val = match AllocBox::try_allocate(&small, [7u8; 512]) {
+            Ok(_) => panic!("allocation should not fit"),
+            Err((err, val)) => {
+                assert_eq!(err, Error::OutOfMemory);
+                // retry with the value we got back.
+                AllocBox::try_allocate(&large, val).ok().unwrap()
+            }
+        };
+        assert!(large.owns(&val));
+        assert_eq!(val[511], 7);
+    }
+
+    #[test]
+    fn unsized_layout() {
+        use std::any::Any;
+        use std::cell::RefCell;
+
+        // records the layout of every block freed.
+        struct Freed(RefCell<Vec<(usize, usize)>>);
+        impl ProxyLogger for Freed {
+            fn allocate_success(&self, _: &Block) {}
+            fn allocate_fail(&self, _: &Error, _: usize, _: usize) {}
+            fn deallocate(&self, block: &Block) {
+                self.0.borrow_mut().push((block.size(), block.align()));
+            }
+            fn reallocate_success(&self, _: &Block, _: &Block) {}
+            fn reallocate_fail(&self, _: &Error, _: &Block, _: usize) {}
+        }
+
+        let alloc = Proxy::new(HEAP, Freed(RefCell::new(Vec::new())));
+        let bytes: AllocBox<[u8], _> = alloc.allocate([0u8; 1000]).ok().unwrap();
+        assert_eq!(bytes.len(), 1000);
+        drop(bytes);
+
+        let any: AllocBox<Any, _> = alloc.allocate(5u16).ok().unwrap();
+        let num = any.downcast::<u16>().ok().unwrap();
+        assert_eq!(num.take(), 5);
+
+        assert_eq!(&*alloc.logger().0.borrow(), &[(1000, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn clone_box() {
+        let alloc = Scoped::new(256).unwrap();
+        let original = alloc.allocate(vec![1, 2, 3]).unwrap();
+        let mut cloned = original.clone();
+        cloned.push(4);
+        assert_eq!(*original, vec![1, 2, 3]);
+        assert_eq!(*cloned, vec![1, 2, 3, 4]);
+        assert!(alloc.owns(&cloned));
+    }
+
+    #[test]
+    #[should_panic(expected = "Failed to clone an AllocBox: OutOfMemory")]
+    fn clone_box_oom() {
+        let alloc = Scoped::new(8).unwrap();
+        let val = alloc.allocate(0u64).unwrap();
+        let _ = val.clone();
+    }
+
+    #[test]
+    fn arrays() {
+        let alloc = Scoped::new(128).unwrap();
+        unsafe {
+            let _pad = alloc.allocate_raw(1, 1).unwrap();
+            let block = alloc.allocate_array::<i32>(10).unwrap();
+            assert_eq!(block.size(), 40);
+            assert_eq!(block.ptr() as usize % mem::align_of::<i32>(), 0);
+
+            let nums = slice::from_raw_parts_mut(block.ptr() as *mut i32, 10);
+            for (i, num) in nums.iter_mut().enumerate() {
+                *num = i as i32;
+            }
+
+            let block = alloc.reallocate_array::<i32>(block, 20).ok().unwrap();
+            assert_eq!(block.size(), 80);
+            assert_eq!(*(block.ptr() as *const i32).offset(9), 9);
+            alloc.deallocate_raw(block);
+
+            assert_eq!(alloc.allocate_array::<u64>(usize::max_value()).err(), Some(Error::OutOfMemory));
+        }
+
+        // a free list only serves arrays that fit in a block.
+        let list = FreeList::new(16, 2).unwrap();
+        unsafe {
+            let block = list.allocate_array::<u32>(4).unwrap();
+            assert!(list.allocate_array::<u32>(5).is_err());
+            list.deallocate_raw(block);
+        }
+    }
+
+    #[test]
+    fn map_in_place() {
+        let alloc = TrackingAllocator::new(HEAP);
+        {
+            let val = alloc.allocate(7u64).unwrap();
+            let addr = &*val as *const u64 as usize;
+            let val = val.map(|v| (v as u32, v as u16));
+            assert_eq!(&*val as *const (u32, u16) as usize, addr);
+            assert_eq!(*val, (7, 7));
+
+            let text = alloc.allocate(5u8).unwrap().map(|v| v.to_string());
+            assert_eq!(*text, "5");
+        }
+        // the blocks were freed with the layouts they were allocated with.
+        alloc.assert_no_leaks();
+    }
+
+    #[test]
+    fn map_realloc() {
+        let alloc = Scoped::new(64).unwrap();
+        let bytes = alloc.allocate([1u8; 4]).unwrap();
+        let nums = bytes.map(|b| [b[0] as u64; 4]);
+        assert_eq!(*nums, [1; 4]);
+        assert!(alloc.owns(&nums));
+
+        let unit = alloc.allocate(()).unwrap();
+        let num = unit.map(|_| 3u32);
+        assert_eq!(*num, 3);
+    }
+
+    #[test]
+    fn defaults() {
+        let _: NullAllocator = Default::default();
+
+        let scoped: Scoped<_> = Default::default();
+        assert_eq!(scoped.bytes_remaining(), scoped::DEFAULT_SCOPED_SIZE);
+
+        let list: FreeList<_> = Default::default();
+        assert_eq!(list.capacity(), freelist::DEFAULT_NUM_BLOCKS);
+        assert_eq!(list.usable_size(1, 1), freelist::DEFAULT_BLOCK_SIZE);
+
+        let fallback: Fallback<FreeList<_>, Scoped<_>> = Default::default();
+        let val = fallback.allocate([0u8; 2048]).unwrap();
+        assert_eq!(fallback.served_by(&unsafe { val.as_block() }), Some(FallbackSource::Fallback));
+
+        let proxy: Proxy<HeapAllocator, StatsLogger> = Default::default();
+        let _val = proxy.allocate(0u32).unwrap();
+        assert_eq!(proxy.logger().snapshot().allocations, 1);
+    }
+
+    #[test]
+    fn into_std_box() {
+        let val = HEAP.allocate([3u64; 16]).unwrap();
+        let addr = &*val as *const _ as usize;
+        let boxed: Box<[u64; 16]> = val.into_std_box();
+        // the heap's block is handed over as is.
+        assert_eq!(&*boxed as *const _ as usize, addr);
+        assert_eq!(boxed[15], 3);
+
+        let alloc = Scoped::new(64).unwrap();
+        let val = alloc.allocate(vec![1, 2, 3]).unwrap();
+        let boxed = val.into_std_box();
+        assert_eq!(*boxed, vec![1, 2, 3]);
+        // the value was moved out, and its memory given back.
+        assert_eq!(alloc.bytes_used(), 0);
+    }
+
     #[test]
     fn heap_in_place() {
         let big = in HEAP.make_place().unwrap() { [0u8; 8_000_000] };
@@ -391,11 +991,244 @@ mod tests {
         let _: AllocBox<Bomb, _> = my_foo.downcast().ok().unwrap();
     }
 
+    #[test]
+    fn unsizing_debug() {
+        use std::fmt::Debug;
+
+        let alloc = Scoped::new(64).unwrap();
+        let val: AllocBox<Debug, _> = alloc.allocate(42i32).unwrap();
+        assert_eq!(format!("{:?}", &*val), "42");
+    }
+
+    #[test]
+    fn raw_round_trip() {
+        let alloc = Scoped::new(64).unwrap();
+        let val = alloc.allocate(vec![1, 2, 3]).unwrap();
+
+        let (ptr, block) = val.into_raw();
+        assert_eq!(block.size(), ::std::mem::size_of::<Vec<i32>>());
+        unsafe { (*ptr).push(4) };
+
+        let val = unsafe { AllocBox::from_raw(ptr, block, &alloc) };
+        assert_eq!(*val, [1, 2, 3, 4]);
+        drop(val);
+        assert_eq!(alloc.bytes_used(), 0);
+    }
+
+    #[test]
+    fn leaking() {
+        struct Node<'a> {
+            val: i32,
+            next: Option<&'a Node<'a>>,
+        }
+
+        let alloc = Scoped::new(256).unwrap();
+        let mut head: Option<&Node> = None;
+        for i in 0..5 {
+            let node = alloc.allocate(Node { val: i, next: head }).ok().unwrap().leak();
+            head = Some(node);
+        }
+
+        let mut vals = Vec::new();
+        while let Some(node) = head {
+            vals.push(node.val);
+            head = node.next;
+        }
+        assert_eq!(vals, [4, 3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn forwarded_traits() {
+        use std::collections::HashSet;
+
+        let alloc = Scoped::new(64).unwrap();
+        let a = alloc.allocate(1i32).unwrap();
+        let b = HEAP.allocate(1i32).unwrap();
+        let c = alloc.allocate(2i32).unwrap();
+
+        assert_eq!(a, b);
+        assert!(a != c);
+        assert!(a < c);
+        assert_eq!(format!("{:?} {}", a, c), "1 2");
+
+        let mut set = HashSet::new();
+        assert!(set.insert(a));
+        assert!(set.insert(c));
+        assert!(!set.insert(alloc.allocate(1i32).unwrap()));
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn slices() {
+        use std::rc::Rc;
+
+        let alloc = Scoped::new(256).unwrap();
+        let counter = Rc::new(());
+        let strings = ["a".to_string(), "b".to_string(), "c".to_string()];
+        let rcs = vec![counter.clone(); 4];
+        {
+            let slice = alloc.allocate_slice(&strings).unwrap();
+            assert_eq!(&*slice, &strings);
+
+            let rc_slice = alloc.allocate_slice(&rcs).unwrap();
+            assert_eq!(rc_slice.len(), 4);
+            assert_eq!(Rc::strong_count(&counter), 9);
+
+            let empty = alloc.allocate_slice::<String>(&[]).unwrap();
+            assert!(empty.is_empty());
+        }
+        // dropping the slice dropped every element.
+        assert_eq!(Rc::strong_count(&counter), 5);
+        assert_eq!(alloc.bytes_used(), 0);
+    }
+
+    #[test]
+    fn collect() {
+        use std::rc::Rc;
+
+        let alloc = Scoped::new(256).unwrap();
+        let nums = collect_into(&alloc, 0..10u32).unwrap();
+        assert_eq!(nums.len(), 10);
+        assert_eq!(nums[9], 9);
+        assert_eq!(alloc.bytes_used(), 40);
+
+        let counter = Rc::new(());
+        {
+            let strings = collect_into(&alloc, (1..4).map(|i| "x".repeat(i))).unwrap();
+            assert_eq!(&*strings, &["x".to_string(), "xx".to_string(), "xxx".to_string()]);
+
+            let rcs = collect_into(&alloc, vec![counter.clone(); 4]).unwrap();
+            assert_eq!(rcs.len(), 4);
+            assert_eq!(Rc::strong_count(&counter), 5);
+        }
+        // the elements were dropped along with the slices.
+        assert_eq!(Rc::strong_count(&counter), 1);
+        assert_eq!(alloc.bytes_used(), 40);
+
+        assert!(collect_into(&alloc, 0..100u32).is_err());
+    }
+
+    #[test]
+    fn aligned_place() {
+        let alloc = Scoped::new(128).unwrap();
+        let _pad = alloc.allocate(0u8).unwrap();
+        {
+            let val = in alloc.make_place_aligned(64).unwrap() { 7i32 };
+            assert_eq!(&*val as *const i32 as usize % 64, 0);
+            assert_eq!(*val, 7);
+            // the box is freed with the alignment it was allocated with.
+            assert_eq!(unsafe { val.as_block() }.align(), 64);
+        }
+
+        // a type's own alignment is kept if it is larger.
+        let val = in HEAP.make_place_aligned(1).unwrap() { 0u64 };
+        assert_eq!(unsafe { val.as_block() }.align(), 8);
+        assert_eq!(HEAP.make_place_aligned::<u8>(3).err(), Some(Error::UnsupportedAlignment));
+
+        // zero-sized values get an address with the alignment asked for, too.
+        let unit = in HEAP.make_place_aligned(64).unwrap() { () };
+        assert_eq!(&*unit as *const () as usize % 64, 0);
+    }
+
+    #[test]
+    fn as_ref() {
+        fn shout<S: AsRef<str>>(s: S) -> String {
+            s.as_ref().to_uppercase()
+        }
+
+        fn zero<S: AsMut<[u8]>>(mut s: S) {
+            for byte in s.as_mut() {
+                *byte = 0;
+            }
+        }
+
+        let alloc = Scoped::new(64).unwrap();
+        let greeting = alloc.allocate_str("hello").unwrap();
+        assert_eq!(shout(&*greeting), "HELLO");
+        assert_eq!(shout(greeting), "HELLO");
+        assert_eq!(alloc.bytes_used(), 0);
+        assert!(alloc.allocate_str("").unwrap().is_empty());
+
+        let mut bytes = alloc.allocate_slice(&[1u8, 2, 3]).unwrap();
+        zero(&mut bytes);
+        assert_eq!(&*bytes, &[0, 0, 0]);
+    }
+
+    #[test]
+    fn slice_into_iter() {
+        use std::rc::Rc;
+
+        let alloc = Scoped::new(256).unwrap();
+        let mut strings = collect_into(&alloc, (0..4).map(|i| i.to_string())).unwrap();
+        for s in &mut strings {
+            s.push('!');
+        }
+        let mut joined = String::new();
+        for s in &strings {
+            joined.push_str(s);
+        }
+        assert_eq!(joined, "0!1!2!3!");
+        let moved: Vec<String> = strings.into_iter().rev().collect();
+        assert_eq!(moved, ["3!", "2!", "1!", "0!"]);
+        assert_eq!(alloc.bytes_used(), 0);
+
+        // stopping early drops the rest, and still frees the block.
+        let counter = Rc::new(());
+        let rcs = alloc.allocate_slice(&vec![counter.clone(); 5]).unwrap();
+        assert_eq!(Rc::strong_count(&counter), 6);
+        {
+            let mut iter = rcs.into_iter();
+            assert_eq!(iter.len(), 5);
+            let first = iter.next().unwrap();
+            let _ = iter.next();
+            assert_eq!(iter.len(), 3);
+            drop(iter);
+            assert_eq!(Rc::strong_count(&counter), 2);
+            drop(first);
+        }
+        assert_eq!(Rc::strong_count(&counter), 1);
+        assert_eq!(alloc.bytes_used(), 0);
+
+        let units = collect_into(&alloc, vec![(); 3]).unwrap();
+        assert_eq!(units.into_iter().count(), 3);
+    }
+
     #[test]
     fn take_out() {
         let _: [u8; 1024] = HEAP.allocate([0; 1024]).ok().unwrap().take();
     }
 
+    #[test]
+    fn take_shared() {
+        // allocators keeping their state in a `RefCell` never hold a borrow
+        // across calls, so values can be taken while others are alive.
+        let recycler = Recycler::new();
+        let vals: Vec<_> = (0..4).map(|i| recycler.get(i.to_string()).ok().unwrap()).collect();
+        let taken: Vec<_> = vals.into_iter().map(AllocBox::take).collect();
+        assert_eq!(taken, ["0", "1", "2", "3"]);
+        assert_eq!(recycler.cached(), 4);
+
+        thread_scoped!(static ARENA: 256);
+        let val = ARENA.with(|arena| {
+            arena.with(|outer| {
+                let kept = outer.allocate(1u64).unwrap();
+                let val = arena.with(|inner| inner.allocate(vec![2u8]).unwrap().take()).unwrap();
+                val[0] + kept.take() as u8
+            }).unwrap()
+        });
+        assert_eq!(val, 3);
+    }
+
+    #[test]
+    fn align_forward_overflow() {
+        use std::usize;
+        use super::align_forward;
+
+        assert_eq!(align_forward(9 as *mut u8, 8), Some(16 as *mut u8));
+        assert_eq!(align_forward(16 as *mut u8, 8), Some(16 as *mut u8));
+        assert_eq!(align_forward((usize::MAX - 3) as *mut u8, 8), None);
+    }
+
     #[test]
     fn boxed_allocator() {
         #[derive(Debug)]
@@ -0,0 +1,143 @@
+//! A per-thread scoped arena.
+
+use std::cell::{Cell, RefCell};
+
+use super::{Error, HeapAllocator, Scoped};
+
+/// A `Scoped` arena meant to be kept in a thread local, so that code running on
+/// the thread can allocate from it without passing it around.
+/// The arena is only allocated the first time it's used.
+///
+/// Everything allocated from the arena lives no longer than the `with` call it came from,
+/// so the arena is reset whenever the outermost call returns.
+///
+/// # Examples
+/// ```rust
+/// #[macro_use]
+/// extern crate allocators;
+///
+/// use allocators::Allocator;
+///
+/// thread_scoped!(static ARENA: 4096);
+///
+/// fn main() {
+///     let sum = ARENA.with(|arena| {
+///         arena.with(|scoped| {
+///             let vals = scoped.allocate([1, 2, 3]).unwrap();
+///             vals.iter().sum::<i32>()
+///         })
+///     });
+///     assert_eq!(sum.unwrap(), 6);
+/// }
+/// ```
+pub struct ThreadScoped {
+    size: usize,
+    arena: RefCell<Option<Scoped<'static, HeapAllocator>>>,
+    depth: Cell<usize>,
+}
+
+/// Declares a thread local `ThreadScoped` arena of the given size in bytes.
+///
+/// `thread_scoped!(static ARENA: 4096);` is short for
+/// `thread_local!(static ARENA: ThreadScoped = ThreadScoped::new(4096));`.
+#[macro_export]
+macro_rules! thread_scoped {
+    ($(#[$attr:meta])* static $name:ident: $size:expr) => (
+        thread_local!($(#[$attr])* static $name: $crate::ThreadScoped = $crate::ThreadScoped::new($size));
+    );
+    ($(#[$attr:meta])* pub static $name:ident: $size:expr) => (
+        thread_local!($(#[$attr])* pub static $name: $crate::ThreadScoped = $crate::ThreadScoped::new($size));
+    );
+}
+
+impl ThreadScoped {
+    /// Creates a new `ThreadScoped` which will allocate `size` bytes from
+    /// the heap when first used.
+    pub fn new(size: usize) -> Self {
+        ThreadScoped {
+            size: size,
+            arena: RefCell::new(None),
+            depth: Cell::new(0),
+        }
+    }
+
+    /// Calls the supplied function with the arena, allocating it first if needed.
+    /// Returns an error if the arena couldn't be allocated.
+    pub fn with<F, U>(&self, f: F) -> Result<U, Error>
+        where F: FnOnce(&Scoped<'static, HeapAllocator>) -> U
+    {
+        if self.arena.borrow().is_none() {
+            match Scoped::new(self.size) {
+                Ok(arena) => *self.arena.borrow_mut() = Some(arena),
+                Err(err) => return Err(err),
+            }
+        }
+
+        // reset the arena once the outermost call is done, even if `f` panics.
+        struct Exit<'a>(&'a ThreadScoped);
+        impl<'a> Drop for Exit<'a> {
+            fn drop(&mut self) {
+                let depth = self.0.depth.get() - 1;
+                self.0.depth.set(depth);
+                if depth == 0 {
+                    if let Some(ref mut arena) = *self.0.arena.borrow_mut() {
+                        arena.reset();
+                    }
+                }
+            }
+        }
+
+        self.depth.set(self.depth.get() + 1);
+        let _exit = Exit(self);
+        let arena = self.arena.borrow();
+        Ok(f(arena.as_ref().unwrap()))
+    }
+
+    /// The number of bytes allocated from the arena so far.
+    pub fn bytes_used(&self) -> usize {
+        match *self.arena.borrow() {
+            Some(ref arena) => arena.bytes_used(),
+            None => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::super::*;
+
+    thread_scoped!(static ARENA: 64);
+
+    #[test]
+    fn thread_isolation() {
+        let addr = |_| ARENA.with(|arena| {
+            arena.with(|scoped| {
+                let val = scoped.allocate(1u64).unwrap();
+                assert_eq!(scoped.bytes_used(), 8);
+                &*val as *const u64 as usize
+            }).unwrap()
+        });
+
+        let here = addr(());
+        let there = thread::spawn(move || addr(())).join().unwrap();
+        // each thread has an arena of its own.
+        assert!(here != there);
+    }
+
+    #[test]
+    fn reset_after_outermost() {
+        ARENA.with(|arena| {
+            arena.with(|outer| {
+                let _a = outer.allocate(0u32).unwrap();
+                arena.with(|inner| {
+                    let _b = inner.allocate(0u32).unwrap();
+                }).unwrap();
+                // the nested call doesn't reset the arena from under the outer one.
+                assert_eq!(arena.bytes_used(), 4);
+            }).unwrap();
+            assert_eq!(arena.bytes_used(), 0);
+        });
+    }
+}
@@ -0,0 +1,517 @@
+//! Ready-made `ProxyLogger` implementations.
+
+use std::cmp;
+use std::collections::HashSet;
+use std::fmt::Write as FmtWrite;
+use std::io::Write;
+use std::mem;
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::{Block, Error, ProxyLogger};
+
+/// A snapshot of the statistics collected by a `StatsLogger`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Stats {
+    /// The number of successful allocations.
+    pub allocations: usize,
+    /// The number of deallocations.
+    pub deallocations: usize,
+    /// The number of successful reallocations.
+    pub reallocations: usize,
+    /// The number of failed allocations and reallocations.
+    pub failures: usize,
+    /// The total number of bytes handed out by allocations.
+    pub total_bytes: usize,
+    /// The number of bytes currently allocated.
+    pub live_bytes: usize,
+    /// The highest number of bytes allocated at once.
+    pub peak_bytes: usize,
+}
+
+/// A logger which keeps count of an allocator's activity.
+/// The counters are atomic, so this can be shared between threads.
+#[derive(Debug, Default)]
+pub struct StatsLogger {
+    allocations: AtomicUsize,
+    deallocations: AtomicUsize,
+    reallocations: AtomicUsize,
+    failures: AtomicUsize,
+    total_bytes: AtomicUsize,
+    live_bytes: AtomicUsize,
+    peak_bytes: AtomicUsize,
+}
+
+impl StatsLogger {
+    /// Creates a new `StatsLogger` with all counters at zero.
+    pub fn new() -> Self {
+        StatsLogger::default()
+    }
+
+    /// Reads the current value of every counter.
+    pub fn snapshot(&self) -> Stats {
+        Stats {
+            allocations: self.allocations.load(Ordering::SeqCst),
+            deallocations: self.deallocations.load(Ordering::SeqCst),
+            reallocations: self.reallocations.load(Ordering::SeqCst),
+            failures: self.failures.load(Ordering::SeqCst),
+            total_bytes: self.total_bytes.load(Ordering::SeqCst),
+            live_bytes: self.live_bytes.load(Ordering::SeqCst),
+            peak_bytes: self.peak_bytes.load(Ordering::SeqCst),
+        }
+    }
+
+    fn add_live(&self, size: usize) {
+        let live = self.live_bytes.fetch_add(size, Ordering::SeqCst) + size;
+        let mut peak = self.peak_bytes.load(Ordering::SeqCst);
+        while live > peak {
+            match self.peak_bytes.compare_exchange(peak, live, Ordering::SeqCst, Ordering::SeqCst) {
+                Ok(_) => break,
+                Err(actual) => peak = actual,
+            }
+        }
+    }
+}
+
+impl ProxyLogger for StatsLogger {
+    fn allocate_success(&self, block: &Block) {
+        self.allocations.fetch_add(1, Ordering::SeqCst);
+        self.total_bytes.fetch_add(block.size(), Ordering::SeqCst);
+        self.add_live(block.size());
+    }
+
+    fn allocate_fail(&self, _err: &Error, _size: usize, _align: usize) {
+        self.failures.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn deallocate(&self, block: &Block) {
+        self.deallocations.fetch_add(1, Ordering::SeqCst);
+        self.live_bytes.fetch_sub(block.size(), Ordering::SeqCst);
+    }
+
+    fn reallocate_success(&self, old_block: &Block, new_block: &Block) {
+        self.reallocations.fetch_add(1, Ordering::SeqCst);
+        if new_block.size() > old_block.size() {
+            let growth = new_block.size() - old_block.size();
+            self.total_bytes.fetch_add(growth, Ordering::SeqCst);
+            self.add_live(growth);
+        } else {
+            self.live_bytes.fetch_sub(old_block.size() - new_block.size(), Ordering::SeqCst);
+        }
+    }
+
+    fn reallocate_fail(&self, _err: &Error, _block: &Block, _req_size: usize) {
+        self.failures.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// A logger which writes a line describing each event to the writer supplied.
+/// Errors while writing are ignored.
+pub struct WriteLogger<W: Write> {
+    writer: Mutex<W>,
+}
+
+impl<W: Write> WriteLogger<W> {
+    /// Creates a new `WriteLogger` writing to `writer`.
+    pub fn new(writer: W) -> Self {
+        WriteLogger { writer: Mutex::new(writer) }
+    }
+
+    /// Consumes the logger, returning the writer.
+    pub fn into_inner(self) -> W {
+        match self.writer.into_inner() {
+            Ok(writer) => writer,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    fn log(&self, args: ::std::fmt::Arguments) {
+        let mut writer = match self.writer.lock() {
+            Ok(writer) => writer,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let _ = writer.write_fmt(args);
+    }
+}
+
+impl<W: Write> ProxyLogger for WriteLogger<W> {
+    fn allocate_success(&self, block: &Block) {
+        self.log(format_args!("allocate {:p}: size {}, align {}\n",
+                              block.ptr(), block.size(), block.align()));
+    }
+
+    fn allocate_fail(&self, err: &Error, size: usize, align: usize) {
+        self.log(format_args!("allocate failed: size {}, align {}: {}\n", size, align, err));
+    }
+
+    fn deallocate(&self, block: &Block) {
+        self.log(format_args!("deallocate {:p}: size {}, align {}\n",
+                              block.ptr(), block.size(), block.align()));
+    }
+
+    fn reallocate_success(&self, old_block: &Block, new_block: &Block) {
+        self.log(format_args!("reallocate {:p} -> {:p}: size {} -> {}, align {}\n",
+                              old_block.ptr(), new_block.ptr(),
+                              old_block.size(), new_block.size(), new_block.align()));
+    }
+
+    fn reallocate_fail(&self, err: &Error, block: &Block, req_size: usize) {
+        self.log(format_args!("reallocate failed {:p}: size {} -> {}, align {}: {}\n",
+                              block.ptr(), block.size(), req_size, block.align(), err));
+    }
+}
+
+/// The number of size classes tracked by a `HistogramLogger`.
+pub const HISTOGRAM_BUCKETS: usize = 32;
+
+/// A logger which counts allocations by size class.
+/// The first class holds sizes 1 through 8, and every class after it
+/// holds sizes up to double the last one's: 9 through 16, 17 through 32, and so on.
+/// The last class holds everything larger as well.
+#[derive(Debug, Default)]
+pub struct HistogramLogger {
+    buckets: [AtomicUsize; HISTOGRAM_BUCKETS],
+}
+
+impl HistogramLogger {
+    /// Creates a new `HistogramLogger` with every count at zero.
+    pub fn new() -> Self {
+        HistogramLogger::default()
+    }
+
+    /// Reads the count of every size class.
+    pub fn histogram(&self) -> [usize; HISTOGRAM_BUCKETS] {
+        let mut histogram = [0; HISTOGRAM_BUCKETS];
+        for (count, bucket) in histogram.iter_mut().zip(self.buckets.iter()) {
+            *count = bucket.load(Ordering::SeqCst);
+        }
+        histogram
+    }
+
+    /// Formats the non-empty size classes, one per line.
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        for (i, &count) in self.histogram().iter().enumerate() {
+            if count == 0 {
+                continue;
+            }
+
+            let low = if i == 0 { 1 } else { (4 << i) + 1 };
+            if i == HISTOGRAM_BUCKETS - 1 {
+                let _ = writeln!(report, "{}+: {}", low, count);
+            } else {
+                let _ = writeln!(report, "{}-{}: {}", low, 8 << i, count);
+            }
+        }
+        report
+    }
+
+    /// The size class of a given size.
+    pub fn bucket(size: usize) -> usize {
+        if size <= 8 {
+            return 0;
+        }
+
+        // the number of bits needed for `size - 1` is the rounded-up log2 of `size`.
+        let bits = mem::size_of::<usize>() * 8 - (size - 1).leading_zeros() as usize;
+        cmp::min(bits - 3, HISTOGRAM_BUCKETS - 1)
+    }
+}
+
+impl ProxyLogger for HistogramLogger {
+    fn allocate_success(&self, block: &Block) {
+        if !block.is_empty() {
+            self.buckets[HistogramLogger::bucket(block.size())].fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn allocate_fail(&self, _err: &Error, _size: usize, _align: usize) {}
+
+    fn deallocate(&self, _block: &Block) {}
+
+    fn reallocate_success(&self, _old_block: &Block, _new_block: &Block) {}
+
+    fn reallocate_fail(&self, _err: &Error, _block: &Block, _req_size: usize) {}
+}
+
+/// A logger which keeps track of every live block, and panics when
+/// a block is deallocated twice or was never allocated to begin with.
+///
+/// `Proxy` informs its logger of a deallocation before passing the
+/// block on to the allocator, so the panic happens before the invalid
+/// free can corrupt anything.
+#[derive(Debug, Default)]
+pub struct DebugLogger {
+    live: Mutex<HashSet<usize>>,
+}
+
+impl DebugLogger {
+    /// Creates a new `DebugLogger`.
+    pub fn new() -> Self {
+        DebugLogger::default()
+    }
+
+    /// The number of blocks currently allocated.
+    pub fn live_blocks(&self) -> usize {
+        self.live().len()
+    }
+
+    fn live(&self) -> ::std::sync::MutexGuard<HashSet<usize>> {
+        match self.live.lock() {
+            Ok(live) => live,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    fn insert(&self, block: &Block) {
+        if !block.is_empty() {
+            self.live().insert(block.ptr() as usize);
+        }
+    }
+
+    fn remove(&self, block: &Block) {
+        if !block.is_empty() && !self.live().remove(&(block.ptr() as usize)) {
+            panic!("Attempted to deallocate {:p}, which is not allocated. \
+                    It has either been freed already or was never allocated.",
+                   block.ptr());
+        }
+    }
+}
+
+impl ProxyLogger for DebugLogger {
+    fn allocate_success(&self, block: &Block) {
+        self.insert(block);
+    }
+
+    fn allocate_fail(&self, _err: &Error, _size: usize, _align: usize) {}
+
+    fn deallocate(&self, block: &Block) {
+        self.remove(block);
+    }
+
+    fn reallocate_success(&self, old_block: &Block, new_block: &Block) {
+        self.remove(old_block);
+        self.insert(new_block);
+    }
+
+    fn reallocate_fail(&self, _err: &Error, _block: &Block, _req_size: usize) {}
+}
+
+/// An event sent by a `ChannelLogger`.
+/// Block addresses are given as integers, so events can be sent between threads.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AllocEvent {
+    /// A block was allocated.
+    Allocate { ptr: usize, size: usize, align: usize },
+    /// An allocation failed.
+    AllocateFail { err: Error, size: usize, align: usize },
+    /// A block was deallocated.
+    Deallocate { ptr: usize, size: usize, align: usize },
+    /// A block was reallocated, possibly moving it.
+    Reallocate { old_ptr: usize, new_ptr: usize, old_size: usize, new_size: usize, align: usize },
+    /// A reallocation failed, leaving the block as it was.
+    ReallocateFail { err: Error, ptr: usize, size: usize, req_size: usize, align: usize },
+}
+
+/// A logger which sends each event over a channel, to be handled on another thread
+/// without slowing down the allocator.
+/// Events sent after the receiver is dropped are discarded.
+pub struct ChannelLogger {
+    sender: Mutex<Sender<AllocEvent>>,
+}
+
+impl ChannelLogger {
+    /// Creates a new `ChannelLogger`, along with the receiving end of its channel.
+    pub fn new() -> (Self, Receiver<AllocEvent>) {
+        let (sender, receiver) = mpsc::channel();
+        (ChannelLogger { sender: Mutex::new(sender) }, receiver)
+    }
+
+    fn send(&self, event: AllocEvent) {
+        let sender = match self.sender.lock() {
+            Ok(sender) => sender,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let _ = sender.send(event);
+    }
+}
+
+impl ProxyLogger for ChannelLogger {
+    fn allocate_success(&self, block: &Block) {
+        self.send(AllocEvent::Allocate {
+            ptr: block.ptr() as usize,
+            size: block.size(),
+            align: block.align(),
+        });
+    }
+
+    fn allocate_fail(&self, err: &Error, size: usize, align: usize) {
+        self.send(AllocEvent::AllocateFail {
+            err: err.clone(),
+            size: size,
+            align: align,
+        });
+    }
+
+    fn deallocate(&self, block: &Block) {
+        self.send(AllocEvent::Deallocate {
+            ptr: block.ptr() as usize,
+            size: block.size(),
+            align: block.align(),
+        });
+    }
+
+    fn reallocate_success(&self, old_block: &Block, new_block: &Block) {
+        self.send(AllocEvent::Reallocate {
+            old_ptr: old_block.ptr() as usize,
+            new_ptr: new_block.ptr() as usize,
+            old_size: old_block.size(),
+            new_size: new_block.size(),
+            align: new_block.align(),
+        });
+    }
+
+    fn reallocate_fail(&self, err: &Error, block: &Block, req_size: usize) {
+        self.send(AllocEvent::ReallocateFail {
+            err: err.clone(),
+            ptr: block.ptr() as usize,
+            size: block.size(),
+            req_size: req_size,
+            align: block.align(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    #[test]
+    fn stats() {
+        let alloc = Proxy::new(HEAP, StatsLogger::new());
+        {
+            let _a = alloc.allocate([0u8; 64]).unwrap();
+            {
+                let _b = alloc.allocate([0u8; 32]).unwrap();
+            }
+            let _c = alloc.allocate([0u8; 16]).unwrap();
+
+            let stats = alloc.logger().snapshot();
+            assert_eq!(stats.allocations, 3);
+            assert_eq!(stats.deallocations, 1);
+            assert_eq!(stats.total_bytes, 112);
+            assert_eq!(stats.live_bytes, 80);
+            assert_eq!(stats.peak_bytes, 96);
+        }
+
+        let stats = alloc.logger().snapshot();
+        assert_eq!(stats.live_bytes, 0);
+        assert_eq!(stats.peak_bytes, 96);
+
+        unsafe {
+            let block = alloc.allocate_raw(8, 8).unwrap();
+            let block = alloc.reallocate_raw(block, 128).ok().unwrap();
+            assert_eq!(alloc.logger().snapshot().peak_bytes, 128);
+            alloc.deallocate_raw(block);
+        }
+
+        let bounded = Proxy::new(Scoped::new(8).unwrap(), StatsLogger::new());
+        assert!(bounded.allocate(0u64).is_ok());
+        assert!(bounded.allocate([0u64; 2]).is_err());
+        assert_eq!(bounded.logger().snapshot().failures, 1);
+    }
+
+    #[test]
+    fn write_logger() {
+        let mut out = Vec::new();
+        {
+            let alloc = Proxy::new(Scoped::new(64).unwrap(), WriteLogger::new(&mut out));
+            let _a = alloc.allocate(0u32).unwrap();
+            assert!(alloc.allocate([0u8; 100]).is_err());
+        }
+
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("allocate 0x"));
+        assert!(lines[0].ends_with("size 4, align 4"));
+        assert_eq!(lines[1], "allocate failed: size 100, align 1: Allocator out of memory.");
+        assert!(lines[2].starts_with("deallocate"));
+        assert!(lines[2].ends_with("size 4, align 4"));
+    }
+
+    #[test]
+    fn debug_logger() {
+        let alloc = Proxy::new(HEAP, DebugLogger::new());
+        let a = alloc.allocate(1i32).unwrap();
+        let b = alloc.allocate(2i32).unwrap();
+        assert_eq!(alloc.logger().live_blocks(), 2);
+        drop(a);
+        drop(b);
+        assert_eq!(alloc.logger().live_blocks(), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn double_free() {
+        let alloc = Proxy::new(Scoped::new(64).unwrap(), DebugLogger::new());
+        unsafe {
+            let block = alloc.allocate_raw(8, 8).unwrap();
+            let copy = Block::new(block.ptr(), block.size(), block.align());
+            alloc.deallocate_raw(block);
+            alloc.deallocate_raw(copy);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn unknown_free() {
+        let alloc = Proxy::new(Scoped::new(64).unwrap(), DebugLogger::new());
+        let mut val = 0u64;
+        unsafe { alloc.deallocate_raw(Block::new(&mut val as *mut u64 as *mut u8, 8, 8)) };
+    }
+
+    #[test]
+    fn histogram() {
+        assert_eq!(HistogramLogger::bucket(1), 0);
+        assert_eq!(HistogramLogger::bucket(8), 0);
+        assert_eq!(HistogramLogger::bucket(9), 1);
+        assert_eq!(HistogramLogger::bucket(16), 1);
+        assert_eq!(HistogramLogger::bucket(17), 2);
+        assert_eq!(HistogramLogger::bucket(::std::usize::MAX), loggers::HISTOGRAM_BUCKETS - 1);
+
+        let alloc = Proxy::new(HEAP, HistogramLogger::new());
+        let _a = alloc.allocate(0u8).unwrap();
+        let _b = alloc.allocate(0u64).unwrap();
+        let _c = alloc.allocate([0u8; 12]).unwrap();
+        let _d = alloc.allocate([0u8; 1000]).unwrap();
+
+        let histogram = alloc.logger().histogram();
+        assert_eq!(histogram[0], 2);
+        assert_eq!(histogram[1], 1);
+        assert_eq!(histogram[7], 1);
+        assert_eq!(histogram.iter().sum::<usize>(), 4);
+        assert_eq!(alloc.logger().report(), "1-8: 2\n9-16: 1\n513-1024: 1\n");
+    }
+
+    #[test]
+    fn channel_logger() {
+        let (logger, events) = ChannelLogger::new();
+        let alloc = Proxy::new(Scoped::new(64).unwrap(), logger);
+        let addr = {
+            let a = alloc.allocate(0u32).unwrap();
+            assert!(alloc.allocate([0u8; 100]).is_err());
+            &*a as *const u32 as usize
+        };
+
+        let received: Vec<_> = events.try_iter().collect();
+        assert_eq!(received, [AllocEvent::Allocate { ptr: addr, size: 4, align: 4 },
+                              AllocEvent::AllocateFail { err: Error::OutOfMemory, size: 100, align: 1 },
+                              AllocEvent::Deallocate { ptr: addr, size: 4, align: 4 }]);
+
+        // the allocator carries on once nobody is listening.
+        drop(events);
+        assert!(alloc.allocate(0u32).is_ok());
+    }
+}
@@ -0,0 +1,295 @@
+//! An allocator which maps memory straight from the operating system.
+
+use std::ptr;
+use std::sync::Mutex;
+
+use libc;
+
+use super::{Allocator, Error, Block, BlockOwner};
+
+fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+/// An allocator which gives every block a private, anonymous memory mapping of its own.
+///
+/// Blocks are page aligned, and their sizes are rounded up to whole pages.
+/// Mapping memory is slow, so this is meant to back large, long-lived allocators,
+/// like the buffer of a big `Scoped` arena, without fragmenting the heap.
+///
+/// This is only available on Unix.
+pub struct MmapAllocator {
+    page_size: usize,
+    // the start and length of every mapping handed out.
+    mappings: Mutex<Vec<(usize, usize)>>,
+}
+
+impl MmapAllocator {
+    /// Creates a new `MmapAllocator`.
+    pub fn new() -> Self {
+        MmapAllocator {
+            page_size: page_size(),
+            mappings: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The size of a page, which every block is aligned to.
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    // rounds a size up to whole pages.
+    fn round_up(&self, size: usize) -> Option<usize> {
+        size.checked_add(self.page_size - 1).map(|size| size & !(self.page_size - 1))
+    }
+
+    unsafe fn map(&self, len: usize) -> Result<*mut u8, Error> {
+        let ptr = libc::mmap(ptr::null_mut(),
+                             len,
+                             libc::PROT_READ | libc::PROT_WRITE,
+                             libc::MAP_PRIVATE | libc::MAP_ANON,
+                             -1,
+                             0);
+        if ptr == libc::MAP_FAILED {
+            return Err(Error::OutOfMemory);
+        }
+
+        self.mappings.lock().unwrap().push((ptr as usize, len));
+        Ok(ptr as *mut u8)
+    }
+
+    unsafe fn unmap(&self, ptr: *mut u8, len: usize) {
+        let mut mappings = self.mappings.lock().unwrap();
+        match mappings.iter().position(|&mapping| mapping == (ptr as usize, len)) {
+            Some(index) => {
+                mappings.swap_remove(index);
+                libc::munmap(ptr as *mut libc::c_void, len);
+            }
+            None => debug_assert!(false, "Attempted to deallocate a block not owned by this MmapAllocator."),
+        }
+    }
+}
+
+impl Default for MmapAllocator {
+    fn default() -> Self {
+        MmapAllocator::new()
+    }
+}
+
+unsafe impl Allocator for MmapAllocator {
+    unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        if size == 0 {
+            return Ok(Block::empty());
+        } else if align > self.page_size {
+            return Err(Error::UnsupportedAlignment);
+        }
+
+        let len = match self.round_up(size) {
+            Some(len) => len,
+            None => return Err(Error::OutOfMemory),
+        };
+
+        match self.map(len) {
+            Ok(ptr) => Ok(Block::new(ptr, size, align)),
+            Err(err) => Err(err),
+        }
+    }
+
+    unsafe fn reallocate_raw<'a>(&'a self, block: Block<'a>, new_size: usize) -> Result<Block<'a>, (Error, Block<'a>)> {
+        if new_size == 0 {
+            self.deallocate_raw(block);
+            return Ok(Block::empty());
+        } else if block.is_empty() {
+            return Err((Error::UnsupportedAlignment, block));
+        }
+
+        // the mapping may already have room.
+        let len = self.round_up(block.size()).unwrap();
+        if self.round_up(new_size) == Some(len) {
+            return Ok(Block::new(block.ptr(), new_size, block.align()));
+        }
+
+        match self.allocate_raw(new_size, block.align()) {
+            Ok(new_block) => {
+                ptr::copy_nonoverlapping(block.ptr(), new_block.ptr(), ::std::cmp::min(block.size(), new_size));
+                self.deallocate_raw(block);
+                Ok(new_block)
+            }
+            Err(err) => Err((err, block)),
+        }
+    }
+
+    unsafe fn deallocate_raw(&self, block: Block) {
+        if block.is_empty() {
+            return;
+        }
+
+        let len = self.round_up(block.size()).unwrap();
+        self.unmap(block.ptr(), len);
+    }
+
+    fn usable_size(&self, size: usize, align: usize) -> usize {
+        match self.round_up(size) {
+            Some(len) if align <= self.page_size => len,
+            _ => size,
+        }
+    }
+}
+
+impl BlockOwner for MmapAllocator {
+    fn owns_block(&self, block: &Block) -> bool {
+        let ptr = block.ptr() as usize;
+        self.mappings.lock().unwrap().iter().any(|&(start, len)| {
+            ptr >= start && ptr + block.size() <= start + len
+        })
+    }
+}
+
+/// An allocator which maps memory like `MmapAllocator`, but follows every block
+/// with an inaccessible guard page, so that running off its end faults
+/// instead of silently corrupting other memory.
+///
+/// Block sizes are rounded up to whole pages, so the guard page starts
+/// right where the block ends. Use the `GUARD_PAGES` constant to get one.
+/// See `Scoped::new_guarded`.
+pub struct GuardPageAllocator;
+
+/// A `GuardPageAllocator` with the static lifetime.
+pub const GUARD_PAGES: &'static GuardPageAllocator = &GuardPageAllocator;
+
+impl GuardPageAllocator {
+    /// Rounds a size up to whole pages, as every block is.
+    pub fn round_up(&self, size: usize) -> Option<usize> {
+        let page_size = page_size();
+        size.checked_add(page_size - 1).map(|size| size & !(page_size - 1))
+    }
+}
+
+unsafe impl Allocator for GuardPageAllocator {
+    unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        let page_size = page_size();
+        if size == 0 {
+            return Ok(Block::empty());
+        } else if align > page_size {
+            return Err(Error::UnsupportedAlignment);
+        }
+
+        let len = match self.round_up(size).and_then(|len| len.checked_add(page_size)) {
+            Some(len) => len,
+            None => return Err(Error::OutOfMemory),
+        };
+
+        let ptr = libc::mmap(ptr::null_mut(),
+                             len,
+                             libc::PROT_READ | libc::PROT_WRITE,
+                             libc::MAP_PRIVATE | libc::MAP_ANON,
+                             -1,
+                             0);
+        if ptr == libc::MAP_FAILED {
+            return Err(Error::OutOfMemory);
+        }
+
+        let guard = (ptr as *mut u8).offset((len - page_size) as isize);
+        if libc::mprotect(guard as *mut libc::c_void, page_size, libc::PROT_NONE) != 0 {
+            libc::munmap(ptr, len);
+            return Err(Error::AllocatorSpecific("Failed to protect the guard page.".into()));
+        }
+
+        Ok(Block::new(ptr as *mut u8, len - page_size, align))
+    }
+
+    /// Blocks are never resized, since the guard page would have to move.
+    unsafe fn reallocate_raw<'a>(&'a self, block: Block<'a>, new_size: usize) -> Result<Block<'a>, (Error, Block<'a>)> {
+        if new_size == 0 {
+            self.deallocate_raw(block);
+            Ok(Block::empty())
+        } else if block.is_empty() {
+            Err((Error::UnsupportedAlignment, block))
+        } else {
+            Err((Error::CannotResizeInPlace, block))
+        }
+    }
+
+    unsafe fn deallocate_raw(&self, block: Block) {
+        if block.is_empty() {
+            return;
+        }
+
+        let len = self.round_up(block.size()).unwrap() + page_size();
+        libc::munmap(block.ptr() as *mut libc::c_void, len);
+    }
+
+    fn usable_size(&self, size: usize, _align: usize) -> usize {
+        self.round_up(size).unwrap_or(size)
+    }
+}
+
+impl Drop for MmapAllocator {
+    fn drop(&mut self) {
+        for (start, len) in self.mappings.get_mut().unwrap().drain(..) {
+            unsafe { libc::munmap(start as *mut libc::c_void, len) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    #[test]
+    fn scoped_on_mmap() {
+        let mmap = MmapAllocator::new();
+        {
+            let alloc = Scoped::new_from(&mmap, 4 * 1024 * 1024).unwrap();
+            let val = alloc.allocate([7u8; 4096]).unwrap();
+            assert!(mmap.owns(&val));
+            assert_eq!(val[4095], 7);
+            assert_eq!(alloc.bytes_remaining(), 4 * 1024 * 1024 - 4096);
+        }
+        // the arena's mapping is gone.
+        assert!(mmap.mappings.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn guard_page() {
+        use std::fs::File;
+        use std::io::Read;
+
+        let alloc = Scoped::new_guarded(100).unwrap();
+        let page = GUARD_PAGES.round_up(1).unwrap();
+        // the arena is rounded up to fill its pages.
+        assert_eq!(alloc.bytes_remaining(), page);
+        let val = alloc.allocate([1u8; 4096]).unwrap();
+        let guard = &*val as *const _ as usize + page;
+
+        // the page after the arena can't be read or written.
+        let mut maps = String::new();
+        File::open("/proc/self/maps").unwrap().read_to_string(&mut maps).unwrap();
+        let perms = maps.lines().find(|line| {
+            let range: Vec<_> = line.split(' ').next().unwrap().split('-')
+                                    .map(|addr| usize::from_str_radix(addr, 16).unwrap())
+                                    .collect();
+            range[0] <= guard && guard < range[1]
+        }).unwrap().split(' ').nth(1).unwrap().to_string();
+        assert_eq!(perms, "---p");
+    }
+
+    #[test]
+    fn pages() {
+        let mmap = MmapAllocator::new();
+        let page = mmap.page_size();
+        assert_eq!(mmap.usable_size(1, 1), page);
+        unsafe {
+            let block = mmap.allocate_raw(10, 8).unwrap();
+            assert_eq!(block.ptr() as usize % page, 0);
+            // growing within the page keeps the block where it is.
+            let grown = mmap.reallocate_raw(block, page).ok().unwrap();
+            let addr = grown.ptr();
+            let moved = mmap.reallocate_raw(grown, page + 1).ok().unwrap();
+            assert!(moved.ptr() != addr);
+            mmap.deallocate_raw(moved);
+            assert_eq!(mmap.allocate_raw(8, page * 2).err(), Some(Error::UnsupportedAlignment));
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! An allocator which hands out a single block at a time.
+
+use std::cell::Cell;
+use std::mem;
+
+use super::{Allocator, Error, Block, BlockOwner, HeapAllocator, HEAP};
+
+/// A `OneShot` holds a single buffer, and hands it out to one allocation at a time.
+/// Any further allocation fails with `OutOfMemory` until the first is freed.
+///
+/// This enforces single ownership of a scratch buffer, like one half of a double buffer:
+/// whoever holds the allocation knows nobody else can be using the memory.
+pub struct OneShot<'a, A: 'a + Allocator> {
+    alloc: &'a A,
+    ptr: *mut u8,
+    size: usize,
+    in_use: Cell<bool>,
+}
+
+impl OneShot<'static, HeapAllocator> {
+    /// Creates a new `OneShot` with a buffer of `size` bytes from the heap.
+    pub fn new(size: usize) -> Result<Self, Error> {
+        OneShot::new_from(HEAP, size)
+    }
+}
+
+impl<'a, A: 'a + Allocator> OneShot<'a, A> {
+    /// Creates a new `OneShot` with a buffer of `size` bytes from another allocator.
+    /// The buffer is aligned to a `usize`, so requests for up to that alignment can be served.
+    pub fn new_from(alloc: &'a A, size: usize) -> Result<Self, Error> {
+        match unsafe { alloc.allocate_raw(size, mem::align_of::<usize>()) } {
+            Ok(block) => Ok(OneShot {
+                alloc: alloc,
+                ptr: if block.is_empty() { mem::align_of::<usize>() as *mut u8 } else { block.ptr() },
+                size: block.size(),
+                in_use: Cell::new(false),
+            }),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// The size of the buffer.
+    pub fn capacity(&self) -> usize {
+        self.size
+    }
+
+    /// Whether the buffer is currently allocated.
+    pub fn in_use(&self) -> bool {
+        self.in_use.get()
+    }
+
+    // whether the buffer can hold a block with this layout.
+    fn fits(&self, size: usize, align: usize) -> bool {
+        size <= self.size && align <= mem::align_of::<usize>()
+    }
+}
+
+unsafe impl<'a, A: 'a + Allocator> Allocator for OneShot<'a, A> {
+    unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        if size == 0 {
+            return Ok(Block::empty());
+        } else if align > mem::align_of::<usize>() {
+            return Err(Error::UnsupportedAlignment);
+        } else if size > self.size || self.in_use.get() {
+            return Err(Error::OutOfMemory);
+        }
+
+        self.in_use.set(true);
+        Ok(Block::new(self.ptr, size, align))
+    }
+
+    /// Blocks are resized in place, within the buffer.
+    unsafe fn reallocate_raw<'b>(&'b self, block: Block<'b>, new_size: usize) -> Result<Block<'b>, (Error, Block<'b>)> {
+        if new_size == 0 {
+            self.deallocate_raw(block);
+            Ok(Block::empty())
+        } else if block.is_empty() {
+            Err((Error::UnsupportedAlignment, block))
+        } else if self.fits(new_size, block.align()) {
+            Ok(Block::new(block.ptr(), new_size, block.align()))
+        } else {
+            Err((Error::OutOfMemory, block))
+        }
+    }
+
+    unsafe fn deallocate_raw(&self, block: Block) {
+        if block.is_empty() {
+            return;
+        }
+
+        if self.owns_block(&block) && self.in_use.get() {
+            self.in_use.set(false);
+        } else {
+            debug_assert!(false, "Attempted to deallocate a block not allocated from this OneShot.");
+        }
+    }
+
+    fn usable_size(&self, size: usize, align: usize) -> usize {
+        if self.fits(size, align) { self.size } else { size }
+    }
+
+    unsafe fn grow_in_place<'b>(&'b self, block: Block<'b>, new_size: usize) -> Result<Block<'b>, (Error, Block<'b>)> {
+        if !block.is_empty() && self.fits(new_size, block.align()) {
+            Ok(Block::new(block.ptr(), new_size, block.align()))
+        } else {
+            Err((Error::CannotResizeInPlace, block))
+        }
+    }
+
+    unsafe fn shrink_in_place<'b>(&'b self, block: Block<'b>, new_size: usize) -> Result<Block<'b>, (Error, Block<'b>)> {
+        if block.is_empty() {
+            return Err((Error::CannotResizeInPlace, block));
+        }
+
+        Ok(Block::new(block.ptr(), new_size, block.align()))
+    }
+}
+
+impl<'a, A: 'a + Allocator> BlockOwner for OneShot<'a, A> {
+    fn owns_block(&self, block: &Block) -> bool {
+        block.ptr() == self.ptr && block.size() <= self.size
+    }
+}
+
+impl<'a, A: 'a + Allocator> Drop for OneShot<'a, A> {
+    fn drop(&mut self) {
+        if self.size > 0 {
+            unsafe { self.alloc.deallocate_raw(Block::new(self.ptr, self.size, mem::align_of::<usize>())) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    #[test]
+    fn one_at_a_time() {
+        let alloc = OneShot::new(64).unwrap();
+        let first = alloc.allocate([1u8; 64]).unwrap();
+        assert!(alloc.in_use());
+        assert!(alloc.owns(&first));
+        assert_eq!(alloc.allocate(0u8).err().unwrap().0, Error::OutOfMemory);
+
+        let addr = &*first as *const _ as usize;
+        drop(first);
+        assert!(!alloc.in_use());
+
+        let second = alloc.allocate(2u64).unwrap();
+        assert_eq!(&*second as *const _ as usize, addr);
+        assert_eq!(*second, 2);
+    }
+
+    #[test]
+    fn limits() {
+        let alloc = OneShot::new(16).unwrap();
+        assert_eq!(alloc.allocate([0u8; 17]).err().unwrap().0, Error::OutOfMemory);
+        unsafe {
+            assert_eq!(alloc.allocate_raw(8, 64).err(), Some(Error::UnsupportedAlignment));
+
+            let block = alloc.allocate_raw(4, 4).unwrap();
+            let block = alloc.reallocate_raw(block, 16).ok().unwrap();
+            let (err, block) = alloc.reallocate_raw(block, 17).err().unwrap();
+            assert_eq!(err, Error::OutOfMemory);
+            alloc.deallocate_raw(block);
+        }
+        assert!(!alloc.in_use());
+    }
+}
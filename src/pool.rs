@@ -0,0 +1,72 @@
+//! A typed object pool.
+
+use std::cmp;
+use std::marker::PhantomData;
+use std::mem;
+
+use super::{Allocator, AllocBox, Error, FreeList, HeapAllocator, HEAP};
+
+/// A `Pool` hands out slots for values of a single type.
+/// It is a `FreeList` with blocks sized to fit a `T`, so values
+/// allocated from it are returned to the pool when dropped.
+pub struct Pool<'a, T, A: 'a + Allocator> {
+    free_list: FreeList<'a, A>,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Pool<'static, T, HeapAllocator> {
+    /// Creates a new `Pool` with room for `count` values, backed by the heap.
+    pub fn new(count: usize) -> Result<Self, Error> {
+        Pool::new_from(HEAP, count)
+    }
+}
+
+impl<'a, T, A: 'a + Allocator> Pool<'a, T, A> {
+    /// Creates a new `Pool` with room for `count` values, backed by another allocator.
+    pub fn new_from(alloc: &'a A, count: usize) -> Result<Self, Error> {
+        let block_size = cmp::max(mem::size_of::<T>(), mem::size_of::<*mut u8>());
+        match FreeList::new_from_aligned(alloc, block_size, count, mem::align_of::<T>()) {
+            Ok(free_list) => Ok(Pool {
+                free_list: free_list,
+                _marker: PhantomData,
+            }),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Attempts to move the value supplied into a slot of the pool.
+    pub fn get(&self, val: T) -> Result<AllocBox<T, FreeList<'a, A>>, (Error, T)> {
+        self.free_list.allocate(val)
+    }
+
+    /// The number of slots currently available.
+    pub fn available(&self) -> usize {
+        self.free_list.available()
+    }
+
+    /// The total number of slots in this pool.
+    pub fn capacity(&self) -> usize {
+        self.free_list.capacity()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    #[test]
+    fn exhaust_and_reacquire() {
+        let pool = Pool::new(4).unwrap();
+        let mut vals: Vec<_> = (0..4).map(|i| pool.get(i).ok().unwrap()).collect();
+        assert_eq!(pool.available(), 0);
+
+        let (err, val) = pool.get(4).err().unwrap();
+        assert_eq!(err, Error::OutOfMemory);
+        assert_eq!(val, 4);
+
+        vals.pop();
+        let val = pool.get(5).ok().unwrap();
+        assert_eq!(*val, 5);
+        assert_eq!(pool.capacity(), 4);
+    }
+}
@@ -0,0 +1,133 @@
+//! A cache of allocations for values of a single type.
+
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::mem;
+
+use super::{Allocator, AllocBox, Error, Block, BlockOwner, HeapAllocator, HEAP};
+
+/// A `Recycler` keeps the blocks of values of type `T` after they are dropped,
+/// and hands them out again to the next values, instead of going back to
+/// its parent allocator each time.
+///
+/// Unlike a `Pool`, it has no fixed capacity: blocks are taken from the parent
+/// whenever the cache is empty, and all the cached ones are given back when
+/// the `Recycler` is dropped.
+pub struct Recycler<'a, T, A: 'a + Allocator> {
+    alloc: &'a A,
+    cache: RefCell<Vec<*mut u8>>,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Recycler<'static, T, HeapAllocator> {
+    /// Creates a new `Recycler` backed by the heap.
+    pub fn new() -> Self {
+        Recycler::new_from(HEAP)
+    }
+}
+
+impl<T> Default for Recycler<'static, T, HeapAllocator> {
+    fn default() -> Self {
+        Recycler::new()
+    }
+}
+
+impl<'a, T, A: 'a + Allocator> Recycler<'a, T, A> {
+    /// Creates a new `Recycler` backed by another allocator.
+    pub fn new_from(alloc: &'a A) -> Self {
+        Recycler {
+            alloc: alloc,
+            cache: RefCell::new(Vec::new()),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Moves the value supplied into a recycled block, or a new one if none are cached.
+    /// The block is cached again when the box is dropped.
+    pub fn get(&self, val: T) -> Result<AllocBox<T, Self>, (Error, T)> {
+        self.allocate(val)
+    }
+
+    /// The number of blocks waiting to be reused.
+    pub fn cached(&self) -> usize {
+        self.cache.borrow().len()
+    }
+
+    // whether a request fits the blocks this caches.
+    fn recycles(size: usize, align: usize) -> bool {
+        size != 0 && size == mem::size_of::<T>() && align == mem::align_of::<T>()
+    }
+}
+
+unsafe impl<'a, T, A: 'a + Allocator> Allocator for Recycler<'a, T, A> {
+    unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        if Self::recycles(size, align) {
+            if let Some(ptr) = self.cache.borrow_mut().pop() {
+                return Ok(Block::new(ptr, size, align));
+            }
+        }
+
+        self.alloc.allocate_raw(size, align)
+    }
+
+    unsafe fn reallocate_raw<'b>(&'b self, block: Block<'b>, new_size: usize) -> Result<Block<'b>, (Error, Block<'b>)> {
+        // every block comes from the parent in the first place.
+        self.alloc.reallocate_raw(block, new_size)
+    }
+
+    unsafe fn deallocate_raw(&self, block: Block) {
+        if Self::recycles(block.size(), block.align()) {
+            self.cache.borrow_mut().push(block.ptr());
+        } else {
+            self.alloc.deallocate_raw(block);
+        }
+    }
+}
+
+impl<'a, T, A: 'a + Allocator + BlockOwner> BlockOwner for Recycler<'a, T, A> {
+    fn owns_block(&self, block: &Block) -> bool {
+        self.alloc.owns_block(block)
+    }
+}
+
+impl<'a, T, A: 'a + Allocator> Drop for Recycler<'a, T, A> {
+    fn drop(&mut self) {
+        let (size, align) = (mem::size_of::<T>(), mem::align_of::<T>());
+        for ptr in self.cache.get_mut().drain(..) {
+            unsafe { self.alloc.deallocate_raw(Block::new(ptr, size, align)) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    #[test]
+    fn reuses_blocks() {
+        let recycler = Recycler::new();
+        let first = recycler.get([1u64; 4]).ok().unwrap();
+        let addr = &*first as *const _ as usize;
+        drop(first);
+        assert_eq!(recycler.cached(), 1);
+
+        let second = recycler.get([2u64; 4]).ok().unwrap();
+        assert_eq!(&*second as *const _ as usize, addr);
+        assert_eq!(recycler.cached(), 0);
+        assert_eq!(second[3], 2);
+    }
+
+    #[test]
+    fn returned_to_parent() {
+        let parent = Proxy::new(HEAP, StatsLogger::new());
+        {
+            let recycler = Recycler::new_from(&parent);
+            for i in 0..10 {
+                let _ = recycler.get(i).ok().unwrap();
+            }
+            // only the first value went to the parent.
+            assert_eq!(parent.logger().snapshot().allocations, 1);
+        }
+        assert_eq!(parent.logger().snapshot().deallocations, 1);
+    }
+}
@@ -0,0 +1,230 @@
+//! A ring buffer allocator, for data which is freed roughly in the order it was allocated.
+
+use std::cell::{Cell, RefCell};
+use std::cmp;
+use std::collections::VecDeque;
+use std::mem;
+use std::ptr;
+
+use super::{align_forward, Allocator, Block, BlockOwner, Error, HeapAllocator, HEAP};
+
+/// A ring buffer allocator.
+///
+/// Blocks are handed out one after another from a fixed buffer, wrapping around
+/// to its start once the end is reached. Memory is reclaimed from the oldest
+/// block onwards, so this works best when blocks are freed in the order they were
+/// allocated, such as with streaming data. A block freed out of order is only reclaimed
+/// once every block allocated before it has been freed as well.
+pub struct RingAllocator<'a, A: 'a + Allocator> {
+    alloc: &'a A,
+    start: *mut u8,
+    size: usize,
+    // the offset at which the next block is placed.
+    head: Cell<usize>,
+    // the (start, end) offsets of every block not yet reclaimed,
+    // oldest first, and whether it has been freed.
+    blocks: RefCell<VecDeque<(usize, usize, bool)>>,
+}
+
+impl RingAllocator<'static, HeapAllocator> {
+    /// Creates a new `RingAllocator` backed by `size` bytes from the heap.
+    pub fn new(size: usize) -> Result<Self, Error> {
+        RingAllocator::new_from(HEAP, size)
+    }
+}
+
+impl<'a, A: 'a + Allocator> RingAllocator<'a, A> {
+    /// Creates a new `RingAllocator` backed by `size` bytes from the allocator supplied.
+    pub fn new_from(alloc: &'a A, size: usize) -> Result<Self, Error> {
+        match unsafe { alloc.allocate_raw(size, mem::align_of::<usize>()) } {
+            Ok(block) => Ok(RingAllocator {
+                alloc: alloc,
+                start: block.ptr(),
+                size: block.size(),
+                head: Cell::new(0),
+                blocks: RefCell::new(VecDeque::new()),
+            }),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// The number of blocks which have not been reclaimed yet.
+    pub fn outstanding(&self) -> usize {
+        self.blocks.borrow().len()
+    }
+
+    // the offset of the first address aligned to `align` at or after `offset`.
+    fn align_offset(&self, offset: usize, align: usize) -> Option<usize> {
+        let ptr = unsafe { self.start.offset(offset as isize) };
+        align_forward(ptr, align).map(|ptr| ptr as usize - self.start as usize)
+    }
+
+    // finds room for a block of `size` bytes, returning its start offset.
+    fn find_room(&self, blocks: &VecDeque<(usize, usize, bool)>, size: usize, align: usize) -> Option<usize> {
+        let fits = |offset: usize, limit: usize| {
+            self.align_offset(offset, align).and_then(|start| {
+                match start.checked_add(size) {
+                    Some(end) if end <= limit => Some(start),
+                    _ => None,
+                }
+            })
+        };
+
+        let head = self.head.get();
+        match blocks.front() {
+            // nothing is live, so start over from the beginning.
+            None => fits(0, self.size),
+            // the live blocks lie between the tail and head, so try after the head,
+            // and otherwise wrap around to the start.
+            Some(&(tail, _, _)) if head > tail => fits(head, self.size).or_else(|| fits(0, tail)),
+            // the head has already wrapped around, and must not run into the tail.
+            Some(&(tail, _, _)) => fits(head, tail),
+        }
+    }
+}
+
+unsafe impl<'a, A: 'a + Allocator> Allocator for RingAllocator<'a, A> {
+    unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        if size == 0 {
+            return Ok(Block::empty());
+        }
+
+        let mut blocks = self.blocks.borrow_mut();
+        match self.find_room(&blocks, size, align) {
+            Some(start) => {
+                blocks.push_back((start, start + size, false));
+                self.head.set(start + size);
+                Ok(Block::new(self.start.offset(start as isize), size, align))
+            }
+            None => Err(Error::OutOfMemory),
+        }
+    }
+
+    unsafe fn reallocate_raw<'b>(&'b self, block: Block<'b>, new_size: usize) -> Result<Block<'b>, (Error, Block<'b>)> {
+        if new_size == 0 {
+            self.deallocate_raw(block);
+            return Ok(Block::empty());
+        } else if block.is_empty() {
+            return Err((Error::UnsupportedAlignment, block));
+        }
+
+        // the newest block can be resized in place, as long as
+        // it doesn't run past the end of the buffer or into the tail.
+        let start = block.ptr() as usize - self.start as usize;
+        {
+            let mut blocks = self.blocks.borrow_mut();
+            let tail = blocks.front().map(|&(tail, _, _)| tail);
+            if let Some(last) = blocks.back_mut() {
+                if last.0 == start {
+                    let limit = match tail {
+                        Some(tail) if tail > start => tail,
+                        _ => self.size,
+                    };
+                    if new_size <= limit - start {
+                        last.1 = start + new_size;
+                        self.head.set(start + new_size);
+                        return Ok(Block::new(block.ptr(), new_size, block.align()));
+                    }
+                }
+            }
+        }
+
+        match self.allocate_raw(new_size, block.align()) {
+            Ok(new_block) => {
+                ptr::copy_nonoverlapping(block.ptr(), new_block.ptr(), cmp::min(block.size(), new_size));
+                self.deallocate_raw(block);
+                Ok(new_block)
+            }
+            Err(err) => Err((err, block)),
+        }
+    }
+
+    unsafe fn deallocate_raw(&self, block: Block) {
+        if block.is_empty() || !self.owns_block(&block) {
+            return;
+        }
+
+        let start = block.ptr() as usize - self.start as usize;
+        let mut blocks = self.blocks.borrow_mut();
+        if let Some(entry) = blocks.iter_mut().find(|entry| entry.0 == start && !entry.2) {
+            entry.2 = true;
+        }
+
+        // reclaim every freed block from the tail onwards.
+        while let Some(&(_, _, true)) = blocks.front() {
+            blocks.pop_front();
+        }
+    }
+}
+
+impl<'a, A: 'a + Allocator> BlockOwner for RingAllocator<'a, A> {
+    fn owns_block(&self, block: &Block) -> bool {
+        let ptr = block.ptr() as usize;
+        let start = self.start as usize;
+
+        ptr >= start && ptr + block.size() <= start + self.size
+    }
+}
+
+impl<'a, A: 'a + Allocator> Drop for RingAllocator<'a, A> {
+    fn drop(&mut self) {
+        if self.size > 0 {
+            unsafe {
+                self.alloc.deallocate_raw(Block::new(self.start, self.size, mem::align_of::<usize>()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::super::*;
+
+    #[test]
+    fn cycling() {
+        let alloc = RingAllocator::new(256).unwrap();
+        let mut live = VecDeque::new();
+        let mut wrapped = 0;
+        let first = unsafe { alloc.allocate_raw(64, 8).unwrap() };
+        let start = first.ptr();
+        live.push_back(first);
+
+        // keep three blocks alive at a time, going around the buffer many times.
+        for _ in 0..20 {
+            let block = unsafe { alloc.allocate_raw(64, 8).unwrap() };
+            if block.ptr() == start {
+                wrapped += 1;
+            }
+            live.push_back(block);
+            if live.len() == 3 {
+                unsafe { alloc.deallocate_raw(live.pop_front().unwrap()) };
+            }
+        }
+
+        assert!(wrapped >= 4);
+        assert_eq!(alloc.outstanding(), 2);
+        // the buffer can't hold three more blocks with two still live.
+        let blocks: Vec<_> = (0..2).map(|_| unsafe { alloc.allocate_raw(64, 8).unwrap() }).collect();
+        assert!(unsafe { alloc.allocate_raw(64, 8) }.is_err());
+        drop(blocks);
+    }
+
+    #[test]
+    fn out_of_order() {
+        let alloc = RingAllocator::new(64).unwrap();
+        unsafe {
+            let a = alloc.allocate_raw(32, 8).unwrap();
+            let b = alloc.allocate_raw(32, 8).unwrap();
+            alloc.deallocate_raw(b);
+            // `b` can't be reclaimed before `a` is.
+            assert_eq!(alloc.outstanding(), 2);
+            assert!(alloc.allocate_raw(8, 8).is_err());
+
+            alloc.deallocate_raw(a);
+            assert_eq!(alloc.outstanding(), 0);
+            assert!(alloc.allocate_raw(64, 8).is_ok());
+        }
+    }
+}
@@ -1,41 +1,234 @@
 //! A scoped linear allocator. This is something of a cross between a stack allocator
 //! and a traditional linear allocator.
 
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
+use std::cmp;
+use std::error::Error as StdError;
+use std::fmt;
 use std::mem;
 use std::ptr;
 
-use super::{Allocator, Error, Block, BlockOwner, HeapAllocator, HEAP};
+use super::{Allocator, Error, Block, BlockOwner, HeapAllocator, NullAllocator, HEAP};
+#[cfg(unix)]
+use super::mmap::{GuardPageAllocator, GUARD_PAGES};
 
 /// A scoped linear allocator.
+///
+/// # Threads
+/// A `Scoped` can be sent to another thread, as long as its parent allocator
+/// can be shared between threads, since that's where its buffer is freed:
+///
+/// ```rust
+/// use std::thread;
+/// use allocators::{Allocator, Scoped};
+///
+/// let alloc = Scoped::new(64).unwrap();
+/// thread::spawn(move || {
+///     let val = alloc.allocate(5u32).unwrap();
+///     assert_eq!(*val, 5);
+/// }).join().unwrap();
+/// ```
+///
+/// Its bump pointer isn't atomic, though, so it can't be used from several threads
+/// at once without a `Mutex` around it. `AtomicScoped` can be, instead.
+///
+/// ```rust,compile_fail
+/// use std::sync::Arc;
+/// use std::thread;
+/// use allocators::{Allocator, Scoped};
+///
+/// let alloc = Arc::new(Scoped::new(64).unwrap());
+/// let shared = alloc.clone();
+/// thread::spawn(move || shared.allocate(5u32).is_ok());
+/// ```
 pub struct Scoped<'parent, A: 'parent + Allocator> {
+    // the alignment of the buffer, which it is freed with.
+    align: usize,
     allocator: &'parent A,
     current: Cell<*mut u8>,
     end: *mut u8,
+    // the spans below `current` freed out of order, sorted by address,
+    // as (start, end) pairs. Adjacent spans are merged.
+    // this stays empty, and never allocates, unless `reuse_spans` is set.
+    free_spans: RefCell<Vec<(usize, usize)>>,
+    reuse_spans: bool,
+    // everything at or above this is known to hold zeroes.
+    // it only moves up until the buffer is given back.
+    high_water: Cell<*mut u8>,
     root: bool,
     start: *mut u8,
 }
 
+/// The error returned when a scope can't be entered.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ScopeError {
+    /// The allocator is already scoped.
+    AlreadyScoped,
+}
+
+impl fmt::Display for ScopeError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(self.description())
+    }
+}
+
+impl StdError for ScopeError {
+    fn description(&self) -> &str {
+        match *self {
+            ScopeError::AlreadyScoped => "Called scope on already scoped allocator.",
+        }
+    }
+}
+
 impl Scoped<'static, HeapAllocator> {
     /// Creates a new `Scoped` backed by `size` bytes from the heap.
     pub fn new(size: usize) -> Result<Self, Error> {
         Scoped::new_from(HEAP, size)
     }
+
+    /// Creates a new `Scoped` backed by `size` bytes from the heap,
+    /// whose buffer is aligned to `align`.
+    /// See `new_from_aligned`.
+    pub fn new_aligned(size: usize, align: usize) -> Result<Self, Error> {
+        Scoped::new_from_aligned(HEAP, size, align)
+    }
+
+    /// Creates a new `Scoped` backed by `size` zeroed bytes from the heap.
+    /// See `new_from_zeroed`.
+    pub fn new_zeroed(size: usize) -> Result<Self, Error> {
+        Scoped::new_from_zeroed(HEAP, size)
+    }
+}
+
+// the parent of a `Scoped` over a borrowed buffer, which has nothing to give back.
+const NULL: &'static NullAllocator = &NullAllocator;
+
+/// The size of the buffer of a `Scoped` made by `default`: 64 KiB.
+pub const DEFAULT_SCOPED_SIZE: usize = 64 * 1024;
+
+impl Default for Scoped<'static, HeapAllocator> {
+    /// Creates a new `Scoped` backed by `DEFAULT_SCOPED_SIZE` bytes from the heap.
+    ///
+    /// # Panics
+    /// Panics if the buffer can't be allocated.
+    fn default() -> Self {
+        Scoped::new(DEFAULT_SCOPED_SIZE).expect("Failed to allocate the buffer of a default Scoped.")
+    }
+}
+
+#[cfg(unix)]
+impl Scoped<'static, GuardPageAllocator> {
+    /// Creates a new `Scoped` whose buffer is mapped from the operating system,
+    /// and followed by an inaccessible guard page. Code writing past the end of the
+    /// arena then faults instead of corrupting other memory.
+    ///
+    /// `size` is rounded up to whole pages.
+    pub fn new_guarded(size: usize) -> Result<Self, Error> {
+        match GUARD_PAGES.round_up(size) {
+            Some(size) => Scoped::new_from(GUARD_PAGES, size),
+            None => Err(Error::OutOfMemory),
+        }
+    }
+}
+
+impl<'buf> Scoped<'buf, NullAllocator> {
+    /// Creates a new `Scoped` which allocates from the buffer supplied,
+    /// rather than from a parent allocator. Nothing is freed when it is dropped,
+    /// so the buffer can live anywhere: on the stack, or in a `static mut`.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use allocators::{Allocator, Scoped};
+    ///
+    /// let mut buf = [0u8; 64];
+    /// let alloc = Scoped::from_buffer(&mut buf);
+    /// let val = alloc.allocate(5u32).unwrap();
+    /// assert_eq!(*val, 5);
+    /// ```
+    pub fn from_buffer(buf: &'buf mut [u8]) -> Self {
+        let start = buf.as_mut_ptr();
+        let end = unsafe { start.offset(buf.len() as isize) };
+        Scoped {
+            align: 1,
+            allocator: NULL,
+            current: Cell::new(start),
+            end: end,
+            free_spans: RefCell::new(Vec::new()),
+            reuse_spans: false,
+            // nothing is known about what the buffer holds.
+            high_water: Cell::new(end),
+            // the buffer isn't ours to free.
+            root: false,
+            start: start,
+        }
+    }
 }
 
 impl<'parent, A: Allocator> Scoped<'parent, A> {
     /// Creates a new `Scoped` backed by `size` bytes from the allocator supplied.
+    ///
+    /// If the allocator can't provide the buffer, this fails with `Error::NoBuffer`,
+    /// giving the layout requested and the allocator's own error.
     pub fn new_from(alloc: &'parent A, size: usize) -> Result<Self, Error> {
-        // Create a memory buffer with the desired size and maximal align from the parent.
-        match unsafe { alloc.allocate_raw(size, mem::align_of::<usize>()) } {
-            Ok(block) => Ok(Scoped {
-                allocator: alloc,
-                current: Cell::new(block.ptr()),
-                end: unsafe { block.ptr().offset(block.size() as isize) },
-                root: true,
-                start: block.ptr(),
+        Scoped::new_from_aligned(alloc, size, mem::align_of::<usize>())
+    }
+
+    /// Creates a new `Scoped` backed by `size` bytes from the allocator supplied,
+    /// whose buffer is aligned to `align`. Allocations with alignment up to `align`
+    /// then never need padding at the start of the buffer, so the first of them
+    /// can use the whole of it.
+    /// `align` must be a power of two, and is raised to the alignment of a `usize`
+    /// if it is smaller.
+    pub fn new_from_aligned(alloc: &'parent A, size: usize, align: usize) -> Result<Self, Error> {
+        Scoped::from_parent(alloc, size, align, false)
+    }
+
+    /// Creates a new `Scoped` backed by `size` bytes from the allocator supplied,
+    /// obtained through `allocate_zeroed_raw`.
+    ///
+    /// Knowing that the buffer starts out zeroed lets `allocate_zeroed_raw` skip
+    /// clearing memory that has never been handed out, and only clear what might
+    /// have been written since. This is cheapest with parents which get zeroed memory
+    /// for free, like the heap for large buffers.
+    pub fn new_from_zeroed(alloc: &'parent A, size: usize) -> Result<Self, Error> {
+        Scoped::from_parent(alloc, size, mem::align_of::<usize>(), true)
+    }
+
+    fn from_parent(alloc: &'parent A, size: usize, align: usize, zeroed: bool) -> Result<Self, Error> {
+        if !align.is_power_of_two() {
+            return Err(Error::UnsupportedAlignment);
+        }
+        let align = cmp::max(align, mem::align_of::<usize>());
+
+        // Create a memory buffer with the desired size and align from the parent.
+        let block = unsafe {
+            if zeroed {
+                alloc.allocate_zeroed_raw(size, align)
+            } else {
+                alloc.allocate_raw(size, align)
+            }
+        };
+
+        match block {
+            Ok(block) => {
+                let end = unsafe { block.ptr().offset(block.size() as isize) };
+                Ok(Scoped {
+                    align: align,
+                    allocator: alloc,
+                    current: Cell::new(block.ptr()),
+                    end: end,
+                    free_spans: RefCell::new(Vec::new()),
+                    reuse_spans: false,
+                    high_water: Cell::new(if zeroed { block.ptr() } else { end }),
+                    root: true,
+                    start: block.ptr(),
+                })
+            }
+            Err(err) => Err(Error::NoBuffer {
+                size: size,
+                align: align,
+                cause: Box::new(err),
             }),
-            Err(err) => Err(err),
         }
     }
 
@@ -43,88 +236,417 @@ impl<'parent, A: Allocator> Scoped<'parent, A> {
     ///
     /// Returns the result of the closure or an error if this allocator
     /// has already been scoped.
-    pub fn scope<F, U>(&self, f: F) -> Result<U, ()>
+    ///
+    /// The memory allocated within the scope is reclaimed when it ends, so values
+    /// allocated there borrow the inner allocator and can't be returned from the closure:
+    ///
+    /// ```rust,compile_fail
+    /// use allocators::{Allocator, Scoped};
+    ///
+    /// let alloc = Scoped::new(64).unwrap();
+    /// let escaped = alloc.scope(|inner| inner.allocate(5u32).unwrap()).unwrap();
+    /// ```
+    pub fn scope<F, U>(&self, f: F) -> Result<U, ScopeError>
         where F: FnMut(&Self) -> U
     {
         if self.is_scoped() {
-            return Err(());
+            return Err(ScopeError::AlreadyScoped);
         }
 
         let mut f = f;
         let old = self.current.get();
         let alloc = Scoped {
+            align: self.align,
             allocator: self.allocator,
             current: self.current.clone(),
             end: self.end,
+            free_spans: RefCell::new(Vec::new()),
+            reuse_spans: self.reuse_spans,
+            high_water: self.high_water.clone(),
             root: false,
             start: old,
         };
 
+        // restore the current pointer once the scope ends, even if `f` panics,
+        // and take on the inner allocator's high-water mark.
+        struct Exit<'a> {
+            current: &'a Cell<*mut u8>,
+            old: *mut u8,
+            high_water: &'a Cell<*mut u8>,
+            inner_high_water: &'a Cell<*mut u8>,
+        }
+        impl<'a> Drop for Exit<'a> {
+            fn drop(&mut self) {
+                self.current.set(self.old);
+                self.high_water.set(self.inner_high_water.get());
+            }
+        }
+
         // set the current pointer to null as a flag to indicate
         // that this allocator is being scoped.
         self.current.set(ptr::null_mut());
+        let exit = Exit {
+            current: &self.current,
+            old: old,
+            high_water: &self.high_water,
+            inner_high_water: &alloc.high_water,
+        };
         let u = f(&alloc);
-        self.current.set(old);
+        drop(exit);
 
+        // the inner allocator must not free the buffer, but its spans are its own.
+        drop(mem::replace(&mut *alloc.free_spans.borrow_mut(), Vec::new()));
         mem::forget(alloc);
         Ok(u)
     }
 
+    /// Calls the supplied function with this allocator, like `scope`, but without
+    /// reclaiming the memory allocated within it when it ends.
+    ///
+    /// Since that memory stays allocated until this allocator is reset or dropped,
+    /// values leaked within the closure can be returned from it by reference:
+    ///
+    /// ```rust
+    /// use allocators::{Allocator, Scoped};
+    ///
+    /// let alloc = Scoped::new(64).unwrap();
+    /// let nums = alloc.scope_ref(|inner| {
+    ///     let nums = inner.allocate([1u32, 2, 3]).unwrap().leak();
+    ///     nums[0] = 4;
+    ///     nums
+    /// }).unwrap();
+    /// assert_eq!(*nums, [4, 2, 3]);
+    /// ```
+    ///
+    /// Those references can't outlive this allocator, though:
+    ///
+    /// ```rust,compile_fail
+    /// use allocators::{Allocator, Scoped};
+    ///
+    /// let num;
+    /// {
+    ///     let alloc = Scoped::new(64).unwrap();
+    ///     num = alloc.scope_ref(|inner| inner.allocate(5u32).unwrap().leak()).unwrap();
+    /// }
+    /// assert_eq!(*num, 5);
+    /// ```
+    ///
+    /// Returns an error if this allocator has already been scoped.
+    pub fn scope_ref<'s, F, U>(&'s self, f: F) -> Result<U, ScopeError>
+        where F: FnOnce(&'s Self) -> U
+    {
+        if self.is_scoped() {
+            return Err(ScopeError::AlreadyScoped);
+        }
+
+        Ok(f(self))
+    }
+
     // Whether this allocator is currently scoped.
     pub fn is_scoped(&self) -> bool {
         self.current.get().is_null()
     }
+
+    /// The number of bytes allocated from this scope so far,
+    /// including any padding inserted for alignment.
+    /// Returns 0 if this allocator is scoped.
+    pub fn bytes_used(&self) -> usize {
+        if self.is_scoped() {
+            0
+        } else {
+            self.current.get() as usize - self.start as usize
+        }
+    }
+
+    /// The number of bytes left for allocation in this scope.
+    /// Returns 0 if this allocator is scoped.
+    pub fn bytes_remaining(&self) -> usize {
+        if self.is_scoped() {
+            0
+        } else {
+            self.end as usize - self.current.get() as usize
+        }
+    }
+
+    /// Makes this allocator keep track of blocks freed out of order,
+    /// as described in `free_span_bytes`.
+    ///
+    /// The spans are recorded in a `Vec` from the global heap, and every allocation
+    /// looks through them before bumping the pointer, so this is off by default.
+    /// Without it, only freeing the most recent block gives memory back, and
+    /// this allocator never touches the global heap itself.
+    pub fn with_free_spans(mut self) -> Self {
+        self.reuse_spans = true;
+        self
+    }
+
+    /// The number of bytes freed out of order which are waiting to be reused.
+    ///
+    /// Only freeing the most recent block moves the bump pointer back. With `with_free_spans`,
+    /// other blocks are kept track of, to serve later allocations which fit in them, and to
+    /// be given back along with the most recent block once everything after them is freed too.
+    /// They're still counted by `bytes_used`. Without it, they're lost until the scope ends
+    /// or the allocator is reset, and this is always 0.
+    pub fn free_span_bytes(&self) -> usize {
+        self.free_spans.borrow().iter().map(|&(start, end)| end - start).sum()
+    }
+
+    // records a span freed below the current position, merging it with its neighbours.
+    fn free_span(&self, start: usize, end: usize) {
+        if !self.reuse_spans {
+            return;
+        }
+
+        let mut spans = self.free_spans.borrow_mut();
+        let index = match spans.binary_search(&(start, end)) {
+            Ok(_) => return,
+            Err(index) => index,
+        };
+
+        spans.insert(index, (start, end));
+        if index + 1 < spans.len() && spans[index + 1].0 == end {
+            spans[index].1 = spans.remove(index + 1).1;
+        }
+        if index > 0 && spans[index - 1].1 == start {
+            spans[index - 1].1 = spans.remove(index).1;
+        }
+    }
+
+    // moves the bump pointer forward to `ptr`, raising the high-water mark with it.
+    fn bump_to(&self, ptr: *mut u8) {
+        self.current.set(ptr);
+        if ptr > self.high_water.get() {
+            self.high_water.set(ptr);
+        }
+    }
+
+    // moves the bump pointer back to `ptr`, along with any free span right below it.
+    fn rewind_to(&self, ptr: *mut u8) {
+        let mut current = ptr as usize;
+        let mut spans = self.free_spans.borrow_mut();
+        while spans.last().map_or(false, |&(_, end)| end == current) {
+            current = spans.pop().unwrap().0;
+        }
+        self.current.set(current as *mut u8);
+    }
+
+    // attempts to serve an allocation from the free spans.
+    fn allocate_from_spans(&self, size: usize, align: usize) -> Option<*mut u8> {
+        let mut spans = self.free_spans.borrow_mut();
+        for index in 0..spans.len() {
+            let (start, end) = spans[index];
+            let aligned = match start.checked_add(align - 1) {
+                Some(ptr) => ptr & !(align - 1),
+                None => continue,
+            };
+            if aligned.checked_add(size).map_or(true, |block_end| block_end > end) {
+                continue;
+            }
+
+            // keep whatever is left on either side.
+            spans.remove(index);
+            if aligned + size < end {
+                spans.insert(index, (aligned + size, end));
+            }
+            if start < aligned {
+                spans.insert(index, (start, aligned));
+            }
+            return Some(aligned as *mut u8);
+        }
+        None
+    }
+
+    /// Makes the entire buffer of this allocator available again.
+    ///
+    /// Values allocated from this allocator borrow it, so taking `&mut self`
+    /// guarantees that none of them are still alive. Blocks obtained through
+    /// `allocate_raw` are not tracked, however, and are invalidated by this.
+    /// Does nothing if this allocator is scoped.
+    pub fn reset(&mut self) {
+        if !self.is_scoped() {
+            self.current.set(self.start);
+            self.free_spans.get_mut().clear();
+        }
+    }
+
+    /// Resizes the buffer of this allocator to `new_size` bytes through the parent allocator,
+    /// keeping the bytes allocated so far. This can give back the memory of an arena
+    /// sized for a peak that has passed.
+    ///
+    /// The buffer may move, which taking `&mut self` makes safe for values allocated
+    /// from this allocator. Blocks obtained through `allocate_raw` are invalidated, though.
+    ///
+    /// Fails if more than `new_size` bytes are in use, or if the parent can't
+    /// resize the buffer, in which case this allocator is left as it was.
+    pub fn shrink_to(&mut self, new_size: usize) -> Result<(), Error> {
+        if self.is_scoped() {
+            return Err(Error::AlreadyScoped);
+        }
+
+        let used = self.bytes_used();
+        if used > new_size {
+            return Err(Error::AllocatorSpecific("Cannot shrink a Scoped below the bytes in use.".into()));
+        }
+
+        let size = self.end as usize - self.start as usize;
+        let block = Block::new(self.start, size, self.align);
+        match unsafe { self.allocator.reallocate_raw(block, new_size) } {
+            Ok(block) => unsafe {
+                // the free spans move along with the buffer.
+                let moved = block.ptr() as usize;
+                let old_start = self.start as usize;
+                for span in self.free_spans.get_mut().iter_mut() {
+                    *span = (span.0 - old_start + moved, span.1 - old_start + moved);
+                }
+
+                // only the bytes kept from the old buffer are known to be zero,
+                // so a grown buffer counts as written to the end.
+                let clean = if block.size() > size {
+                    block.size()
+                } else {
+                    cmp::min(self.high_water.get() as usize - old_start, block.size())
+                };
+
+                self.start = block.ptr();
+                self.current.set(block.ptr().offset(used as isize));
+                self.end = block.ptr().offset(block.size() as isize);
+                self.high_water.set(block.ptr().offset(clean as isize));
+                Ok(())
+            },
+            Err((err, _)) => Err(err),
+        }
+    }
+
+    /// Claims all the bytes left at the end of this allocator's buffer as a single block,
+    /// with an alignment of 1, so that it can be filled in bulk, like reading a file into it.
+    /// The block covers `bytes_remaining()` bytes, and nothing more can be allocated
+    /// past it until it is deallocated, or the allocator is reset or rewound.
+    ///
+    /// Returns an empty block if this allocator is scoped or full.
+    pub fn remaining_block(&self) -> Block {
+        let remaining = self.bytes_remaining();
+        if remaining == 0 {
+            return Block::empty();
+        }
+
+        let current = self.current.get();
+        self.bump_to(self.end);
+        Block::new(current, remaining, 1)
+    }
+
+    /// Records the current position of this allocator, to be later
+    /// passed to `rewind`.
+    pub fn mark(&self) -> Marker {
+        Marker(self.current.get())
+    }
+
+    /// Rewinds this allocator to a previously recorded position,
+    /// making all memory allocated since then available again.
+    ///
+    /// # Safety
+    /// This invalidates every value allocated after the mark was taken.
+    /// It is the caller's responsibility to not use them afterwards.
+    ///
+    /// # Panics
+    /// Panics if this allocator is scoped or the marker does not lie
+    /// between the start of this allocator and its current position.
+    pub unsafe fn rewind(&self, marker: Marker) {
+        assert!(!self.is_scoped(), "Called rewind on already scoped allocator.");
+        assert!(marker.0 >= self.start && marker.0 <= self.current.get(),
+                "Marker does not belong to the live region of this allocator.");
+
+        let mark = marker.0 as usize;
+        let mut spans = self.free_spans.borrow_mut();
+        spans.retain(|&(start, _)| start < mark);
+        if let Some(last) = spans.last_mut() {
+            last.1 = cmp::min(last.1, mark);
+        }
+        drop(spans);
+        self.rewind_to(marker.0);
+    }
 }
 
+/// A saved position in a `Scoped` allocator.
+/// See `Scoped::mark` and `Scoped::rewind`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Marker(*mut u8);
+
 unsafe impl<'a, A: Allocator> Allocator for Scoped<'a, A> {
     unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
         if self.is_scoped() {
-            return Err(Error::AllocatorSpecific("Called allocate on already scoped \
-                                                          allocator."
-                                                             .into()));
+            return Err(Error::AlreadyScoped);
         }
 
         if size == 0 {
             return Ok(Block::empty());
         }
 
+        if let Some(ptr) = self.allocate_from_spans(size, align) {
+            return Ok(Block::new(ptr, size, align));
+        }
+
         let current_ptr = self.current.get();
-        let aligned_ptr = super::align_forward(current_ptr, align);
-        let end_ptr = aligned_ptr.offset(size as isize);
+        let aligned_ptr = match super::align_forward(current_ptr, align) {
+            Some(ptr) => ptr,
+            None => return Err(Error::OutOfMemory),
+        };
 
-        if end_ptr > self.end {
-            Err(Error::OutOfMemory)
-        } else {
-            self.current.set(end_ptr);
-            Ok(Block::new(aligned_ptr, size, align))
+        match (aligned_ptr as usize).checked_add(size) {
+            Some(end) if end <= self.end as usize => {
+                self.bump_to(end as *mut u8);
+                Ok(Block::new(aligned_ptr, size, align))
+            }
+            _ => Err(Error::OutOfMemory),
         }
     }
 
-    /// Because of the way this allocator is designed, reallocating a block that is not 
-    /// the most recent will lead to fragmentation.
+    /// Memory which has been handed out before, in this scope or any other since
+    /// the buffer was obtained, is cleared here. The rest of the buffer is only
+    /// known to be zero if it came zeroed from the parent, as with `new_from_zeroed`;
+    /// otherwise, the whole block is cleared.
+    unsafe fn allocate_zeroed_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        let high_water = self.high_water.get() as usize;
+        match self.allocate_raw(size, align) {
+            Ok(block) => {
+                let ptr = block.ptr() as usize;
+                if !block.is_empty() && ptr < high_water {
+                    ptr::write_bytes(block.ptr(), 0, cmp::min(block.size(), high_water - ptr));
+                }
+                Ok(block)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Because of the way this allocator is designed, reallocating a block that is not
+    /// the most recent moves it, leaving its old space in a free span if those are kept.
     unsafe fn reallocate_raw<'b>(&'b self, block: Block<'b>, new_size: usize) -> Result<Block<'b>, (Error, Block<'b>)> {
         let current_ptr = self.current.get();
 
         if new_size == 0 {
+            self.deallocate_raw(block);
             Ok(Block::empty())
         } else if block.is_empty() {
             Err((Error::UnsupportedAlignment, block))
         } else if block.ptr().offset(block.size() as isize) == current_ptr {
             // if this block is the last allocated, resize it if we can.
             // otherwise, we are out of memory.
-            let new_cur = current_ptr.offset((new_size - block.size()) as isize);
-            if new_cur < self.end {
-                self.current.set(new_cur);
+            // like `allocate_raw`, this may fill the buffer right up to the end.
+            let fits = (block.ptr() as usize).checked_add(new_size)
+                                             .map_or(false, |new_cur| new_cur <= self.end as usize);
+            if fits {
+                self.bump_to(block.ptr().offset(new_size as isize));
                 Ok(Block::new(block.ptr(), new_size, block.align()))
             } else {
                 Err((Error::OutOfMemory, block))
             }
         } else {
-            // try to allocate a new block at the end, and copy the old mem over.
-            // this will lead to some fragmentation.
+            // try to allocate a new block elsewhere, and copy the old mem over.
+            // the old block is kept for reuse.
             match self.allocate_raw(new_size, block.align()) {
                 Ok(new_block) => {
-                    ptr::copy_nonoverlapping(block.ptr(), new_block.ptr(), block.size());
+                    ptr::copy_nonoverlapping(block.ptr(), new_block.ptr(), cmp::min(block.size(), new_size));
+                    self.deallocate_raw(block);
                     Ok(new_block)
                 }
                 Err(err) => {
@@ -134,15 +656,48 @@ unsafe impl<'a, A: Allocator> Allocator for Scoped<'a, A> {
         }
     }
 
+    unsafe fn grow_in_place<'b>(&'b self, block: Block<'b>, new_size: usize) -> Result<Block<'b>, (Error, Block<'b>)> {
+        // only the last block has room after it.
+        if block.is_empty() || block.ptr().offset(block.size() as isize) != self.current.get() {
+            return Err((Error::CannotResizeInPlace, block));
+        }
+
+        let fits = (block.ptr() as usize).checked_add(new_size)
+                                         .map_or(false, |new_cur| new_cur <= self.end as usize);
+        if fits {
+            self.bump_to(block.ptr().offset(new_size as isize));
+            Ok(Block::new(block.ptr(), new_size, block.align()))
+        } else {
+            Err((Error::CannotResizeInPlace, block))
+        }
+    }
+
+    unsafe fn shrink_in_place<'b>(&'b self, block: Block<'b>, new_size: usize) -> Result<Block<'b>, (Error, Block<'b>)> {
+        if block.is_empty() {
+            return Err((Error::CannotResizeInPlace, block));
+        }
+
+        // any block can shrink, giving its tail back.
+        let tail = block.ptr().offset(new_size as isize);
+        if block.ptr().offset(block.size() as isize) == self.current.get() {
+            self.rewind_to(tail);
+        } else if new_size < block.size() {
+            self.free_span(tail as usize, block.ptr() as usize + block.size());
+        }
+        Ok(Block::new(block.ptr(), new_size, block.align()))
+    }
+
     unsafe fn deallocate_raw(&self, block: Block) {
         if block.is_empty() || block.ptr().is_null() {
             return;
         }
-        // no op for this unless this is the last allocation.
-        // The memory gets reused when the scope is cleared.
+        // the last allocation moves the bump pointer back. Others are
+        // kept as free spans, until everything after them is freed as well.
         let current_ptr = self.current.get();
         if !self.is_scoped() && block.ptr().offset(block.size() as isize) == current_ptr {
-            self.current.set(block.ptr());
+            self.rewind_to(block.ptr());
+        } else {
+            self.free_span(block.ptr() as usize, block.ptr() as usize + block.size());
         }
     }
 }
@@ -151,7 +706,7 @@ impl<'a, A: Allocator> BlockOwner for Scoped<'a, A> {
     fn owns_block(&self, block: &Block) -> bool {
         let ptr = block.ptr();
 
-        ptr >= self.start && ptr <= self.end
+        ptr >= self.start && (ptr as usize + block.size()) <= self.end as usize
     }
 }
 
@@ -165,14 +720,87 @@ impl<'a, A: Allocator> Drop for Scoped<'a, A> {
         if self.root && size > 0 {
             unsafe {
                 self.allocator
-                    .deallocate_raw(Block::new(self.start, size, mem::align_of::<usize>()))
+                    .deallocate_raw(Block::new(self.start, size, self.align))
             }
         }
     }
 }
 
+// the buffer belongs to this allocator alone, so moving it to another thread
+// is fine. The parent is only borrowed, though, and will be called from
+// that thread, so it has to be `Sync`. `Scoped` is never `Sync` itself.
 unsafe impl<'a, A: 'a + Allocator + Sync> Send for Scoped<'a, A> {}
 
+/// A typed front end to a `Scoped` allocator which runs the destructors of
+/// the values allocated through it when it is dropped, in the reverse order of allocation.
+///
+/// Values are handed out as plain references, so they can't be freed
+/// one by one; the memory is reclaimed along with that of the underlying allocator.
+pub struct DropScoped<'a, 'parent: 'a, A: 'parent + Allocator> {
+    scoped: &'a Scoped<'parent, A>,
+    destructors: RefCell<Vec<(*mut u8, unsafe fn(*mut u8))>>,
+}
+
+unsafe fn drop_glue<T>(ptr: *mut u8) {
+    ptr::drop_in_place(ptr as *mut T);
+}
+
+impl<'a, 'parent: 'a, A: 'parent + Allocator> DropScoped<'a, 'parent, A> {
+    /// Creates a new `DropScoped` allocating from `scoped`.
+    pub fn new(scoped: &'a Scoped<'parent, A>) -> Self {
+        DropScoped {
+            scoped: scoped,
+            destructors: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Moves a value into the allocator, returning a reference to it.
+    /// Its destructor will run when this is dropped, so it must not
+    /// borrow anything that doesn't outlive the underlying allocator.
+    pub fn allocate<T: 'a>(&self, val: T) -> Result<&mut T, (Error, T)> {
+        let (size, align) = (mem::size_of::<T>(), mem::align_of::<T>());
+        let ptr = match unsafe { self.scoped.allocate_raw(size, align) } {
+            // zero-sized values need an aligned address, but no memory.
+            Ok(ref block) if block.is_empty() => align as *mut T,
+            Ok(block) => block.ptr() as *mut T,
+            Err(err) => return Err((err, val)),
+        };
+
+        unsafe {
+            ptr::write(ptr, val);
+            self.destructors.borrow_mut().push((ptr as *mut u8, drop_glue::<T>));
+            Ok(&mut *ptr)
+        }
+    }
+
+    /// Calls the supplied function with a new scope of the underlying allocator.
+    /// The destructors of values allocated within the scope run when it ends.
+    pub fn scope<F, U>(&self, mut f: F) -> Result<U, ScopeError>
+        where F: for<'b> FnMut(&DropScoped<'b, 'parent, A>) -> U
+    {
+        self.scoped.scope(|inner| f(&DropScoped::new(inner)))
+    }
+
+    /// The number of values whose destructors are pending.
+    pub fn len(&self) -> usize {
+        self.destructors.borrow().len()
+    }
+
+    /// Whether no destructors are pending.
+    pub fn is_empty(&self) -> bool {
+        self.destructors.borrow().is_empty()
+    }
+}
+
+impl<'a, 'parent: 'a, A: 'parent + Allocator> Drop for DropScoped<'a, 'parent, A> {
+    fn drop(&mut self) {
+        let destructors = mem::replace(self.destructors.get_mut(), Vec::new());
+        for &(ptr, destructor) in destructors.iter().rev() {
+            unsafe { destructor(ptr) };
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::super::*;
@@ -189,6 +817,41 @@ mod tests {
              .unwrap();
     }
 
+    #[test]
+    fn already_scoped_error() {
+        let alloc = Scoped::new(64).unwrap();
+        alloc.scope(|_inner| {
+            assert_eq!(alloc.allocate(0u8).err().unwrap().0, Error::AlreadyScoped);
+        }).unwrap();
+    }
+
+    #[test]
+    fn resize_in_place() {
+        let alloc = Scoped::new(64).unwrap();
+        unsafe {
+            let first = alloc.allocate_raw(8, 8).unwrap();
+            let last = alloc.allocate_raw(8, 8).unwrap();
+
+            let (err, _first) = alloc.grow_in_place(first, 16).err().unwrap();
+            assert_eq!(err, Error::CannotResizeInPlace);
+            let last = alloc.grow_in_place(last, 48).ok().unwrap();
+            assert_eq!(alloc.bytes_used(), 56);
+            assert!(alloc.grow_in_place(last, 64).is_err());
+        }
+
+        let alloc = Scoped::new(64).unwrap();
+        unsafe {
+            let first = alloc.allocate_raw(8, 8).unwrap();
+            let last = alloc.allocate_raw(32, 8).unwrap();
+            let last = alloc.shrink_in_place(last, 16).ok().unwrap();
+            assert_eq!(alloc.bytes_used(), 24);
+            // a block in the middle shrinks too, but its tail is lost.
+            let first = alloc.shrink_in_place(first, 4).ok().unwrap();
+            assert_eq!(first.size(), 4);
+            assert_eq!(alloc.bytes_used(), 24);
+        }
+    }
+
     #[test]
     fn scope_scope() {
         let alloc = Scoped::new(64).unwrap();
@@ -199,6 +862,8 @@ mod tests {
                           let _ = bottom.allocate(23);
                       })
                       .unwrap();
+                 // the outer allocator can't be scoped twice.
+                 assert_eq!(alloc.scope(|_| ()).err(), Some(ScopeError::AlreadyScoped));
              })
              .unwrap();
     }
@@ -211,6 +876,16 @@ mod tests {
         assert_eq!(err, Error::OutOfMemory);
     }
 
+    #[test]
+    fn zero_sized() {
+        let alloc = Scoped::new(0).unwrap();
+        let unit = alloc.allocate(()).unwrap();
+        let empty = alloc.allocate([0u64; 0]).unwrap();
+        assert_eq!(&*empty as *const [u64; 0] as usize % 8, 0);
+        assert_eq!(*unit, ());
+        assert_eq!(alloc.bytes_used(), 0);
+    }
+
     #[test]
     fn placement_in() {
         let alloc = Scoped::new(8_000_000).unwrap();
@@ -233,6 +908,253 @@ mod tests {
              .unwrap();
     }
 
+    #[test]
+    fn mark_rewind() {
+        let alloc = Scoped::new(64).unwrap();
+        let _byte = alloc.allocate(0u8).unwrap();
+
+        let marker = alloc.mark();
+        let first = unsafe { alloc.allocate_raw(8, 8).unwrap().ptr() };
+        // fill up the rest of the arena.
+        for _ in 0..6 {
+            let _ = unsafe { alloc.allocate_raw(8, 8).unwrap() };
+        }
+        assert!(unsafe { alloc.allocate_raw(8, 8) }.is_err());
+
+        unsafe { alloc.rewind(marker) };
+        assert_eq!(alloc.mark(), marker);
+        assert_eq!(unsafe { alloc.allocate_raw(8, 8).unwrap().ptr() }, first);
+    }
+
+    #[test]
+    fn high_address() {
+        use std::cell::{Cell, RefCell};
+        use std::usize;
+
+        // a fabricated arena right at the top of the address space.
+        let top = (usize::MAX - 3) as *mut u8;
+        let alloc = Scoped {
+            align: 8,
+            allocator: HEAP,
+            current: Cell::new(top),
+            end: usize::MAX as *mut u8,
+            free_spans: RefCell::new(Vec::new()),
+            reuse_spans: false,
+            high_water: Cell::new(usize::MAX as *mut u8),
+            root: false,
+            start: top,
+        };
+
+        unsafe {
+            assert_eq!(alloc.allocate_raw(1, 8).err().unwrap(), Error::OutOfMemory);
+            assert_eq!(alloc.allocate_raw(8, 1).err().unwrap(), Error::OutOfMemory);
+        }
+        assert_eq!(alloc.bytes_used(), 0);
+    }
+
+    #[test]
+    fn realloc_to_end() {
+        let alloc = Scoped::new(64).unwrap();
+        unsafe {
+            let _first = alloc.allocate_raw(8, 8).unwrap();
+            let block = alloc.allocate_raw(8, 8).unwrap();
+            let ptr = block.ptr();
+
+            // grow the last block to fill the rest of the buffer exactly.
+            let block = alloc.reallocate_raw(block, 56).ok().unwrap();
+            assert_eq!(block.ptr(), ptr);
+            assert_eq!(alloc.bytes_remaining(), 0);
+
+            let (err, block) = alloc.reallocate_raw(block, 57).err().unwrap();
+            assert_eq!(err, Error::OutOfMemory);
+
+            // shrinking in place gives the space back.
+            let block = alloc.reallocate_raw(block, 8).ok().unwrap();
+            assert_eq!(block.ptr(), ptr);
+            assert_eq!(alloc.bytes_remaining(), 48);
+        }
+    }
+
+    #[test]
+    fn reset() {
+        let mut alloc = Scoped::new(64).unwrap();
+        for _ in 0..3 {
+            {
+                let vals: Vec<_> = (0..8u64).map(|i| alloc.allocate(i).unwrap()).collect();
+                assert!(alloc.allocate(0u8).is_err());
+                assert_eq!(*vals[7], 7);
+            }
+            alloc.reset();
+            assert_eq!(alloc.bytes_remaining(), 64);
+        }
+    }
+
+    #[test]
+    fn shrink_to() {
+        let mut alloc = Scoped::new(1024).unwrap();
+        {
+            let _vals: Vec<_> = (0..64u64).map(|i| alloc.allocate(i).unwrap()).collect();
+        }
+        alloc.reset();
+        unsafe { alloc.allocate_raw(8, 8).unwrap() };
+        assert!(alloc.shrink_to(4).is_err());
+
+        alloc.shrink_to(32).unwrap();
+        assert_eq!(alloc.bytes_used(), 8);
+        assert_eq!(alloc.bytes_remaining(), 24);
+        let _vals: Vec<_> = (0..3u64).map(|i| alloc.allocate(i).unwrap()).collect();
+        assert_eq!(alloc.allocate(0u8).err().unwrap().0, Error::OutOfMemory);
+    }
+
+    #[test]
+    fn from_buffer() {
+        let mut buf = [0u8; 256];
+        let start = buf.as_ptr() as usize;
+        {
+            let alloc = Scoped::from_buffer(&mut buf);
+            let vals: Vec<_> = (0..).map(|i| alloc.allocate(i as u64))
+                                    .take_while(|val| val.is_ok())
+                                    .map(|val| val.unwrap())
+                                    .collect();
+            // the buffer may not start out aligned for a u64.
+            assert!(vals.len() == 31 || vals.len() == 32);
+            for val in &vals {
+                let addr = &**val as *const u64 as usize;
+                assert!(addr >= start && addr + 8 <= start + 256);
+            }
+        }
+        assert!(buf.iter().any(|&byte| byte != 0));
+    }
+
+    #[test]
+    fn free_spans() {
+        let alloc = Scoped::new(64).unwrap().with_free_spans();
+        {
+            let vals: Vec<_> = (0..4u64).map(|i| alloc.allocate(i).unwrap()).collect();
+            let mut vals = vals.into_iter();
+            let (a, b, c, d) = (vals.next().unwrap(), vals.next().unwrap(),
+                                vals.next().unwrap(), vals.next().unwrap());
+
+            // freed out of order, the first blocks wait in free spans.
+            drop(a);
+            drop(b);
+            assert_eq!(alloc.free_span_bytes(), 16);
+            assert_eq!(alloc.bytes_used(), 32);
+
+            // and are reused by allocations that fit.
+            let e = alloc.allocate(5u32).unwrap();
+            assert_eq!(alloc.free_span_bytes(), 12);
+            assert_eq!(alloc.bytes_used(), 32);
+
+            // once the blocks after them go, the bump pointer moves all the way back.
+            drop(e);
+            drop(d);
+            assert_eq!(alloc.bytes_used(), 24);
+            drop(c);
+        }
+        assert_eq!(alloc.bytes_used(), 0);
+        assert_eq!(alloc.free_span_bytes(), 0);
+    }
+
+    #[test]
+    fn no_free_spans() {
+        let alloc = Scoped::new(64).unwrap();
+        let a = alloc.allocate(1u64).unwrap();
+        let b = alloc.allocate(2u64).unwrap();
+
+        // without free spans, a block freed out of order stays used.
+        drop(a);
+        assert_eq!(alloc.free_span_bytes(), 0);
+        drop(b);
+        assert_eq!(alloc.bytes_used(), 8);
+    }
+
+    #[test]
+    fn vec_reuses_spans() {
+        let alloc = Scoped::new(256).unwrap().with_free_spans();
+        let mut vec = AllocVec::new_in(&alloc);
+        for i in 0..16u32 {
+            vec.push(i);
+            // keep the vector from growing in place.
+            let _ = alloc.allocate(0u8).unwrap().leak();
+        }
+        // the buffers it outgrew are kept for reuse.
+        assert!(alloc.free_span_bytes() > 0);
+        assert_eq!(vec[15], 15);
+    }
+
+    #[test]
+    fn scope_ref() {
+        let alloc = Scoped::new(64).unwrap().with_free_spans();
+        let (a, b) = alloc.scope_ref(|inner| {
+            let _temp = inner.allocate(0u64).unwrap();
+            let a = inner.allocate(1u64).unwrap().leak();
+            let b = inner.allocate(2u64).unwrap().leak();
+            (a, b)
+        }).unwrap();
+
+        // the memory stays allocated after the closure ends,
+        // except for that of the temporary, which is reused.
+        let c = alloc.allocate(3u64).unwrap();
+        assert_eq!((*a, *b, *c), (1, 2, 3));
+        assert_eq!(alloc.bytes_used(), 24);
+
+        alloc.scope(|inner| {
+            assert_eq!(alloc.scope_ref(|_| ()), Err(ScopeError::AlreadyScoped));
+            assert!(inner.scope_ref(|_| ()).is_ok());
+        }).unwrap();
+    }
+
+    #[test]
+    fn bytes_used() {
+        let alloc = Scoped::new(64).unwrap();
+        assert_eq!(alloc.bytes_used(), 0);
+        assert_eq!(alloc.bytes_remaining(), 64);
+
+        let _a = alloc.allocate(0u8).unwrap();
+        assert_eq!(alloc.bytes_used(), 1);
+        // padded up to the alignment of u32.
+        let _b = alloc.allocate(0u32).unwrap();
+        assert_eq!(alloc.bytes_used(), 8);
+        let _c = alloc.allocate([0u16; 3]).unwrap();
+        assert_eq!(alloc.bytes_used(), 14);
+        assert_eq!(alloc.bytes_remaining(), 50);
+
+        alloc.scope(|inner| {
+                 assert_eq!(alloc.bytes_used(), 0);
+                 assert_eq!(alloc.bytes_remaining(), 0);
+                 assert_eq!(inner.bytes_used(), 0);
+                 assert_eq!(inner.bytes_remaining(), 50);
+             })
+             .unwrap();
+    }
+
+    #[test]
+    fn owning_boundary() {
+        let alloc = Scoped::new(64).unwrap();
+
+        // a block starting one past the end belongs to someone else.
+        assert!(!alloc.owns_block(&Block::new(alloc.end, 4, 4)));
+        // as does one which starts inside but runs off the end.
+        let inside = unsafe { alloc.end.offset(-4) };
+        assert!(!alloc.owns_block(&Block::new(inside, 8, 4)));
+        assert!(alloc.owns_block(&Block::new(inside, 4, 4)));
+    }
+
+    #[test]
+    fn fallback_owning() {
+        let main = Scoped::new(16).unwrap();
+        let fallback = Scoped::new(64).unwrap();
+        let alloc = Fallback::new(&main, &fallback);
+
+        let small = alloc.allocate(0u64).unwrap();
+        assert!(main.owns(&small));
+
+        let big = alloc.allocate([0u8; 32]).unwrap();
+        assert!(!main.owns(&big));
+        assert!(fallback.owns(&big));
+    }
+
     #[test]
     fn mutex_sharing() {
         use std::thread;
@@ -247,4 +1169,155 @@ mod tests {
             });
         }
     }
+
+    #[test]
+    fn drop_scoped() {
+        use std::cell::RefCell;
+
+        struct Bomb<'a>(u8, &'a RefCell<Vec<u8>>);
+        impl<'a> Drop for Bomb<'a> {
+            fn drop(&mut self) {
+                self.1.borrow_mut().push(self.0);
+            }
+        }
+
+        let dropped = RefCell::new(Vec::new());
+        let alloc = Scoped::new(64).unwrap();
+        {
+            let arena = DropScoped::new(&alloc);
+            assert!(arena.is_empty());
+            let first = arena.allocate(Bomb(1, &dropped)).ok().unwrap();
+            arena.allocate(Bomb(2, &dropped)).ok().unwrap();
+            arena.scope(|inner| {
+                inner.allocate(Bomb(3, &dropped)).ok().unwrap();
+                inner.allocate(Bomb(4, &dropped)).ok().unwrap();
+            }).unwrap();
+            // the inner scope's values went with it.
+            assert_eq!(*dropped.borrow(), [4, 3]);
+
+            first.0 = 5;
+            arena.allocate(()).ok().unwrap();
+            assert_eq!(arena.len(), 3);
+        }
+
+        assert_eq!(*dropped.borrow(), [4, 3, 2, 5]);
+    }
+
+    #[test]
+    fn remaining_block() {
+        let alloc = Scoped::new(64).unwrap();
+        unsafe { alloc.allocate_raw(10, 1).unwrap() };
+        let remaining = alloc.bytes_remaining();
+
+        let block = alloc.remaining_block();
+        assert_eq!(block.size(), remaining);
+        unsafe { ::std::ptr::write_bytes(block.ptr(), 7, block.size()) };
+        assert_eq!(alloc.bytes_remaining(), 0);
+        assert_eq!(alloc.allocate(0u8).err().map(|(err, _)| err), Some(Error::OutOfMemory));
+        // nothing left to claim.
+        assert!(alloc.remaining_block().is_empty());
+
+        // giving it back makes the space available again.
+        unsafe { alloc.deallocate_raw(block) };
+        assert_eq!(alloc.bytes_remaining(), remaining);
+        assert!(alloc.allocate(0u8).is_ok());
+    }
+
+    #[test]
+    fn aligned_buffer() {
+        #[repr(align(64))]
+        struct CacheLine([u8; 64]);
+
+        let alloc = Scoped::new_aligned(128, 64).unwrap();
+        let first = alloc.allocate(CacheLine([1; 64])).ok().unwrap();
+        assert_eq!(&*first as *const _ as usize % 64, 0);
+        // no padding was needed.
+        assert_eq!(alloc.bytes_used(), 64);
+        let second = alloc.allocate(CacheLine([2; 64])).ok().unwrap();
+        assert_eq!(second.0[63], 2);
+        assert_eq!(alloc.bytes_remaining(), 0);
+
+        assert_eq!(Scoped::new_aligned(64, 3).err(), Some(Error::UnsupportedAlignment));
+    }
+
+    #[test]
+    fn scope_panic() {
+        use std::panic::{self, AssertUnwindSafe};
+
+        let alloc = Scoped::new(64).unwrap();
+        let _a = alloc.allocate(0u64).unwrap();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            alloc.scope(|inner| {
+                let _b = inner.allocate(0u64).unwrap();
+                panic!("in scope");
+            }).unwrap();
+        }));
+        assert!(result.is_err());
+
+        // the outer allocator is usable again, without the inner scope's memory.
+        assert!(!alloc.is_scoped());
+        assert_eq!(alloc.bytes_used(), 8);
+        assert!(alloc.allocate(0u64).is_ok());
+    }
+
+    #[test]
+    fn zeroed() {
+        let mut alloc = Scoped::new_zeroed(64).unwrap();
+        unsafe {
+            let block = alloc.allocate_zeroed_raw(16, 8).unwrap();
+            ::std::ptr::write_bytes(block.ptr(), 0xFF, 16);
+            // memory never handed out is left alone, as it's known to be zero.
+            *alloc.start.offset(40) = 0xAA;
+        }
+        assert_eq!(alloc.high_water.get() as usize - alloc.start as usize, 16);
+
+        alloc.reset();
+        unsafe {
+            let block = alloc.allocate_zeroed_raw(48, 8).unwrap();
+            let bytes = ::std::slice::from_raw_parts(block.ptr(), 48);
+            assert!(bytes[..40].iter().all(|&byte| byte == 0));
+            assert_eq!(bytes[40], 0xAA);
+        }
+        assert_eq!(alloc.high_water.get() as usize - alloc.start as usize, 48);
+
+        // memory written within a scope is cleared after it, too.
+        alloc.reset();
+        alloc.scope(|inner| unsafe {
+            let block = inner.allocate_raw(64, 8).unwrap();
+            ::std::ptr::write_bytes(block.ptr(), 0xFF, 64);
+        }).unwrap();
+        unsafe {
+            let block = alloc.allocate_zeroed_raw(64, 8).unwrap();
+            assert!(::std::slice::from_raw_parts(block.ptr(), 64).iter().all(|&byte| byte == 0));
+        }
+    }
+
+    #[test]
+    fn zeroed_dirty_buffer() {
+        let mut buf = [0xFFu8; 32];
+        let alloc = Scoped::from_buffer(&mut buf);
+        let val = unsafe {
+            let block = alloc.allocate_zeroed_raw(8, 8).unwrap();
+            *(block.ptr() as *mut u64)
+        };
+        assert_eq!(val, 0);
+    }
+
+    #[test]
+    fn no_buffer() {
+        let quota = Quota::new(HEAP, 100);
+        match Scoped::new_from(&quota, 128) {
+            Err(Error::NoBuffer { size, align, cause }) => {
+                assert_eq!(size, 128);
+                assert_eq!(align, ::std::mem::align_of::<usize>());
+                assert_eq!(*cause, Error::OutOfMemory);
+            }
+            _ => panic!("the quota is too small for the buffer"),
+        }
+
+        let err = Scoped::new_from_aligned(&quota, 101, 64).err().unwrap();
+        assert_eq!(err.to_string(), "Could not allocate a buffer of 101 bytes aligned to 64: \
+                                     Allocator out of memory.");
+        assert!(Scoped::new_from(&quota, 100).is_ok());
+    }
 }
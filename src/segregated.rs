@@ -0,0 +1,204 @@
+//! A segregated free list allocator.
+
+use std::cmp;
+use std::mem;
+use std::ptr;
+
+use super::{Allocator, Error, Block, BlockOwner, FreeList, HeapAllocator, HEAP};
+
+// the size classes are the powers of two between these, inclusive.
+const MIN_CLASS: usize = 16;
+const MAX_CLASS: usize = 4096;
+
+/// A `SegregatedFreeList` keeps a `FreeList` for each power-of-two size class
+/// from 16 to 4096 bytes, and serves each request from the smallest class it fits in.
+/// Requests larger than the biggest class, or with greater alignment than a pointer,
+/// go straight to the backing allocator.
+///
+/// Since each request is rounded up to its size class, up to half of every block
+/// may go unused. In exchange, allocation and deallocation are as cheap as
+/// for a single `FreeList`.
+///
+/// A size class which has run out of blocks fails with `OutOfMemory` rather than
+/// turning to the backing allocator, so that deallocation can be routed purely by size.
+pub struct SegregatedFreeList<'a, A: 'a + Allocator> {
+    alloc: &'a A,
+    classes: Vec<FreeList<'a, A>>,
+}
+
+impl SegregatedFreeList<'static, HeapAllocator> {
+    /// Creates a new `SegregatedFreeList` backed by the heap,
+    /// with `blocks_per_class` blocks in every size class.
+    pub fn new(blocks_per_class: usize) -> Result<Self, Error> {
+        SegregatedFreeList::new_from(HEAP, blocks_per_class)
+    }
+}
+
+impl<'a, A: 'a + Allocator> SegregatedFreeList<'a, A> {
+    /// Creates a new `SegregatedFreeList` backed by another allocator,
+    /// with `blocks_per_class` blocks in every size class.
+    pub fn new_from(alloc: &'a A, blocks_per_class: usize) -> Result<Self, Error> {
+        let mut classes = Vec::new();
+        let mut size = MIN_CLASS;
+        while size <= MAX_CLASS {
+            match FreeList::new_from(alloc, size, blocks_per_class) {
+                Ok(list) => classes.push(list),
+                Err(err) => return Err(err),
+            }
+            size *= 2;
+        }
+
+        Ok(SegregatedFreeList {
+            alloc: alloc,
+            classes: classes,
+        })
+    }
+
+    // the size class a request is served from, or `None` if it
+    // goes to the backing allocator.
+    fn class_of(&self, size: usize, align: usize) -> Option<&FreeList<'a, A>> {
+        class_index(size, align).map(|i| &self.classes[i])
+    }
+}
+
+fn class_index(size: usize, align: usize) -> Option<usize> {
+    if size > MAX_CLASS || align > mem::align_of::<*mut u8>() {
+        return None;
+    }
+
+    let class_size = cmp::max(size.next_power_of_two(), MIN_CLASS);
+    Some((class_size.trailing_zeros() - MIN_CLASS.trailing_zeros()) as usize)
+}
+
+unsafe impl<'a, A: 'a + Allocator> Allocator for SegregatedFreeList<'a, A> {
+    unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        if size == 0 {
+            return Ok(Block::empty());
+        }
+
+        match self.class_of(size, align) {
+            Some(class) => class.allocate_raw(size, align),
+            None => self.alloc.allocate_raw(size, align),
+        }
+    }
+
+    unsafe fn reallocate_raw<'b>(&'b self, block: Block<'b>, new_size: usize) -> Result<Block<'b>, (Error, Block<'b>)> {
+        if new_size == 0 {
+            self.deallocate_raw(block);
+            return Ok(Block::empty());
+        } else if block.is_empty() {
+            return Err((Error::UnsupportedAlignment, block));
+        }
+
+        let old_class = class_index(block.size(), block.align());
+        let new_class = class_index(new_size, block.align());
+
+        match (old_class, new_class) {
+            // same size class: the block is already big enough.
+            (Some(old), Some(new)) if old == new => {
+                return Ok(Block::new(block.ptr(), new_size, block.align()));
+            }
+            (None, None) => return self.alloc.reallocate_raw(block, new_size),
+            _ => {}
+        }
+
+        // moving between classes, or between a class and the backing allocator.
+        match self.allocate_raw(new_size, block.align()) {
+            Ok(new_block) => {
+                ptr::copy_nonoverlapping(block.ptr(), new_block.ptr(), cmp::min(block.size(), new_size));
+                self.deallocate_raw(block);
+                Ok(new_block)
+            }
+            Err(err) => Err((err, block)),
+        }
+    }
+
+    unsafe fn deallocate_raw(&self, block: Block) {
+        if block.is_empty() {
+            return;
+        }
+
+        match self.class_of(block.size(), block.align()) {
+            Some(class) => class.deallocate_raw(block),
+            None => self.alloc.deallocate_raw(block),
+        }
+    }
+
+    fn usable_size(&self, size: usize, align: usize) -> usize {
+        match class_index(size, align) {
+            Some(index) => MIN_CLASS << index,
+            None => self.alloc.usable_size(size, align),
+        }
+    }
+}
+
+impl<'a, A: 'a + BlockOwner> BlockOwner for SegregatedFreeList<'a, A> {
+    fn owns_block(&self, block: &Block) -> bool {
+        match self.class_of(block.size(), block.align()) {
+            Some(class) => class.owns_block(block),
+            None => self.alloc.owns_block(block),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    #[test]
+    fn round_trip() {
+        let alloc = SegregatedFreeList::new(2).unwrap();
+        for _ in 0..4 {
+            let a = alloc.allocate(1u8).unwrap();
+            let b = alloc.allocate([2u8; 24]).unwrap();
+            let c = alloc.allocate([3u64; 64]).unwrap();
+            let d = alloc.allocate([4u8; 4096]).unwrap();
+            // bigger than any size class.
+            let e = alloc.allocate([5u8; 8192]).unwrap();
+
+            assert_eq!(*a, 1);
+            assert_eq!(b[23], 2);
+            assert_eq!(c[63], 3);
+            assert_eq!(d[4095], 4);
+            assert_eq!(e[8191], 5);
+        }
+    }
+
+    #[test]
+    fn usable_size() {
+        let alloc = SegregatedFreeList::new(1).unwrap();
+        assert_eq!(alloc.usable_size(1, 1), 16);
+        assert_eq!(alloc.usable_size(100, 8), 128);
+        // oversize requests go to the heap, which doesn't round.
+        assert_eq!(alloc.usable_size(5000, 8), 5000);
+    }
+
+    #[test]
+    fn class_exhaustion() {
+        let alloc = SegregatedFreeList::new(1).unwrap();
+        let _small = alloc.allocate(0u64).unwrap();
+        assert!(alloc.allocate(0u64).is_err());
+        // other classes are unaffected.
+        assert!(alloc.allocate([0u8; 100]).is_ok());
+    }
+
+    #[test]
+    fn realloc_between_classes() {
+        let alloc = SegregatedFreeList::new(1).unwrap();
+        unsafe {
+            let block = alloc.allocate_raw(20, 8).unwrap();
+            *block.ptr() = 42;
+            let ptr = block.ptr();
+
+            // still in the 32 byte class.
+            let block = alloc.reallocate_raw(block, 32).ok().unwrap();
+            assert_eq!(block.ptr(), ptr);
+
+            let block = alloc.reallocate_raw(block, 100).ok().unwrap();
+            assert!(block.ptr() != ptr);
+            assert_eq!(*block.ptr(), 42);
+
+            alloc.deallocate_raw(block);
+        }
+    }
+}
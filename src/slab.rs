@@ -0,0 +1,251 @@
+//! A slab allocator for values of a single type.
+
+use std::cell::RefCell;
+use std::cmp;
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr;
+
+use super::{Allocator, Error, Block, BlockOwner, HeapAllocator, HEAP};
+
+// a slab and the free list of its slots.
+struct Slab {
+    ptr: *mut u8,
+    free: *mut u8,
+    used: usize,
+}
+
+/// A `SlabAllocator` serves values of type `T` from slabs of memory
+/// it allocates from a parent allocator.
+///
+/// Each slab is divided into slots the size of a `T`, with the free slots linked
+/// into a list of their own, like a `FreeList`. Slabs are added whenever all the existing
+/// ones are full, and given back to the parent as soon as they are empty again.
+pub struct SlabAllocator<'a, T, A: 'a + Allocator> {
+    alloc: &'a A,
+    slots_per_slab: usize,
+    slabs: RefCell<Vec<Slab>>,
+    _marker: PhantomData<T>,
+}
+
+impl<T> SlabAllocator<'static, T, HeapAllocator> {
+    /// Creates a new `SlabAllocator` backed by the heap, with
+    /// `slots_per_slab` values to each slab.
+    pub fn new(slots_per_slab: usize) -> Result<Self, Error> {
+        SlabAllocator::new_from(HEAP, slots_per_slab)
+    }
+}
+
+impl<'a, T, A: 'a + Allocator> SlabAllocator<'a, T, A> {
+    /// Creates a new `SlabAllocator` backed by another allocator, with
+    /// `slots_per_slab` values to each slab. No slabs are allocated until they are needed.
+    pub fn new_from(alloc: &'a A, slots_per_slab: usize) -> Result<Self, Error> {
+        if slots_per_slab == 0 {
+            return Err(Error::AllocatorSpecific("A slab must have at least one slot.".into()));
+        }
+
+        if Self::slot_size().checked_mul(slots_per_slab).is_none() {
+            return Err(Error::OutOfMemory);
+        }
+
+        Ok(SlabAllocator {
+            alloc: alloc,
+            slots_per_slab: slots_per_slab,
+            slabs: RefCell::new(Vec::new()),
+            _marker: PhantomData,
+        })
+    }
+
+    /// The number of slabs currently allocated.
+    pub fn slabs(&self) -> usize {
+        self.slabs.borrow().len()
+    }
+
+    // every slot holds either a `T` or a link to the next free slot.
+    fn slot_align() -> usize {
+        cmp::max(mem::align_of::<T>(), mem::align_of::<*mut u8>())
+    }
+
+    fn slot_size() -> usize {
+        let size = cmp::max(mem::size_of::<T>(), mem::size_of::<*mut u8>());
+        let align = Self::slot_align();
+        (size + align - 1) & !(align - 1)
+    }
+
+    fn slab_size(&self) -> usize {
+        Self::slot_size() * self.slots_per_slab
+    }
+
+    fn contains(&self, slab: &Slab, ptr: *mut u8) -> bool {
+        ptr >= slab.ptr && (ptr as usize) < slab.ptr as usize + self.slab_size()
+    }
+
+    fn new_slab(&self) -> Result<Slab, Error> {
+        let block = match unsafe { self.alloc.allocate_raw(self.slab_size(), Self::slot_align()) } {
+            Ok(block) => block,
+            Err(err) => return Err(err),
+        };
+
+        // link every slot to the one after it.
+        let slot_size = Self::slot_size();
+        let mut free = ptr::null_mut();
+        for i in (0..self.slots_per_slab).rev() {
+            unsafe {
+                let slot = block.ptr().offset((i * slot_size) as isize);
+                *(slot as *mut *mut u8) = free;
+                free = slot;
+            }
+        }
+
+        Ok(Slab {
+            ptr: block.ptr(),
+            free: free,
+            used: 0,
+        })
+    }
+}
+
+unsafe impl<'a, T, A: 'a + Allocator> Allocator for SlabAllocator<'a, T, A> {
+    unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        if size == 0 {
+            return Ok(Block::empty());
+        } else if size > Self::slot_size() {
+            return Err(Error::OutOfMemory);
+        }
+
+        if align > Self::slot_align() {
+            return Err(Error::UnsupportedAlignment);
+        }
+
+        let mut slabs = self.slabs.borrow_mut();
+        let index = match slabs.iter().position(|slab| !slab.free.is_null()) {
+            Some(index) => index,
+            None => {
+                match self.new_slab() {
+                    Ok(slab) => slabs.push(slab),
+                    Err(err) => return Err(err),
+                }
+                slabs.len() - 1
+            }
+        };
+
+        let slab = &mut slabs[index];
+        let slot = slab.free;
+        slab.free = *(slot as *mut *mut u8);
+        slab.used += 1;
+        Ok(Block::new(slot, size, align))
+    }
+
+    unsafe fn reallocate_raw<'b>(&'b self, block: Block<'b>, new_size: usize) -> Result<Block<'b>, (Error, Block<'b>)> {
+        if new_size == 0 {
+            self.deallocate_raw(block);
+            Ok(Block::empty())
+        } else if block.is_empty() {
+            Err((Error::UnsupportedAlignment, block))
+        } else if new_size <= Self::slot_size() {
+            Ok(Block::new(block.ptr(), new_size, block.align()))
+        } else {
+            Err((Error::OutOfMemory, block))
+        }
+    }
+
+    unsafe fn deallocate_raw(&self, block: Block) {
+        if block.is_empty() {
+            return;
+        }
+
+        let mut slabs = self.slabs.borrow_mut();
+        let index = match slabs.iter().position(|slab| self.contains(slab, block.ptr())) {
+            Some(index) => index,
+            None => {
+                debug_assert!(false, "Attempted to deallocate a block not owned by this SlabAllocator.");
+                return;
+            }
+        };
+
+        let empty = {
+            let slab = &mut slabs[index];
+            *(block.ptr() as *mut *mut u8) = slab.free;
+            slab.free = block.ptr();
+            slab.used -= 1;
+            slab.used == 0
+        };
+
+        if empty {
+            let slab = slabs.swap_remove(index);
+            self.alloc.deallocate_raw(Block::new(slab.ptr, self.slab_size(), Self::slot_align()));
+        }
+    }
+
+    fn usable_size(&self, size: usize, align: usize) -> usize {
+        if size <= Self::slot_size() && align <= Self::slot_align() {
+            Self::slot_size()
+        } else {
+            size
+        }
+    }
+}
+
+impl<'a, T, A: 'a + Allocator> BlockOwner for SlabAllocator<'a, T, A> {
+    fn owns_block(&self, block: &Block) -> bool {
+        self.slabs.borrow().iter().any(|slab| self.contains(slab, block.ptr()))
+    }
+}
+
+impl<'a, T, A: 'a + Allocator> Drop for SlabAllocator<'a, T, A> {
+    fn drop(&mut self) {
+        let slab_size = self.slab_size();
+        for slab in self.slabs.get_mut().drain(..) {
+            unsafe {
+                self.alloc.deallocate_raw(Block::new(slab.ptr, slab_size, Self::slot_align()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    #[test]
+    fn slabs() {
+        let alloc: SlabAllocator<u64, _> = SlabAllocator::new(4).unwrap();
+        assert_eq!(alloc.slabs(), 0);
+
+        let first: Vec<_> = (0..4).map(|i| alloc.allocate(i as u64).unwrap()).collect();
+        assert_eq!(alloc.slabs(), 1);
+        let second: Vec<_> = (0..2).map(|i| alloc.allocate(i as u64).unwrap()).collect();
+        assert_eq!(alloc.slabs(), 2);
+        assert!(alloc.owns(&first[0]) && alloc.owns(&second[0]));
+
+        // emptying the first slab gives it back.
+        drop(first);
+        assert_eq!(alloc.slabs(), 1);
+        assert_eq!(*second[1], 1);
+
+        // the partially full slab is reused before a new one is made.
+        let _third = alloc.allocate(0u64).unwrap();
+        assert_eq!(alloc.slabs(), 1);
+    }
+
+    #[test]
+    fn returned_to_parent() {
+        let parent = Proxy::new(HEAP, StatsLogger::new());
+        {
+            let alloc: SlabAllocator<[u8; 24], _> = SlabAllocator::new_from(&parent, 8).unwrap();
+            let vals: Vec<_> = (0..9).map(|_| alloc.allocate([0u8; 24]).unwrap()).collect();
+            assert_eq!(parent.logger().snapshot().allocations, 2);
+            drop(vals);
+            assert_eq!(parent.logger().snapshot().deallocations, 2);
+        }
+    }
+
+    #[test]
+    fn too_large() {
+        let alloc: SlabAllocator<u32, _> = SlabAllocator::new(4).unwrap();
+        assert_eq!(alloc.allocate([0u32; 4]).err().unwrap().0, Error::OutOfMemory);
+        // slots have room for a pointer at least.
+        assert_eq!(alloc.usable_size(4, 4), ::std::mem::size_of::<usize>());
+        assert!(SlabAllocator::<u32, _>::new(0).is_err());
+    }
+}
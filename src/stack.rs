@@ -0,0 +1,248 @@
+//! A stack allocator with explicitly pushed and popped frames.
+
+use std::cell::Cell;
+use std::cmp;
+use std::mem;
+use std::ptr;
+
+use super::{align_forward, Allocator, Block, BlockOwner, Error, HeapAllocator, HEAP};
+
+/// A stack allocator.
+///
+/// Like `Scoped`, this is a linear allocator over a fixed buffer. Instead of closures,
+/// its lifetimes are managed with frames: `push_frame` starts one, and `pop_frame`
+/// frees everything allocated since. Frames must be popped in the reverse order they
+/// were pushed, which is checked when debug assertions are enabled.
+pub struct StackAllocator<'a, A: 'a + Allocator> {
+    alloc: &'a A,
+    start: *mut u8,
+    end: *mut u8,
+    current: Cell<*mut u8>,
+    // the position at which the innermost frame starts.
+    base: Cell<*mut u8>,
+    depth: Cell<usize>,
+}
+
+/// A frame of a `StackAllocator`, to be passed to `pop_frame`.
+#[must_use]
+#[derive(Debug)]
+pub struct Frame {
+    // the start of the buffer of the allocator which pushed this frame.
+    stack: *mut u8,
+    position: *mut u8,
+    prev_base: *mut u8,
+    depth: usize,
+}
+
+impl StackAllocator<'static, HeapAllocator> {
+    /// Creates a new `StackAllocator` backed by `size` bytes from the heap.
+    pub fn new(size: usize) -> Result<Self, Error> {
+        StackAllocator::new_from(HEAP, size)
+    }
+}
+
+impl<'a, A: 'a + Allocator> StackAllocator<'a, A> {
+    /// Creates a new `StackAllocator` backed by `size` bytes from the allocator supplied.
+    pub fn new_from(alloc: &'a A, size: usize) -> Result<Self, Error> {
+        match unsafe { alloc.allocate_raw(size, mem::align_of::<usize>()) } {
+            Ok(block) => Ok(StackAllocator {
+                alloc: alloc,
+                start: block.ptr(),
+                end: unsafe { block.ptr().offset(block.size() as isize) },
+                current: Cell::new(block.ptr()),
+                base: Cell::new(block.ptr()),
+                depth: Cell::new(0),
+            }),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Starts a new frame.
+    pub fn push_frame(&self) -> Frame {
+        let depth = self.depth.get() + 1;
+        let frame = Frame {
+            stack: self.start,
+            position: self.current.get(),
+            prev_base: self.base.get(),
+            depth: depth,
+        };
+
+        self.base.set(self.current.get());
+        self.depth.set(depth);
+        frame
+    }
+
+    /// Ends a frame, freeing everything allocated since it was pushed.
+    ///
+    /// # Safety
+    /// This invalidates every value allocated within the frame.
+    /// It is the caller's responsibility to not use them afterwards.
+    ///
+    /// The frame must have been pushed by this allocator's `push_frame`, and
+    /// not popped already. Without debug assertions, only the former is checked;
+    /// popping any other frame moves the allocator to a position it doesn't own,
+    /// and later allocations are undefined behaviour.
+    ///
+    /// # Panics
+    /// Panics if the frame was pushed by another allocator, or if debug assertions
+    /// are enabled and the frame is not the innermost one.
+    pub unsafe fn pop_frame(&self, frame: Frame) {
+        assert!(frame.stack == self.start, "Popped a frame pushed by another allocator.");
+        debug_assert!(frame.depth == self.depth.get(),
+                      "Popped frame {} while frame {} is the innermost one.",
+                      frame.depth,
+                      self.depth.get());
+
+        self.current.set(frame.position);
+        self.base.set(frame.prev_base);
+        self.depth.set(frame.depth - 1);
+    }
+
+    /// The number of frames currently pushed.
+    pub fn depth(&self) -> usize {
+        self.depth.get()
+    }
+
+    /// The number of bytes allocated so far, over all frames.
+    pub fn bytes_used(&self) -> usize {
+        self.current.get() as usize - self.start as usize
+    }
+}
+
+unsafe impl<'a, A: 'a + Allocator> Allocator for StackAllocator<'a, A> {
+    unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        if size == 0 {
+            return Ok(Block::empty());
+        }
+
+        let aligned = match align_forward(self.current.get(), align) {
+            Some(aligned) => aligned,
+            None => return Err(Error::OutOfMemory),
+        };
+
+        match (aligned as usize).checked_add(size) {
+            Some(end) if end <= self.end as usize => {
+                self.current.set(end as *mut u8);
+                Ok(Block::new(aligned, size, align))
+            }
+            _ => Err(Error::OutOfMemory),
+        }
+    }
+
+    unsafe fn reallocate_raw<'b>(&'b self, block: Block<'b>, new_size: usize) -> Result<Block<'b>, (Error, Block<'b>)> {
+        if new_size == 0 {
+            self.deallocate_raw(block);
+            Ok(Block::empty())
+        } else if block.is_empty() {
+            Err((Error::UnsupportedAlignment, block))
+        } else if block.ptr().offset(block.size() as isize) == self.current.get() &&
+                  block.ptr() >= self.base.get() {
+            // the last block of the innermost frame can be resized in place.
+            let fits = (block.ptr() as usize).checked_add(new_size)
+                                             .map_or(false, |end| end <= self.end as usize);
+            if fits {
+                self.current.set(block.ptr().offset(new_size as isize));
+                Ok(Block::new(block.ptr(), new_size, block.align()))
+            } else {
+                Err((Error::OutOfMemory, block))
+            }
+        } else {
+            match self.allocate_raw(new_size, block.align()) {
+                Ok(new_block) => {
+                    ptr::copy_nonoverlapping(block.ptr(), new_block.ptr(), cmp::min(block.size(), new_size));
+                    Ok(new_block)
+                }
+                Err(err) => Err((err, block)),
+            }
+        }
+    }
+
+    unsafe fn deallocate_raw(&self, block: Block) {
+        if block.is_empty() {
+            return;
+        }
+
+        // the last block of the innermost frame can be given back right away.
+        // anything else is freed along with its frame.
+        if block.ptr().offset(block.size() as isize) == self.current.get() &&
+           block.ptr() >= self.base.get() {
+            self.current.set(block.ptr());
+        }
+    }
+}
+
+impl<'a, A: 'a + Allocator> BlockOwner for StackAllocator<'a, A> {
+    fn owns_block(&self, block: &Block) -> bool {
+        let ptr = block.ptr();
+
+        ptr >= self.start && (ptr as usize + block.size()) <= self.end as usize
+    }
+}
+
+impl<'a, A: 'a + Allocator> Drop for StackAllocator<'a, A> {
+    fn drop(&mut self) {
+        let size = self.end as usize - self.start as usize;
+        if size > 0 {
+            unsafe {
+                self.alloc.deallocate_raw(Block::new(self.start, size, mem::align_of::<usize>()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    #[test]
+    fn frames() {
+        let alloc = StackAllocator::new(64).unwrap();
+        unsafe {
+            alloc.allocate_raw(8, 8).unwrap();
+            let outer = alloc.push_frame();
+            alloc.allocate_raw(16, 8).unwrap();
+            let inner = alloc.push_frame();
+            alloc.allocate_raw(32, 8).unwrap();
+            assert_eq!(alloc.depth(), 2);
+            assert!(alloc.allocate_raw(16, 8).is_err());
+
+            alloc.pop_frame(inner);
+            assert_eq!(alloc.bytes_used(), 24);
+            alloc.pop_frame(outer);
+            assert_eq!(alloc.bytes_used(), 8);
+            assert_eq!(alloc.depth(), 0);
+        }
+    }
+
+    #[test]
+    fn frame_base() {
+        let alloc = StackAllocator::new(64).unwrap();
+        unsafe {
+            let block = alloc.allocate_raw(8, 8).unwrap();
+            let frame = alloc.push_frame();
+            // a block from an outer frame can't be freed into the current one.
+            alloc.deallocate_raw(block);
+            assert_eq!(alloc.bytes_used(), 8);
+            alloc.pop_frame(frame);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    #[cfg(debug_assertions)]
+    fn mismatched_pop() {
+        let alloc = StackAllocator::new(64).unwrap();
+        let outer = alloc.push_frame();
+        let _inner = alloc.push_frame();
+        unsafe { alloc.pop_frame(outer) };
+    }
+
+    #[test]
+    #[should_panic]
+    fn foreign_pop() {
+        let alloc = StackAllocator::new(64).unwrap();
+        let other = StackAllocator::new(64).unwrap();
+        let frame = other.push_frame();
+        unsafe { alloc.pop_frame(frame) };
+    }
+}
@@ -0,0 +1,204 @@
+//! An allocator wrapper which checks how its blocks are used, for testing.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use super::{Allocator, Error, Block, BlockOwner};
+
+/// A `TrackingAllocator` wraps another allocator and keeps a record of every
+/// block it has handed out and not yet taken back.
+///
+/// Unlike a `Proxy` with a `StatsLogger`, it doesn't just count: it panics as soon as
+/// a block is freed twice, freed with a different size or alignment than it was
+/// allocated with, or was never allocated by it at all. Leaks can be checked for
+/// with `assert_no_leaks`. This makes it handy for testing other allocators
+/// which get their memory from it.
+pub struct TrackingAllocator<A> {
+    alloc: A,
+    // the size and alignment of each live block, by address.
+    live: Mutex<HashMap<usize, (usize, usize)>>,
+}
+
+impl<A: Allocator> TrackingAllocator<A> {
+    /// Creates a new `TrackingAllocator` wrapping the allocator supplied.
+    pub fn new(alloc: A) -> Self {
+        TrackingAllocator {
+            alloc: alloc,
+            live: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The number of blocks currently allocated.
+    pub fn live_blocks(&self) -> usize {
+        self.live.lock().unwrap().len()
+    }
+
+    /// The number of bytes currently allocated.
+    pub fn live_bytes(&self) -> usize {
+        self.live.lock().unwrap().values().map(|&(size, _)| size).sum()
+    }
+
+    /// Panics if any block allocated from this hasn't been freed.
+    pub fn assert_no_leaks(&self) {
+        let (blocks, bytes) = (self.live_blocks(), self.live_bytes());
+        if blocks != 0 {
+            panic!("{} blocks ({} bytes) were never deallocated.", blocks, bytes);
+        }
+    }
+
+    fn tracked<'a>(&self, result: Result<Block<'a>, Error>) -> Result<Block<'a>, Error> {
+        if let Ok(ref block) = result {
+            self.track(block);
+        }
+        result
+    }
+
+    fn track(&self, block: &Block) {
+        if !block.is_empty() {
+            self.live.lock().unwrap().insert(block.ptr() as usize, (block.size(), block.align()));
+        }
+    }
+
+    // checks a block being given back against the record of it, and forgets it.
+    // the lock is released before any panic, so it isn't poisoned for later calls.
+    fn untrack(&self, block: &Block) {
+        if block.is_empty() {
+            return;
+        }
+
+        let addr = block.ptr() as usize;
+        let record = self.live.lock().unwrap().remove(&addr);
+        match record {
+            Some((size, align)) => {
+                if (size, align) != (block.size(), block.align()) {
+                    panic!("Block at {:#x} was allocated with size {} and align {}, \
+                            but freed with size {} and align {}.",
+                           addr, size, align, block.size(), block.align());
+                }
+            }
+            None => panic!("Block at {:#x} is not allocated: it was freed twice, \
+                            or came from another allocator.", addr),
+        }
+    }
+}
+
+unsafe impl<A: Allocator> Allocator for TrackingAllocator<A> {
+    unsafe fn allocate_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        self.tracked(self.alloc.allocate_raw(size, align))
+    }
+
+    unsafe fn allocate_zeroed_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        self.tracked(self.alloc.allocate_zeroed_raw(size, align))
+    }
+
+    unsafe fn allocate_excess_raw(&self, size: usize, align: usize) -> Result<Block, Error> {
+        self.tracked(self.alloc.allocate_excess_raw(size, align))
+    }
+
+    unsafe fn reallocate_raw<'a>(&'a self, block: Block<'a>, new_size: usize) -> Result<Block<'a>, (Error, Block<'a>)> {
+        // the block stays on record if reallocation fails.
+        self.untrack(&block);
+        match self.alloc.reallocate_raw(block, new_size) {
+            Ok(new_block) => {
+                self.track(&new_block);
+                Ok(new_block)
+            }
+            Err((err, old)) => {
+                self.track(&old);
+                Err((err, old))
+            }
+        }
+    }
+
+    unsafe fn deallocate_raw(&self, block: Block) {
+        self.untrack(&block);
+        self.alloc.deallocate_raw(block);
+    }
+
+    fn usable_size(&self, size: usize, align: usize) -> usize {
+        self.alloc.usable_size(size, align)
+    }
+}
+
+impl<A: BlockOwner> BlockOwner for TrackingAllocator<A> {
+    fn owns_block(&self, block: &Block) -> bool {
+        self.alloc.owns_block(block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    #[test]
+    #[should_panic(expected = "never deallocated")]
+    fn detects_leak() {
+        let alloc = TrackingAllocator::new(HEAP);
+        let val = alloc.allocate(0u64).unwrap();
+        ::std::mem::forget(val);
+        alloc.assert_no_leaks();
+    }
+
+    #[test]
+    #[should_panic(expected = "freed twice")]
+    fn detects_double_free() {
+        let alloc = TrackingAllocator::new(HEAP);
+        unsafe {
+            let block = alloc.allocate_raw(16, 8).unwrap();
+            let copy = Block::new(block.ptr(), block.size(), block.align());
+            alloc.deallocate_raw(block);
+            alloc.deallocate_raw(copy);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "but freed with size 8")]
+    fn detects_mismatched_layout() {
+        let alloc = TrackingAllocator::new(HEAP);
+        unsafe {
+            let block = alloc.allocate_raw(16, 8).unwrap();
+            alloc.deallocate_raw(Block::new(block.ptr(), 8, 8));
+        }
+    }
+
+    #[test]
+    fn usable_after_panic() {
+        use std::panic::{self, AssertUnwindSafe};
+
+        let alloc = TrackingAllocator::new(HEAP);
+        let mut buf = [0u64; 2];
+        let result = panic::catch_unwind(AssertUnwindSafe(|| unsafe {
+            alloc.deallocate_raw(Block::new(buf.as_mut_ptr() as *mut u8, 16, 8));
+        }));
+        assert!(result.is_err());
+
+        // the record isn't poisoned.
+        let val = alloc.allocate(1u64).unwrap();
+        assert_eq!(alloc.live_blocks(), 1);
+        drop(val);
+        alloc.assert_no_leaks();
+    }
+
+    #[test]
+    fn allocators_free_everything() {
+        let tracking = TrackingAllocator::new(HEAP);
+        {
+            let scoped = Scoped::new_from(&tracking, 64).unwrap();
+            let list = FreeList::new_from(&tracking, 16, 4).unwrap();
+            let alloc = Fallback::new(&scoped, &list);
+            let vals: Vec<_> = (0..10u64).map(|i| alloc.allocate(i).unwrap()).collect();
+            // one buffer for the scoped allocator, and a block for each free list slot.
+            assert_eq!(tracking.live_blocks(), 5);
+            drop(vals);
+        }
+        tracking.assert_no_leaks();
+
+        let mut vec = AllocVec::new_in(&tracking);
+        for i in 0..100 {
+            vec.push(i);
+        }
+        assert!(tracking.live_bytes() >= 100 * 4);
+        drop(vec);
+        tracking.assert_no_leaks();
+    }
+}
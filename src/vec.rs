@@ -0,0 +1,210 @@
+//! A growable vector backed by a custom allocator.
+
+use std::cmp;
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+use std::slice;
+use std::usize;
+
+use super::{Allocator, Block, Error};
+
+/// A contiguous growable array, with its memory provided by a custom allocator.
+///
+/// The buffer grows through the allocator's `reallocate_raw`.
+/// With a `Scoped` allocator, this is only done in place while the vector's
+/// buffer is the most recent allocation; otherwise, every growth moves the
+/// buffer, and the old one can only be reused if the allocator was made
+/// with `Scoped::with_free_spans`. Without that, it is lost until the scope is cleared.
+pub struct AllocVec<'a, T, A: 'a + ?Sized + Allocator> {
+    ptr: *mut T,
+    cap: usize,
+    len: usize,
+    allocator: &'a A,
+    _marker: PhantomData<T>,
+}
+
+impl<'a, T, A: ?Sized + Allocator> AllocVec<'a, T, A> {
+    /// Creates a new, empty `AllocVec` which will allocate from the allocator supplied.
+    /// This does not allocate until elements are pushed.
+    pub fn new_in(allocator: &'a A) -> Self {
+        AllocVec {
+            ptr: mem::align_of::<T>() as *mut T,
+            cap: if mem::size_of::<T>() == 0 { usize::MAX } else { 0 },
+            len: 0,
+            allocator: allocator,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Creates a new, empty `AllocVec` with room for `capacity` elements.
+    pub fn with_capacity_in(capacity: usize, allocator: &'a A) -> Result<Self, Error> {
+        let mut vec = AllocVec::new_in(allocator);
+        match vec.grow_to(capacity) {
+            Ok(()) => Ok(vec),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// The number of elements in this vector.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether this vector has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of elements this vector can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.cap
+    }
+
+    /// Appends an element to the back of this vector, growing it if necessary.
+    /// Gives back the element if the allocator failed to make room for it.
+    pub fn push(&mut self, val: T) -> Result<(), (Error, T)> {
+        if self.len == self.cap {
+            let new_cap = cmp::max(self.cap.saturating_mul(2), 4);
+            if let Err(err) = self.grow_to(new_cap) {
+                return Err((err, val));
+            }
+        }
+
+        unsafe { ptr::write(self.ptr.offset(self.len as isize), val) };
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Removes the last element of this vector and returns it,
+    /// or `None` if it is empty.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            None
+        } else {
+            self.len -= 1;
+            Some(unsafe { ptr::read(self.ptr.offset(self.len as isize)) })
+        }
+    }
+
+    // the block of memory currently holding the elements.
+    fn block(&self) -> Block<'a> {
+        if self.cap == 0 || mem::size_of::<T>() == 0 {
+            Block::empty()
+        } else {
+            Block::new(self.ptr as *mut u8, self.cap * mem::size_of::<T>(), mem::align_of::<T>())
+        }
+    }
+
+    fn grow_to(&mut self, new_cap: usize) -> Result<(), Error> {
+        if new_cap <= self.cap {
+            return Ok(());
+        }
+
+        let size = match new_cap.checked_mul(mem::size_of::<T>()) {
+            Some(size) => size,
+            None => return Err(Error::OutOfMemory),
+        };
+
+        let result = unsafe {
+            if self.cap == 0 {
+                self.allocator.allocate_raw(size, mem::align_of::<T>())
+            } else {
+                self.allocator.reallocate_raw(self.block(), size).map_err(|(err, _)| err)
+            }
+        };
+
+        match result {
+            Ok(block) => {
+                self.ptr = block.ptr() as *mut T;
+                self.cap = new_cap;
+                Ok(())
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl<'a, T, A: ?Sized + Allocator> Deref for AllocVec<'a, T, A> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl<'a, T, A: ?Sized + Allocator> DerefMut for AllocVec<'a, T, A> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+impl<'a, T, A: ?Sized + Allocator> Drop for AllocVec<'a, T, A> {
+    fn drop(&mut self) {
+        unsafe {
+            ptr::drop_in_place(&mut **self as *mut [T]);
+            self.allocator.deallocate_raw(self.block());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    #[test]
+    fn push_past_capacity() {
+        let alloc = Scoped::new(1024).unwrap();
+        let mut vec = AllocVec::with_capacity_in(2, &alloc).unwrap();
+        assert!(vec.is_empty());
+        for i in 0..100u32 {
+            vec.push(i).unwrap();
+        }
+        assert!(!vec.is_empty());
+        assert!(vec.capacity() >= 100);
+        assert_eq!(vec.len(), 100);
+        for (i, val) in vec.iter().enumerate() {
+            assert_eq!(*val, i as u32);
+        }
+
+        assert_eq!(vec.pop(), Some(99));
+        assert_eq!(vec.len(), 99);
+    }
+
+    #[test]
+    fn moved_growth() {
+        // the vector's buffer isn't the last allocation, so growing has to copy.
+        let alloc = Scoped::new(1024).unwrap();
+        let mut vec = AllocVec::with_capacity_in(4, &alloc).unwrap();
+        vec.push("a".to_string()).unwrap();
+        let _blocker = alloc.allocate(0u8).unwrap();
+        for _ in 0..8 {
+            vec.push("b".to_string()).unwrap();
+        }
+        assert_eq!(vec[0], "a");
+        assert_eq!(vec[8], "b");
+    }
+
+    #[test]
+    fn out_of_memory() {
+        let alloc = Scoped::new(16).unwrap();
+        let mut vec = AllocVec::new_in(&alloc);
+        for i in 0..4u32 {
+            vec.push(i).unwrap();
+        }
+        let (err, val) = vec.push(4).err().unwrap();
+        assert_eq!(err, Error::OutOfMemory);
+        assert_eq!(val, 4);
+        assert_eq!(&*vec, &[0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn zero_sized() {
+        let mut vec = AllocVec::new_in(HEAP);
+        for _ in 0..1000 {
+            vec.push(()).unwrap();
+        }
+        assert_eq!(vec.len(), 1000);
+    }
+}